@@ -52,6 +52,8 @@ use std::os::unix::fs::MetadataExt;
 /// Additionally, AtomicCreateFile::prune_stale_file(p) returns true if the
 /// given path `p` is an empty file with no lock held. In that case, the file is
 /// deleted with the lock held so the name can be reused.
+/// AtomicCreateFile::prune_stale_temp_file(p) does the same for temporary
+/// files of any size.
 pub struct AtomicCreateFile<'l> {
     pub path: &'l Path,
     pub temp: (PathBuf, File),
@@ -136,7 +138,19 @@ impl<'l> AtomicCreateFile<'l> {
     /// This can occur if a crash happens: in that case, there is a 0-byte file
     /// with no lock on it which can be safely deleted.
     pub fn prune_stale_file<P: AsRef<Path>>(p: P) -> bool {
-        let p = p.as_ref();
+        Self::prune_unlocked_file(p.as_ref(), true, true)
+    }
+
+    /// prune_stale_temp_file returns true if the given temporary file is
+    /// stale, deleting it unless `dry_run` is set. Temporary files are only
+    /// written to while a lock is held on them, so unlike
+    /// [`Self::prune_stale_file`], a temporary file is stale regardless of its
+    /// size, as long as no lock is held on it.
+    pub fn prune_stale_temp_file<P: AsRef<Path>>(p: P, dry_run: bool) -> bool {
+        Self::prune_unlocked_file(p.as_ref(), false, !dry_run)
+    }
+
+    fn prune_unlocked_file(p: &Path, only_empty: bool, delete: bool) -> bool {
         // Attempt to open, lock, and delete the file. Return true on
         // success, false otherwise.
         OpenOptions::new()
@@ -144,13 +158,15 @@ impl<'l> AtomicCreateFile<'l> {
             .open(p)
             .and_then(|file| {
                 let md = file.metadata()?;
-                if !md.is_file() || md.len() > 0 {
+                if !md.is_file() || (only_empty && md.len() > 0) {
                     // Files with non-zero length are not stale.
                     return Ok(false);
                 }
 
                 if lock_name(p, &file).is_ok() {
-                    fs::remove_file(p)?;
+                    if delete {
+                        fs::remove_file(p)?;
+                    }
                     Ok(true)
                 } else {
                     Ok(false) // Another process has the lock.
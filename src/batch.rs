@@ -2,11 +2,9 @@
 //! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
 
 //! Batch file operation implementations.
-use crate::packidx::ObjectChecksum;
+use crate::packidx::{HashAlgorithm, ObjectChecksum};
 use crate::progress::ProgressReporter;
 use crate::repo::run_in_parallel;
-use crypto::digest::Digest;
-use crypto::sha1::Sha1;
 use std::{
     fs,
     io::{self, Read, Write},
@@ -15,32 +13,51 @@ use std::{
 use zstd::stream::raw::CParameter;
 use zstd::Encoder;
 
+/// The smallest window log supported by Zstandard.
+const MIN_WINDOW_LOG: u32 = 10;
+
 /// Computes the content checksums of the files at the listed paths.
-pub fn compute_checksums<P>(paths: &[P]) -> io::Result<Vec<ObjectChecksum>>
+pub fn compute_checksums<P>(
+    paths: &[P],
+    hash_algorithm: HashAlgorithm,
+) -> io::Result<Vec<ObjectChecksum>>
 where
     P: AsRef<Path> + Sync,
 {
     run_in_parallel(num_cpus::get(), paths.iter(), |path| {
         let buf = fs::read(path)?;
-        let checksum_buf = &mut [0u8; 20];
-        let mut hasher = Sha1::new();
-        hasher.input(&buf);
-        hasher.result(checksum_buf);
-        Ok(*checksum_buf)
+        Ok(hash_algorithm.checksum(&buf))
     })
     .into_iter()
     .collect::<io::Result<Vec<_>>>()
 }
 
 /// Options for the batch compression functions.
-pub struct CompressionOptions {
+pub struct CompressionOptions<'a> {
     pub level: i32,
     pub window_log: u32,
     pub num_workers: u32,
+    /// The dictionary to compress with, if any. The same dictionary is
+    /// needed to decompress the data.
+    pub dictionary: Option<&'a [u8]>,
+    /// If set, a new Zstandard frame is started after this many bytes of
+    /// input, so that decompression can start at the beginning of any of
+    /// the frames.
+    pub max_frame_input: Option<u64>,
+}
+
+/// The compressed and decompressed size of a Zstandard frame written by
+/// [`compress_files`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameSize {
+    pub compressed: u64,
+    pub decompressed: u64,
 }
 
 /// Compresses the specified set of files using ZStandard compression and the specified options.
-/// Returns the number of bytes processed (the size of the decompressed stream).
+/// Returns the sizes of the Zstandard frames written, which are the same
+/// as the number of bytes processed (the size of the decompressed stream),
+/// unless [`CompressionOptions::max_frame_input`] is set.
 ///
 /// # Arguments
 /// * `pack_file` - the output writer
@@ -51,35 +68,144 @@ pub struct CompressionOptions {
 pub fn compress_files<W, I, R>(
     pack_file: W,
     object_readers: I,
-    opts: &CompressionOptions,
+    opts: &CompressionOptions<'_>,
     reporter: &ProgressReporter,
-) -> io::Result<u64>
+) -> io::Result<Vec<FrameSize>>
 where
     W: Write,
     I: ExactSizeIterator<Item = io::Result<R>>,
     R: Read,
 {
     assert!(opts.num_workers > 0);
-    // Initialize encoder.
-    let mut encoder = Encoder::new(pack_file, opts.level)?;
-    // Zstandard takes NbWorkers to mean extra compression threads (0 means on same thread as IO).
-    encoder.set_parameter(CParameter::NbWorkers(opts.num_workers - 1))?;
-    encoder.set_parameter(CParameter::EnableLongDistanceMatching(true))?;
-    encoder.set_parameter(CParameter::WindowLog(opts.window_log))?;
+    let mut writer = FrameWriter::new(pack_file, opts);
 
     let n_objects = object_readers.len();
-    let mut processed_bytes = 0;
     for (i, obj) in object_readers.enumerate() {
         // TODO(peterwaller-arm): Verify object checksums here, abort on mismatch.
         let mut obj = obj?;
         // let mut file = File::open(&obj)?;
-        let bytes = io::copy(&mut obj, &mut encoder)?;
-        processed_bytes += bytes;
+        io::copy(&mut obj, &mut writer)?;
         reporter.checkpoint(i, Some(n_objects - i));
     }
 
     reporter.checkpoint(n_objects, Some(0));
-    // Important to call .finish()
-    encoder.finish()?;
-    Ok(processed_bytes)
+    writer.finish()
+}
+
+/// Counts the bytes written to the underlying writer.
+struct CountingWriter<W: Write> {
+    writer: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.writer.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Compresses the data written to it into one or more Zstandard frames, see
+/// [`CompressionOptions::max_frame_input`].
+struct FrameWriter<'a, W: Write> {
+    /// The underlying writer, while no frame is being written.
+    writer: Option<CountingWriter<W>>,
+    /// The encoder of the frame being written, which owns the underlying writer.
+    encoder: Option<Encoder<'static, CountingWriter<W>>>,
+    opts: &'a CompressionOptions<'a>,
+    frames: Vec<FrameSize>,
+    /// The number of bytes written to the underlying writer before the current frame.
+    frame_start: u64,
+    /// The number of bytes of input in the current frame.
+    frame_input: u64,
+}
+
+impl<W: Write> FrameWriter<'_, W> {
+    fn new<'a>(writer: W, opts: &'a CompressionOptions<'a>) -> FrameWriter<'a, W> {
+        FrameWriter {
+            writer: Some(CountingWriter { writer, count: 0 }),
+            encoder: None,
+            opts,
+            frames: vec![],
+            frame_start: 0,
+            frame_input: 0,
+        }
+    }
+
+    /// Returns the encoder of the current frame, starting one if needed.
+    fn encoder(&mut self) -> io::Result<&mut Encoder<'static, CountingWriter<W>>> {
+        if let Some(writer) = self.writer.take() {
+            let opts = self.opts;
+            let mut encoder = match opts.dictionary {
+                Some(dictionary) => Encoder::with_dictionary(writer, opts.level, dictionary)?,
+                None => Encoder::new(writer, opts.level)?,
+            };
+            // Zstandard takes NbWorkers to mean extra compression threads (0 means on same thread as IO).
+            encoder.set_parameter(CParameter::NbWorkers(opts.num_workers - 1))?;
+            encoder.set_parameter(CParameter::EnableLongDistanceMatching(true))?;
+            // The window never needs to be larger than the frame.
+            let window_log = match opts.max_frame_input {
+                Some(size) => (u64::BITS - size.saturating_sub(1).leading_zeros())
+                    .clamp(MIN_WINDOW_LOG, opts.window_log),
+                None => opts.window_log,
+            };
+            encoder.set_parameter(CParameter::WindowLog(window_log))?;
+            self.encoder = Some(encoder);
+        }
+        Ok(self.encoder.as_mut().unwrap())
+    }
+
+    /// Ends the current frame, if one was started.
+    fn end_frame(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            let writer = encoder.finish()?;
+            self.frames.push(FrameSize {
+                compressed: writer.count - self.frame_start,
+                decompressed: self.frame_input,
+            });
+            self.frame_start = writer.count;
+            self.frame_input = 0;
+            self.writer = Some(writer);
+        }
+        Ok(())
+    }
+
+    /// Ends the last frame, and returns the sizes of the frames. At least
+    /// one frame is written, even if there was no input.
+    fn finish(mut self) -> io::Result<Vec<FrameSize>> {
+        if self.frames.is_empty() {
+            self.encoder()?;
+        }
+        self.end_frame()?;
+        Ok(self.frames)
+    }
+}
+
+impl<W: Write> Write for FrameWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = match self.opts.max_frame_input {
+            Some(max) => {
+                if self.frame_input >= max {
+                    self.end_frame()?;
+                }
+                std::cmp::min(buf.len() as u64, max - self.frame_input) as usize
+            }
+            None => buf.len(),
+        };
+        let n = self.encoder()?.write(&buf[..len])?;
+        self.frame_input += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.encoder {
+            Some(encoder) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
 }
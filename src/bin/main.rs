@@ -1,16 +1,25 @@
 //! SPDX-License-Identifier: Apache-2.0
 //! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
 
+use elfshaker::bisect;
 use elfshaker::clone;
+use elfshaker::diff;
+use elfshaker::export;
 use elfshaker::extract;
 use elfshaker::find;
+use elfshaker::fsck;
+use elfshaker::gc;
 use elfshaker::list;
 use elfshaker::loosen;
 use elfshaker::pack;
+use elfshaker::reflog;
+use elfshaker::refs;
+use elfshaker::resolve;
 use elfshaker::show;
 use elfshaker::status;
 use elfshaker::store;
 use elfshaker::update;
+use elfshaker::upgrade_index;
 use elfshaker::utils;
 
 use clap::{App, Arg, ArgMatches};
@@ -51,8 +60,17 @@ fn run_subcommand(app: &mut App, matches: ArgMatches) -> Result<(), Box<dyn Erro
         (show::SUBCOMMAND, Some(matches)) => show::run(matches),
         (find::SUBCOMMAND, Some(matches)) => find::run(matches),
         (update::SUBCOMMAND, Some(matches)) => update::run(matches),
+        (upgrade_index::SUBCOMMAND, Some(matches)) => upgrade_index::run(matches),
         (clone::SUBCOMMAND, Some(matches)) => clone::run(matches),
         (loosen::SUBCOMMAND, Some(matches)) => loosen::run(matches),
+        (gc::SUBCOMMAND, Some(matches)) => gc::run(matches),
+        (fsck::SUBCOMMAND, Some(matches)) => fsck::run(matches),
+        (diff::SUBCOMMAND, Some(matches)) => diff::run(matches),
+        (export::SUBCOMMAND, Some(matches)) => export::run(matches),
+        (refs::SUBCOMMAND, Some(matches)) => refs::run(matches),
+        (reflog::SUBCOMMAND, Some(matches)) => reflog::run(matches),
+        (resolve::SUBCOMMAND, Some(matches)) => resolve::run(matches),
+        (bisect::SUBCOMMAND, Some(matches)) => bisect::run(matches),
         _ => {
             app.print_long_help()?;
             println!();
@@ -71,8 +89,17 @@ fn get_app() -> App<'static, 'static> {
         .subcommand(show::get_app())
         .subcommand(find::get_app())
         .subcommand(update::get_app())
+        .subcommand(upgrade_index::get_app())
         .subcommand(clone::get_app())
         .subcommand(loosen::get_app())
+        .subcommand(gc::get_app())
+        .subcommand(fsck::get_app())
+        .subcommand(diff::get_app())
+        .subcommand(export::get_app())
+        .subcommand(refs::get_app())
+        .subcommand(reflog::get_app())
+        .subcommand(resolve::get_app())
+        .subcommand(bisect::get_app())
         .arg(
             Arg::with_name("verbose")
                 .long("verbose")
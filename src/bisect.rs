@@ -0,0 +1,183 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::{error::Error, process::Command};
+
+use super::utils::open_repo_from_cwd;
+use crate::repo::{BisectMark, BisectStep, Repository};
+
+pub const SUBCOMMAND: &str = "bisect";
+
+/// The exit code of `bisect run` commands which cannot test the snapshot
+/// (the same as for `git bisect run`).
+const SKIP_EXIT_CODE: i32 = 125;
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let mut repo = open_repo_from_cwd()?;
+
+    match matches.subcommand() {
+        ("start", Some(matches)) => {
+            let good = repo.find_snapshot(matches.value_of("good").unwrap())?;
+            let bad = repo.find_snapshot(matches.value_of("bad").unwrap())?;
+            let step = repo.bisect_start(&good, &bad, matches.is_present("force"))?;
+            print_step(&step);
+        }
+        ("good", Some(matches)) => mark(&mut repo, matches, BisectMark::Good)?,
+        ("bad", Some(matches)) => mark(&mut repo, matches, BisectMark::Bad)?,
+        ("skip", Some(matches)) => mark(&mut repo, matches, BisectMark::Skip)?,
+        ("run", Some(matches)) => {
+            let command: Vec<_> = matches.values_of("command").unwrap().collect();
+            bisect_run(&mut repo, &command)?;
+        }
+        ("reset", Some(_)) => {
+            if let Some(head) = repo.bisect_reset()? {
+                println!("HEAD is back at {}", head);
+            }
+        }
+        _ => unreachable!("clap requires a subcommand"),
+    }
+
+    Ok(())
+}
+
+fn mark(
+    repo: &mut Repository,
+    matches: &ArgMatches,
+    mark: BisectMark,
+) -> Result<(), Box<dyn Error>> {
+    let snapshot = matches
+        .value_of("snapshot")
+        .map(|s| repo.find_snapshot(s))
+        .transpose()?;
+    let step = repo.bisect_mark(snapshot.as_ref(), mark)?;
+    print_step(&step);
+    Ok(())
+}
+
+/// Runs the command on each extracted snapshot, and marks the snapshot
+/// according to its exit code, until the first bad snapshot is found.
+fn bisect_run(repo: &mut Repository, command: &[&str]) -> Result<(), Box<dyn Error>> {
+    loop {
+        let snapshot = match repo.bisect_current()? {
+            Some(snapshot) => snapshot,
+            // The bisection is over, or was never started.
+            None => return Err("No snapshot is being tested! Use bisect start first.".into()),
+        };
+        println!("Running {} on {}", command.join(" "), snapshot);
+        let status = Command::new(command[0])
+            .args(&command[1..])
+            .current_dir(repo.path())
+            .status()?;
+        let mark = match status.code() {
+            Some(0) => BisectMark::Good,
+            Some(SKIP_EXIT_CODE) => BisectMark::Skip,
+            Some(code) if (1..128).contains(&code) => BisectMark::Bad,
+            _ => {
+                return Err(format!(
+                    "The command failed with {}, stopping the bisection at {}!",
+                    status, snapshot
+                )
+                .into())
+            }
+        };
+        let step = repo.bisect_mark(Some(&snapshot), mark)?;
+        print_step(&step);
+        if !matches!(step, BisectStep::Test { .. }) {
+            return Ok(());
+        }
+    }
+}
+
+fn print_step(step: &BisectStep) {
+    match step {
+        BisectStep::Test {
+            snapshot,
+            remaining,
+        } => println!(
+            "Bisecting: {} snapshot(s) left to test, extracted {}",
+            remaining, snapshot
+        ),
+        BisectStep::FirstBad(snapshot) => println!("{} is the first bad snapshot", snapshot),
+        BisectStep::Ambiguous(snapshots) => {
+            println!("The first bad snapshot could be any of:");
+            for snapshot in snapshots {
+                println!("{}", snapshot);
+            }
+        }
+    }
+}
+
+fn snapshot_arg() -> Arg<'static, 'static> {
+    Arg::with_name("snapshot")
+        .index(1)
+        .help("The snapshot to mark (defaults to the extracted one).")
+}
+
+pub fn get_app() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .about(
+            "Finds the first bad snapshot between a good and a bad one, by binary search \
+            over the snapshots in order (see resolve). Each snapshot to test is extracted \
+            in turn, and is marked as good, bad or skipped, by hand or by bisect run. The \
+            state of the bisection is kept in elfshaker_data/BISECT until bisect reset.",
+        )
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("start")
+                .about("Starts a bisection, and extracts the first snapshot to test.")
+                .arg(
+                    Arg::with_name("good")
+                        .required(true)
+                        .index(1)
+                        .help("A good snapshot."),
+                )
+                .arg(
+                    Arg::with_name("bad")
+                        .required(true)
+                        .index(2)
+                        .help("A bad snapshot, which comes after the good one."),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Overwrite the files modified since the last extraction."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("good")
+                .about("Marks the snapshot as good, and extracts the next one to test.")
+                .arg(snapshot_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("bad")
+                .about("Marks the snapshot as bad, and extracts the next one to test.")
+                .arg(snapshot_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("skip")
+                .about("Marks the snapshot as untestable, and extracts the next one to test.")
+                .arg(snapshot_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about(
+                    "Runs the command in the worktree for each snapshot to test, until the \
+                    first bad snapshot is found. Exit code 0 marks the snapshot as good, 125 \
+                    as skipped (like contrib/manyclangs-run does when the snapshot cannot be \
+                    used), other codes below 128 as bad. Other codes stop the bisection.",
+                )
+                .setting(AppSettings::TrailingVarArg)
+                .arg(
+                    Arg::with_name("command")
+                        .required(true)
+                        .index(1)
+                        .multiple(true)
+                        .help("The command to run, followed by its arguments."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reset")
+                .about("Ends the bisection, and extracts the snapshot HEAD was at before it."),
+        )
+}
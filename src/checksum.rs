@@ -0,0 +1,294 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+//! Contains the content checksums identifying objects, and the hash
+//! algorithms used to compute them.
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::Deref;
+use std::str::FromStr;
+
+use serde::de::{SeqAccess, Visitor};
+use serde::{ser::SerializeTuple, Deserialize, Deserializer, Serialize, Serializer};
+use sha1::Digest;
+
+/// The size of the largest checksum, in bytes.
+const MAX_CHECKSUM_SIZE: usize = 32;
+
+/// The content checksum of an object. Its size depends on the
+/// [`HashAlgorithm`] used to compute it, which is recorded in the index.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectChecksum {
+    bytes: [u8; MAX_CHECKSUM_SIZE],
+    len: u8,
+}
+
+impl ObjectChecksum {
+    /// Returns [`None`] if the checksum is larger than any of those computed
+    /// by the [`HashAlgorithm`]s.
+    pub fn from_slice(checksum: &[u8]) -> Option<Self> {
+        if checksum.len() > MAX_CHECKSUM_SIZE {
+            return None;
+        }
+        let mut bytes = [0; MAX_CHECKSUM_SIZE];
+        bytes[..checksum.len()].copy_from_slice(checksum);
+        Some(Self {
+            bytes,
+            len: checksum.len() as u8,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl From<[u8; 20]> for ObjectChecksum {
+    fn from(checksum: [u8; 20]) -> Self {
+        Self::from_slice(&checksum).unwrap()
+    }
+}
+
+impl Deref for ObjectChecksum {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl AsRef<[u8]> for ObjectChecksum {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl fmt::Debug for ObjectChecksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ObjectChecksum({})", hex::encode(self))
+    }
+}
+
+/// Serialized as an array of bytes, like the `[u8; 20]` used for SHA-1
+/// checksums by older versions of elfshaker.
+impl Serialize for ObjectChecksum {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_tuple(self.len as usize)?;
+        for byte in self.as_bytes() {
+            s.serialize_element(byte)?;
+        }
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ObjectChecksum {
+    fn deserialize<D>(deserializer: D) -> Result<ObjectChecksum, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct VisitObjectChecksum;
+
+        impl<'de> Visitor<'de> for VisitObjectChecksum {
+            type Value = ObjectChecksum;
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<ObjectChecksum, V::Error>
+            where
+                V: SeqAccess<'de>,
+            {
+                let mut bytes = vec![];
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                self.visit_bytes(&bytes)
+            }
+
+            fn visit_bytes<E>(self, bytes: &[u8]) -> Result<ObjectChecksum, E>
+            where
+                E: serde::de::Error,
+            {
+                ObjectChecksum::from_slice(bytes)
+                    .ok_or_else(|| E::invalid_length(bytes.len(), &self))
+            }
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "at most {} bytes", MAX_CHECKSUM_SIZE)
+            }
+        }
+
+        deserializer.deserialize_tuple(MAX_CHECKSUM_SIZE, VisitObjectChecksum)
+    }
+}
+
+/// The algorithms used to compute [`ObjectChecksum`]s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// Used by all indexes created by older versions of elfshaker. SHA-1 is
+    /// not collision resistant, so it is only kept to read those indexes.
+    Sha1,
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub const ALL: &'static [HashAlgorithm] = &[Self::Sha1, Self::Sha256, Self::Blake3];
+
+    /// The names of the algorithms, as accepted by [`HashAlgorithm::from_str`].
+    pub const NAMES: &'static [&'static str] = &["sha1", "sha256", "blake3"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    /// The size of the checksums computed by the algorithm, in bytes.
+    pub fn checksum_size(self) -> usize {
+        match self {
+            Self::Sha1 => 20,
+            Self::Sha256 | Self::Blake3 => 32,
+        }
+    }
+
+    pub fn hasher(self) -> Hasher {
+        Hasher(match self {
+            Self::Sha1 => HasherState::Sha1(sha1::Sha1::new()),
+            Self::Sha256 => HasherState::Sha256(sha2::Sha256::new()),
+            Self::Blake3 => HasherState::Blake3(Box::new(blake3::Hasher::new())),
+        })
+    }
+
+    /// Computes the checksum of the data.
+    pub fn checksum(self, data: &[u8]) -> ObjectChecksum {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Computes the checksum of everything read from the reader.
+    pub fn checksum_reader(self, mut reader: impl Read) -> io::Result<ObjectChecksum> {
+        let mut hasher = self.hasher();
+        io::copy(&mut reader, &mut hasher)?;
+        Ok(hasher.finalize())
+    }
+}
+
+/// New snapshots are stored with BLAKE3 checksums, unless another algorithm
+/// is selected.
+impl Default for HashAlgorithm {
+    fn default() -> Self {
+        Self::Blake3
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            "blake3" => Ok(Self::Blake3),
+            _ => Err(format!(
+                "Unknown hash algorithm '{}' (expected one of {})!",
+                s,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Computes an [`ObjectChecksum`] incrementally, see [`HashAlgorithm::hasher`].
+pub struct Hasher(HasherState);
+
+enum HasherState {
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.0 {
+            HasherState::Sha1(hasher) => hasher.update(data),
+            HasherState::Sha256(hasher) => hasher.update(data),
+            HasherState::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    pub fn finalize(self) -> ObjectChecksum {
+        let checksum = match self.0 {
+            HasherState::Sha1(hasher) => ObjectChecksum::from_slice(&hasher.finalize()),
+            HasherState::Sha256(hasher) => ObjectChecksum::from_slice(&hasher.finalize()),
+            HasherState::Blake3(hasher) => ObjectChecksum::from_slice(hasher.finalize().as_bytes()),
+        };
+        checksum.unwrap()
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_known_values() {
+        let expected = [
+            (
+                HashAlgorithm::Sha1,
+                "a9993e364706816aba3e25717850c26c9cd0d89d",
+            ),
+            (
+                HashAlgorithm::Sha256,
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                HashAlgorithm::Blake3,
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ),
+        ];
+        for (algorithm, checksum) in expected {
+            let actual = algorithm.checksum(b"abc");
+            assert_eq!(checksum, hex::encode(actual));
+            assert_eq!(algorithm.checksum_size(), actual.len());
+            assert_eq!(Ok(algorithm), algorithm.name().parse());
+        }
+    }
+
+    #[test]
+    fn checksums_serialize_as_byte_arrays() {
+        // SHA-1 checksums are encoded like the [u8; 20] of older versions.
+        let sha1 = [7u8; 20];
+        let encoded = rmp_serde::to_vec(&ObjectChecksum::from(sha1)).unwrap();
+        assert_eq!(rmp_serde::to_vec(&sha1).unwrap(), encoded);
+        let decoded: ObjectChecksum = rmp_serde::from_read_ref(&encoded).unwrap();
+        assert_eq!(ObjectChecksum::from(sha1), decoded);
+
+        let blake3 = HashAlgorithm::Blake3.checksum(b"abc");
+        let encoded = rmp_serde::to_vec(&blake3).unwrap();
+        let decoded: ObjectChecksum = rmp_serde::from_read_ref(&encoded).unwrap();
+        assert_eq!(blake3, decoded);
+    }
+}
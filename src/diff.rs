@@ -0,0 +1,220 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use clap::{App, Arg, ArgMatches};
+use serde::Serialize;
+use std::{collections::HashMap, error::Error, ffi::OsString, path::Path};
+
+use super::utils::{format_size, open_repo_from_cwd, print_table};
+use crate::packidx::FileEntry;
+use crate::repo::Repository;
+
+pub const SUBCOMMAND: &str = "diff";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum DiffStatus {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// One side (old or new) of a changed file.
+#[derive(Debug, Serialize)]
+struct DiffSide {
+    size: u64,
+    checksum: String,
+    mode: u32,
+    symlink_target: Option<String>,
+}
+
+impl From<&FileEntry> for DiffSide {
+    fn from(entry: &FileEntry) -> Self {
+        Self {
+            size: entry.obj_metadata.size,
+            checksum: hex::encode(entry.checksum),
+            mode: entry.file_metadata.bits_mods,
+            symlink_target: if entry.file_metadata.is_symlink_file {
+                Some(entry.file_metadata.symlink_target.display().to_string())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DiffEntry {
+    status: DiffStatus,
+    path: String,
+    old: Option<DiffSide>,
+    new: Option<DiffSide>,
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let from = matches.value_of("from").unwrap();
+    let to = matches.value_of("to").unwrap();
+    let json_output = matches.is_present("json");
+    let name_only = matches.is_present("name-only");
+    let bytes = matches.is_present("bytes");
+
+    let repo = open_repo_from_cwd()?;
+    let from_entries = repo.load_snapshot_entries(&repo.find_snapshot(from)?)?;
+    let to_entries = repo.load_snapshot_entries(&repo.find_snapshot(to)?)?;
+    let diff = diff_entries(&from_entries, &to_entries);
+
+    if json_output {
+        println!("{}", serde_json::to_string(&diff)?);
+    } else if name_only {
+        for entry in &diff {
+            println!("{}", entry.path);
+        }
+    } else {
+        print_diff(&diff, bytes);
+    }
+
+    Ok(())
+}
+
+pub fn get_app() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .about(
+            "Prints the files which were added, removed or modified between two snapshots, \
+            without extracting either.",
+        )
+        .arg(
+            Arg::with_name("from")
+                .required(true)
+                .index(1)
+                .help("The old snapshot."),
+        )
+        .arg(
+            Arg::with_name("to")
+                .required(true)
+                .index(2)
+                .help("The new snapshot."),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .conflicts_with("name-only")
+                .help("Prints the changes in JSON format."),
+        )
+        .arg(
+            Arg::with_name("name-only")
+                .long("name-only")
+                .help("Prints only the paths of the changed files."),
+        )
+        .arg(
+            Arg::with_name("bytes")
+                .long("bytes")
+                .help("Print the sizes in bytes."),
+        )
+}
+
+/// Classifies the changes between two snapshots, sorted by path.
+fn diff_entries(from_entries: &[FileEntry], to_entries: &[FileEntry]) -> Vec<DiffEntry> {
+    let (added, removed) = Repository::compute_entry_diff(from_entries, to_entries);
+
+    let mut changes: HashMap<&OsString, (Option<&FileEntry>, Option<&FileEntry>)> = HashMap::new();
+    for entry in &removed {
+        changes.entry(&entry.path).or_default().0 = Some(entry);
+    }
+    for entry in &added {
+        changes.entry(&entry.path).or_default().1 = Some(entry);
+    }
+
+    // Files with the same contents might still differ in their metadata.
+    let to_lookup: HashMap<_, _> = to_entries.iter().map(|e| (&e.path, e)).collect();
+    for old in from_entries {
+        if changes.contains_key(&old.path) {
+            continue;
+        }
+        if let Some(&new) = to_lookup.get(&old.path) {
+            if old.file_metadata.bits_mods != new.file_metadata.bits_mods
+                || old.file_metadata.is_symlink_file != new.file_metadata.is_symlink_file
+                || old.file_metadata.symlink_target != new.file_metadata.symlink_target
+            {
+                changes.insert(&old.path, (Some(old), Some(new)));
+            }
+        }
+    }
+
+    let mut diff = changes
+        .into_iter()
+        .map(|(path, (old, new))| DiffEntry {
+            status: match (old, new) {
+                (Some(_), Some(_)) => DiffStatus::Modified,
+                (Some(_), None) => DiffStatus::Removed,
+                (None, _) => DiffStatus::Added,
+            },
+            path: Path::new(path).display().to_string(),
+            old: old.map(DiffSide::from),
+            new: new.map(DiffSide::from),
+        })
+        .collect::<Vec<_>>();
+    diff.sort_by(|a, b| a.path.cmp(&b.path));
+    diff
+}
+
+fn print_diff(diff: &[DiffEntry], bytes: bool) {
+    let size_str = |side: &Option<DiffSide>| match side {
+        Some(side) if bytes => side.size.to_string(),
+        Some(side) => format_size(side.size),
+        None => "-".to_owned(),
+    };
+    let checksum_str = |side: &Option<DiffSide>| match side {
+        Some(side) => side.checksum.clone(),
+        None => "-".to_owned(),
+    };
+    let mode_str = |side: &Option<DiffSide>| match side {
+        Some(side) => format!("{:o}", side.mode),
+        None => "-".to_owned(),
+    };
+
+    let table = diff
+        .iter()
+        .map(|entry| {
+            let mut file = entry.path.clone();
+            let old_target = entry.old.as_ref().and_then(|s| s.symlink_target.as_ref());
+            let new_target = entry.new.as_ref().and_then(|s| s.symlink_target.as_ref());
+            match (old_target, new_target) {
+                (Some(old), Some(new)) if old != new => {
+                    file += &format!(" (symlink {} => {})", old, new)
+                }
+                (None, Some(new)) => file += &format!(" (symlink => {})", new),
+                (Some(old), None) => file += &format!(" (symlink {} =>)", old),
+                _ => {}
+            }
+            [
+                match entry.status {
+                    DiffStatus::Added => "A",
+                    DiffStatus::Removed => "D",
+                    DiffStatus::Modified => "M",
+                }
+                .to_owned(),
+                size_str(&entry.old),
+                size_str(&entry.new),
+                checksum_str(&entry.old),
+                checksum_str(&entry.new),
+                mode_str(&entry.old),
+                mode_str(&entry.new),
+                file,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_table(
+        Some(&[
+            "STATUS".to_owned(),
+            "OLD SIZE".to_owned(),
+            "NEW SIZE".to_owned(),
+            "OLD CHECKSUM".to_owned(),
+            "NEW CHECKSUM".to_owned(),
+            "OLD MODE".to_owned(),
+            "NEW MODE".to_owned(),
+            "FILE".to_owned(),
+        ]),
+        table.iter(),
+    );
+}
@@ -0,0 +1,176 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use clap::{App, Arg, ArgMatches};
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use super::utils::open_repo_from_cwd;
+use crate::packidx::FileEntry;
+use crate::repo::{Error as RepoError, Repository, SnapshotId};
+
+pub const SUBCOMMAND: &str = "export";
+
+/// The mode of files in the archive, if the snapshot does not record one.
+const DEFAULT_FILE_MODE: u32 = 0o644;
+/// The mode of symlinks in the archive, if the snapshot does not record one.
+const DEFAULT_SYMLINK_MODE: u32 = 0o777;
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let snapshot = matches.value_of("snapshot").unwrap();
+    let output = matches.value_of_os("output").unwrap();
+    let compress =
+        matches.is_present("zstd") || Path::new(output).extension() == Some(OsStr::new("zst"));
+
+    let repo = open_repo_from_cwd()?;
+    let snapshot = repo.find_snapshot(snapshot)?;
+    let mut entries = repo.load_snapshot_entries(&snapshot)?;
+    // Read the objects in the order in which they are stored, so that each
+    // frame of the pack only needs to be decompressed once.
+    entries.sort_by(|a, b| (a.obj_metadata.offset, &a.path).cmp(&(b.obj_metadata.offset, &b.path)));
+
+    if output == "-" {
+        write_archive(&repo, &snapshot, &entries, io::stdout().lock(), compress)?;
+    } else {
+        let file = File::create(output).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("couldn't create {}", Path::new(output).display()),
+            )
+        })?;
+        write_archive(&repo, &snapshot, &entries, BufWriter::new(file), compress)?;
+    }
+
+    eprintln!("Exported '{}' ({} files)", snapshot, entries.len());
+    Ok(())
+}
+
+pub fn get_app() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .about(
+            "Writes the files in the snapshot to a tar archive, preserving their modes, \
+            modification times and symlinks.",
+        )
+        .arg(
+            Arg::with_name("snapshot")
+                .required(true)
+                .index(1)
+                .help("The snapshot to export."),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .required(true)
+                .value_name("file")
+                .help(
+                    "The archive to write, or - for stdout. \
+                    Archives with a .zst extension are compressed with Zstandard.",
+                ),
+        )
+        .arg(
+            Arg::with_name("zstd")
+                .long("zstd")
+                .help("Compress the archive with Zstandard, regardless of the extension."),
+        )
+}
+
+/// Writes the entries to `writer` as a tar archive, optionally compressed with Zstandard.
+fn write_archive<W: Write>(
+    repo: &Repository,
+    snapshot: &SnapshotId,
+    entries: &[FileEntry],
+    writer: W,
+    compress: bool,
+) -> Result<(), Box<dyn Error>> {
+    if compress {
+        // Level 0 selects the default compression level.
+        let encoder = zstd::Encoder::new(writer, 0)?;
+        write_tar(repo, snapshot, entries, encoder)?
+            .finish()?
+            .flush()?;
+    } else {
+        write_tar(repo, snapshot, entries, writer)?.flush()?;
+    }
+    Ok(())
+}
+
+/// Writes the entries to `writer` as a tar archive, and returns the writer.
+fn write_tar<W: Write>(
+    repo: &Repository,
+    snapshot: &SnapshotId,
+    entries: &[FileEntry],
+    writer: W,
+) -> Result<W, RepoError> {
+    let mut builder = tar::Builder::new(writer);
+    repo.read_entries(snapshot.pack(), entries, true, |entry, contents| {
+        Ok(append_entry(&mut builder, entry, contents)?)
+    })?;
+    Ok(builder.into_inner()?)
+}
+
+fn append_entry<W: Write>(
+    builder: &mut tar::Builder<W>,
+    entry: &FileEntry,
+    contents: &[u8],
+) -> io::Result<()> {
+    let metadata = &entry.file_metadata;
+    let path = Path::new(&entry.path);
+
+    // The tar header only has room for whole seconds, so the nanoseconds are
+    // stored in a PAX extended header, which applies to the next entry.
+    if metadata.last_modified >= 0 && metadata.last_modified_nanos != 0 {
+        let record = pax_record(
+            "mtime",
+            &format!(
+                "{}.{:09}",
+                metadata.last_modified, metadata.last_modified_nanos
+            ),
+        );
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::XHeader);
+        header.set_mode(DEFAULT_FILE_MODE);
+        header.set_size(record.len() as u64);
+        builder.append_data(&mut header, "PaxHeader", &record[..])?;
+    }
+
+    let mut header = tar::Header::new_gnu();
+    header.set_mtime(metadata.last_modified.max(0) as u64);
+    if metadata.is_symlink_file && !metadata.symlink_target.as_os_str().is_empty() {
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_mode(permission_bits(metadata.bits_mods, DEFAULT_SYMLINK_MODE));
+        header.set_size(0);
+        builder.append_link(&mut header, path, &metadata.symlink_target)
+    } else {
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(permission_bits(metadata.bits_mods, DEFAULT_FILE_MODE));
+        header.set_size(contents.len() as u64);
+        builder.append_data(&mut header, path, contents)
+    }
+}
+
+/// Strips the file type from `bits_mods`, falling back to `default` on
+/// platforms which do not record permissions.
+fn permission_bits(bits_mods: u32, default: u32) -> u32 {
+    match bits_mods & 0o7777 {
+        0 => default,
+        bits => bits,
+    }
+}
+
+/// Formats a PAX extended header record (`<length> <key>=<value>\n`), where
+/// the length includes the digits of the length itself.
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let rest = format!(" {}={}\n", key, value);
+    let mut len = rest.len() + 1;
+    while len.to_string().len() + rest.len() != len {
+        len = len.to_string().len() + rest.len();
+    }
+    format!("{}{}", len, rest).into_bytes()
+}
@@ -10,7 +10,9 @@ use super::utils::{
     create_percentage_print_reporter, open_repo_from_cwd, open_repo_with_separate_worktree_from,
 };
 use crate::packidx::PackError;
-use crate::repo::{Error as RepoError, ExtractOptions, ExtractResult, REPO_DIR};
+use crate::repo::{
+    Error as RepoError, ExtractOptions, ExtractResult, Repository, SnapshotId, REPO_DIR,
+};
 
 pub const SUBCOMMAND: &str = "extract";
 
@@ -22,17 +24,14 @@ pub fn do_extract(
 ) -> Result<ExtractResult, RepoError> {
     let mut repo: crate::repo::Repository =
         open_repo_with_separate_worktree_from(&data_dir_location, &worktree_path)?;
-    let new_head = match repo.find_snapshot(snapshot) {
-        Err(RepoError::PackError(PackError::SnapshotNotFound(_))) => {
-            info!("Snapshot not available locally. Updating remotes...");
-            repo.update_remotes()?;
-            repo.find_snapshot(snapshot)?
-        }
-        r => r?,
-    };
+    let new_head = find_snapshot_or_update(&mut repo, snapshot)?;
 
     match repo.read_head()? {
-        (Some(h), _) if h == new_head && !opts.reset => {
+        (Some(h), _)
+            if h == new_head
+                && !opts.reset()
+                && repo.read_head_sparse_spec()? == opts.sparse_spec() =>
+        {
             // The specified snapshot is already extracted and --reset is not specified,
             // so this is a no-op.
             warn!(
@@ -52,6 +51,34 @@ pub fn do_extract(
     repo.extract_snapshot(new_head.clone(), opts)
 }
 
+/// Extracts all files in the snapshot into `destination`, without reading or
+/// updating HEAD. This allows multiple snapshots to be extracted side by side
+/// from the same `elfshaker_data`.
+pub fn do_extract_into(
+    data_dir_location: PathBuf,
+    destination: PathBuf,
+    snapshot: &str,
+    opts: ExtractOptions,
+) -> Result<ExtractResult, RepoError> {
+    let mut repo: crate::repo::Repository =
+        open_repo_with_separate_worktree_from(&data_dir_location, &destination)?;
+    let snapshot = find_snapshot_or_update(&mut repo, snapshot)?;
+
+    repo.set_progress_reporter(|msg| create_percentage_print_reporter(msg, 5));
+    repo.extract_snapshot_into(&snapshot, &destination, opts)
+}
+
+fn find_snapshot_or_update(repo: &mut Repository, snapshot: &str) -> Result<SnapshotId, RepoError> {
+    match repo.find_snapshot(snapshot) {
+        Err(RepoError::PackError(PackError::SnapshotNotFound(_))) => {
+            info!("Snapshot not available locally. Updating remotes...");
+            repo.update_remotes()?;
+            repo.find_snapshot(snapshot)
+        }
+        r => r,
+    }
+}
+
 pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     let snapshot = matches.value_of("snapshot").unwrap();
     let is_reset = matches.is_present("reset");
@@ -76,13 +103,33 @@ pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     opts.set_reset(is_reset);
     opts.set_force(is_force);
     opts.set_num_workers(threads);
+    opts.set_include(
+        matches
+            .values_of("include")
+            .map(|values| values.map(str::to_owned).collect())
+            .unwrap_or_default(),
+    );
+    opts.set_exclude(
+        matches
+            .values_of("exclude")
+            .map(|values| values.map(str::to_owned).collect())
+            .unwrap_or_default(),
+    );
 
-    let result = do_extract(
-        std::env::current_dir()?.join(REPO_DIR),
-        std::env::current_dir()?,
-        snapshot,
-        opts,
-    )?;
+    let result = match matches.value_of_os("into") {
+        Some(destination) => do_extract_into(
+            std::env::current_dir()?.join(REPO_DIR),
+            std::env::current_dir()?.join(destination),
+            snapshot,
+            opts,
+        )?,
+        None => do_extract(
+            std::env::current_dir()?.join(REPO_DIR),
+            std::env::current_dir()?,
+            snapshot,
+            opts,
+        )?,
+    };
 
     eprintln!("A \t{} files", result.added_file_count);
     eprintln!("D \t{} files", result.removed_file_count);
@@ -108,11 +155,35 @@ pub fn get_app() -> App<'static, 'static> {
         .arg(
             Arg::with_name("verify")
                 .long("verify")
-                .help("Enables SHA-1 verification of the extracted files. This has a small performance overhead."),
+                .help("Enables checksum verification of the extracted files. This has a small performance overhead."),
         )
         .arg(Arg::with_name("force")
                 .long("force")
                 .help("Disables certain runtime checks that aim to detect unexpected file modification and prevent data loss."))
+        .arg(Arg::with_name("into")
+                .long("into")
+                .takes_value(true)
+                .value_name("dir")
+                .conflicts_with("reset")
+                .help("Extract all files from the snapshot into the specified directory instead. \
+                      HEAD is not read or updated, so this can be used to extract several snapshots side by side. \
+                      The directory must be empty, unless --force is specified."))
+        .arg(Arg::with_name("include")
+                .long("include")
+                .takes_value(true)
+                .value_name("glob")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only extract the files matching this glob pattern (can be specified multiple times). \
+                      `*` does not match `/`, use `**` to match any number of directories. \
+                      The patterns are recorded in HEAD and used in later incremental extracts."))
+        .arg(Arg::with_name("exclude")
+                .long("exclude")
+                .takes_value(true)
+                .value_name("glob")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Do not extract the files matching this glob pattern (can be specified multiple times)."))
         .arg(Arg::with_name("threads")
                 .short("T")
                 .long("threads")
@@ -2,52 +2,312 @@
 //! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
 
 use clap::{App, Arg, ArgMatches};
-use std::error::Error;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde::Serialize;
+use std::{error::Error, ops::ControlFlow};
 
-use super::utils::{open_repo_from_cwd, print_table};
+use super::utils::{
+    format_snapshot_attributes, open_repo_from_cwd, print_table, AttributeFilter,
+    SNAPSHOT_ATTRIBUTES_HEADER,
+};
+use crate::packidx::{Attributes, MESSAGE_ATTRIBUTE};
+use crate::repo::{PackId, Repository};
 
 pub const SUBCOMMAND: &str = "find";
 
+/// The parts of a snapshot which the search term is matched against.
+const SEARCH_FIELDS: [&str; 4] = ["tag", "message", "meta", "path"];
+
+/// How the search term is matched.
+enum Pattern {
+    Substring(String),
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn is_match(&self, s: &str) -> bool {
+        match self {
+            Self::Substring(term) => s.contains(term.as_str()),
+            Self::Glob(glob) => glob.is_match(s),
+            Self::Regex(regex) => regex.is_match(s),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FindResult {
+    snapshot: String,
+    pack: String,
+    tag: String,
+    attributes: Attributes,
+    /// The paths which matched, when searching paths.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<String>,
+}
+
+/// What to search for, and where.
+struct Query {
+    /// [`None`] matches all snapshots.
+    pattern: Option<Pattern>,
+    fields: Vec<String>,
+    filters: Vec<AttributeFilter>,
+    limit: Option<usize>,
+}
+
+impl Query {
+    fn searches(&self, field: &str) -> bool {
+        self.fields.iter().any(|f| f == field)
+    }
+
+    /// Matches the snapshot, and returns the matching paths, or [`None`] if
+    /// the snapshot does not match.
+    fn match_snapshot<'a, I>(
+        &self,
+        tag: &str,
+        attributes: &Attributes,
+        paths: I,
+    ) -> Option<Vec<String>>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        if !self.filters.iter().all(|f| f.matches(attributes)) {
+            return None;
+        }
+        let pattern = match &self.pattern {
+            Some(pattern) => pattern,
+            None => return Some(vec![]),
+        };
+        let matched_paths: Vec<_> = if self.searches("path") {
+            paths
+                .filter(|path| pattern.is_match(path))
+                .map(str::to_owned)
+                .collect()
+        } else {
+            vec![]
+        };
+        let is_match = (self.searches("tag") && pattern.is_match(tag))
+            || (self.searches("message")
+                && attributes
+                    .get(MESSAGE_ATTRIBUTE)
+                    .is_some_and(|message| pattern.is_match(message)))
+            || (self.searches("meta")
+                && attributes
+                    .iter()
+                    .any(|(key, value)| pattern.is_match(&format!("{}={}", key, value))))
+            || !matched_paths.is_empty();
+        is_match.then_some(matched_paths)
+    }
+
+    fn is_full(&self, results: &[FindResult]) -> bool {
+        self.limit.is_some_and(|limit| results.len() >= limit)
+    }
+}
+
 pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     let term = matches.value_of("term").unwrap();
+    let pattern = if term.is_empty() {
+        None
+    } else if matches.is_present("glob") {
+        Some(Pattern::Glob(
+            GlobBuilder::new(term)
+                .literal_separator(true)
+                .build()?
+                .compile_matcher(),
+        ))
+    } else if matches.is_present("regex") {
+        Some(Pattern::Regex(Regex::new(term)?))
+    } else {
+        Some(Pattern::Substring(term.to_owned()))
+    };
+    let query = Query {
+        pattern,
+        fields: matches
+            .values_of("in")
+            .unwrap()
+            .flat_map(|v| v.split(','))
+            .map(str::to_owned)
+            .collect(),
+        filters: matches
+            .values_of("meta")
+            .into_iter()
+            .flatten()
+            .map(str::parse)
+            .collect::<Result<Vec<AttributeFilter>, _>>()?,
+        limit: matches.value_of("limit").map(str::parse).transpose()?,
+    };
+    if let Some(field) = query
+        .fields
+        .iter()
+        .find(|f| !SEARCH_FIELDS.contains(&f.as_str()))
+    {
+        return Err(format!(
+            "Cannot search '{}', expected one of {}!",
+            field,
+            SEARCH_FIELDS.join(", ")
+        )
+        .into());
+    }
+
     let repo = open_repo_from_cwd()?;
+    let mut packs = repo.packs()?;
+    if let Some(names) = matches.values_of("pack") {
+        let names: Vec<_> = names.collect();
+        if let Some(name) = names
+            .iter()
+            .find(|name| !packs.iter().any(|p| p.to_string() == **name))
+        {
+            return Err(crate::repo::Error::PackNotFound(name.to_string()).into());
+        }
+        packs.retain(|p| names.contains(&p.to_string().as_str()));
+    }
+
+    let mut results = vec![];
+    for pack_id in &packs {
+        if query.is_full(&results) {
+            break;
+        }
+        find_in_pack(&repo, pack_id, &query, &mut results)?;
+    }
 
-    let mut table = vec![];
-    for pack_id in repo.packs()? {
-        for snapshot in repo.load_index_snapshots(&pack_id)? {
-            table.push([snapshot.to_string(), pack_id.to_string()])
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string(&results)?);
+    } else {
+        let long = matches.is_present("long");
+        let table = results.into_iter().map(|result| {
+            let mut row = vec![result.tag, result.pack];
+            if long {
+                row.extend(format_snapshot_attributes(&result.attributes));
+            }
+            row
+        });
+        let mut header = vec!["SNAPSHOT".to_owned(), "PACK".to_owned()];
+        if long {
+            header.extend(SNAPSHOT_ATTRIBUTES_HEADER.map(str::to_owned));
         }
+        print_table(Some(header), table);
     }
+    Ok(())
+}
 
-    let table = repo
-        .packs()?
-        .into_iter()
-        .flat_map(|p| {
-            repo.load_index_snapshots(&p).map(|pack_index| {
-                pack_index
-                    .into_iter()
-                    .filter_map(|s| {
-                        s.contains(term)
-                            .then(|| IntoIterator::into_iter([s.to_string(), p.to_string()]))
-                    })
-                    .collect::<Vec<_>>()
-            })
-        })
-        .flatten();
-
-    let i = IntoIterator::into_iter(["SNAPSHOT".to_owned(), "PACK".to_owned()]);
-    print_table(Some(i), table);
+/// Adds the matching snapshots of the pack to the results, in a single pass
+/// over its index. The file lists are only loaded when searching paths.
+fn find_in_pack(
+    repo: &Repository,
+    pack_id: &PackId,
+    query: &Query,
+    results: &mut Vec<FindResult>,
+) -> Result<(), Box<dyn Error>> {
+    let mut push_result = |tag: &str, attributes: &Attributes, paths| {
+        results.push(FindResult {
+            snapshot: format!("{}:{}", pack_id, tag),
+            pack: pack_id.to_string(),
+            tag: tag.to_owned(),
+            attributes: attributes.clone(),
+            paths,
+        });
+        if query.is_full(results) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    };
+
+    if query.pattern.is_some() && query.searches("path") {
+        let index = repo.load_index(pack_id)?;
+        let no_attributes = Attributes::new();
+        index.for_each_snapshot(|tag, entries| {
+            let attributes = index.snapshot_attributes(tag).unwrap_or(&no_attributes);
+            let paths = entries.iter().filter_map(|entry| entry.path.to_str());
+            match query.match_snapshot(tag, attributes, paths) {
+                Some(mut paths) => {
+                    paths.sort();
+                    push_result(tag, attributes, paths)
+                }
+                None => ControlFlow::Continue(()),
+            }
+        })?;
+    } else {
+        for (tag, attributes) in repo.load_index_snapshot_attributes(pack_id)? {
+            if let Some(paths) = query.match_snapshot(&tag, &attributes, std::iter::empty()) {
+                if push_result(&tag, &attributes, paths).is_break() {
+                    break;
+                }
+            }
+        }
+    }
     Ok(())
 }
 
 pub fn get_app() -> App<'static, 'static> {
     App::new(SUBCOMMAND)
-        .about("Searches the repository index.")
+        .about(
+            "Searches the repository index. By default, finds the snapshots whose tag \
+            contains the search term.",
+        )
         .arg(
             Arg::with_name("term")
                 .required(true)
                 .index(1)
                 .default_value("")
-                .help("The search term."),
+                .help("The search term (matches all snapshots if empty)."),
+        )
+        .arg(
+            Arg::with_name("glob")
+                .long("glob")
+                .conflicts_with("regex")
+                .help("Matches the search term as a glob pattern, where * does not match / and ** matches any number of directories."),
+        )
+        .arg(
+            Arg::with_name("regex")
+                .long("regex")
+                .help("Matches the search term as a regular expression, which matches anywhere unless anchored with ^ and $."),
+        )
+        .arg(
+            Arg::with_name("in")
+                .takes_value(true)
+                .long("in")
+                .value_name("field")
+                .multiple(true)
+                .number_of_values(1)
+                .default_value("tag")
+                .help("What the search term is matched against: tag, message, meta (as key=value) or path (the files in the snapshot). Can be given multiple times, or separated by commas."),
+        )
+        .arg(
+            Arg::with_name("meta")
+                .takes_value(true)
+                .long("meta")
+                .value_name("key[=value]")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only finds the snapshots which have the attribute (with the value, if given). Can be given multiple times."),
+        )
+        .arg(
+            Arg::with_name("pack")
+                .takes_value(true)
+                .long("pack")
+                .value_name("pack")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only searches the pack (e.g. my-pack or loose/my-snapshot). Can be given multiple times."),
+        )
+        .arg(
+            Arg::with_name("limit")
+                .takes_value(true)
+                .long("limit")
+                .value_name("count")
+                .help("Stops after finding this many snapshots."),
+        )
+        .arg(
+            Arg::with_name("long")
+                .long("long")
+                .conflicts_with("json")
+                .help("Also prints the creation time, message and other attributes of the snapshots."),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Prints the snapshots in JSON format, with their attributes and the matching paths when searching paths. Prefer it to the table in scripts."),
         )
 }
@@ -0,0 +1,81 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use clap::{App, Arg, ArgMatches};
+use log::info;
+use std::error::Error;
+
+use super::utils::open_repo_from_cwd;
+
+pub const SUBCOMMAND: &str = "fsck";
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let json_output = matches.is_present("json");
+
+    // Parse --threads
+    let threads: u32 = match matches.value_of("threads").unwrap().parse()? {
+        0 => {
+            let phys_cores = num_cpus::get_physical();
+            info!(
+                "-T|--threads=0: defaulting to number of physical cores (OS reports {} cores)",
+                phys_cores
+            );
+            phys_cores as u32
+        }
+        n => n,
+    };
+
+    let repo = open_repo_from_cwd()?;
+    let report = repo.fsck(threads)?;
+
+    if json_output {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        for issue in &report.issues {
+            match &issue.object {
+                Some(object) => println!(
+                    "{} {} ({}): {}",
+                    issue.kind,
+                    issue.path.display(),
+                    object,
+                    issue.message
+                ),
+                None => println!("{} {}: {}", issue.kind, issue.path.display(), issue.message),
+            }
+        }
+        eprintln!(
+            "Checked {} pack(s), {} index(es), {} packed object(s) and {} loose object(s): {} problem(s) found.",
+            report.checked_packs,
+            report.checked_indexes,
+            report.checked_objects,
+            report.checked_loose_objects,
+            report.issues.len(),
+        );
+    }
+
+    if !report.is_ok() {
+        return Err("The repository is corrupt!".into());
+    }
+    Ok(())
+}
+
+pub fn get_app() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .about(
+            "Verifies the integrity of the repository, by checking all pack headers \
+            and indexes, and re-hashing all packed and loose objects.",
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Prints the report in JSON format."),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .short("T")
+                .long("threads")
+                .takes_value(true)
+                .help("Use the specified number of worker threads for decompression and hashing.")
+                .default_value("0"),
+        )
+}
@@ -0,0 +1,39 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use clap::{App, Arg, ArgMatches};
+use std::error::Error;
+
+use super::utils::{format_size, open_repo_from_cwd};
+
+pub const SUBCOMMAND: &str = "gc";
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let dry_run = matches.is_present("dry-run");
+
+    let repo = open_repo_from_cwd()?;
+    let report = repo.gc(dry_run)?;
+
+    println!(
+        "{} {} loose object(s) and {} temporary file(s), reclaiming {}.",
+        if dry_run { "Would remove" } else { "Removed" },
+        report.removed_object_count,
+        report.removed_temp_file_count,
+        format_size(report.reclaimed_bytes),
+    );
+
+    Ok(())
+}
+
+pub fn get_app() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .about(
+            "Removes loose objects which are not referenced by any loose snapshot, \
+            and stale temporary files left behind by interrupted operations.",
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Only report what would be removed, without deleting anything."),
+        )
+}
@@ -3,22 +3,32 @@
 
 pub mod atomicfile;
 pub mod batch;
+pub mod checksum;
 pub mod entrypool;
 pub mod log;
 pub mod packidx;
 pub mod progress;
 pub mod repo;
 
+pub mod bisect;
 pub mod clone;
+pub mod diff;
+pub mod export;
 pub mod extract;
 pub mod find;
+pub mod fsck;
+pub mod gc;
 pub mod list;
 pub mod loosen;
 pub mod pack;
+pub mod reflog;
+pub mod refs;
+pub mod resolve;
 pub mod show;
 pub mod status;
 pub mod store;
 pub mod update;
+pub mod upgrade_index;
 pub mod utils;
 
 use cxx::{CxxString, CxxVector};
@@ -57,6 +67,7 @@ mod bridge {
          * @param elfshaker_repo_dir
          * @param pack
          * @param snapshot
+         * @param opts opts.include and opts.exclude select the files to extract using glob patterns (all files if empty)
          * @return whether the extraction was successful
          */
         fn extract(
@@ -66,6 +77,21 @@ mod bridge {
             opts: ExtractOptions,
         ) -> Result<ExtractResult>;
 
+        /**
+         * @brief Extract all files of a pack:snapshot into a destination directory, without touching HEAD
+         *
+         * @param elfshaker_repo_dir
+         * @param destination_dir the directory to extract into (must be empty unless opts.force is set)
+         * @param snapshot
+         * @return the number of extracted files
+         */
+        fn extract_into(
+            elfshaker_repo_dir: &CxxString,
+            destination_dir: &CxxString,
+            snapshot: &CxxString,
+            opts: ExtractOptions,
+        ) -> Result<ExtractResult>;
+
         /**
          * @brief Create a snapshot in the specified repo given a list of files (paths must be relative to worktree!)
          *
@@ -129,6 +155,8 @@ fn init_elfshaker_store(
         PathBuf::from(worktree_dir.to_string()),
         "init",
         &Vec::<std::path::PathBuf>::new(),
+        None,
+        packidx::Attributes::new(),
     );
     result
 }
@@ -149,6 +177,22 @@ fn extract(
     Ok(result)
 }
 
+fn extract_into(
+    elfshaker_repo_dir: &CxxString,
+    destination_dir: &CxxString,
+    snapshot: &CxxString,
+    opts: bridge::ExtractOptions,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    let _ = *LAZY_LOGGER;
+    let result = extract::do_extract_into(
+        std::path::PathBuf::from(elfshaker_repo_dir.to_string()),
+        std::path::PathBuf::from(destination_dir.to_string()),
+        &snapshot.to_string(),
+        opts,
+    )?;
+    Ok(result)
+}
+
 fn store(
     elfshaker_repo_dir: &CxxString,
     worktree_dir: &CxxString,
@@ -166,6 +210,8 @@ fn store(
         PathBuf::from(worktree_dir.to_string()),
         &snapshot_name.to_str()?,
         &files_to_snapshot_paths,
+        None,
+        packidx::Attributes::new(),
     );
 
     result
@@ -185,10 +231,18 @@ fn pack(
         std::path::PathBuf::from(elfshaker_repo_dir.to_string()),
         std::path::PathBuf::from(worktree_dir.to_string()),
         &pack_name.to_str()?,
-        PACK_COMPRESSION_LEVEL,
-        threads,
-        frames,
         None,
+        repo::PackOptions {
+            compression_level: PACK_COMPRESSION_LEVEL,
+            compression_window_log: pack::DEFAULT_COMPRESSION_WINDOW_LOG,
+            num_workers: threads,
+            num_frames: frames,
+            chunk_objects: false,
+            ordering: repo::ObjectOrdering::Size,
+            train_dictionary: false,
+            append: false,
+            seek_interval: 0,
+        },
     )
 }
 
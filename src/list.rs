@@ -4,7 +4,10 @@
 use clap::{App, Arg, ArgMatches};
 use std::{error::Error, ops::ControlFlow, path::Path};
 
-use super::utils::{format_size, open_repo_from_cwd, print_table};
+use super::utils::{
+    format_size, format_snapshot_attributes, open_repo_from_cwd, print_table, AttributeFilter,
+    SNAPSHOT_ATTRIBUTES_HEADER,
+};
 use crate::repo::{PackId, Repository, SnapshotId};
 
 pub const SUBCOMMAND: &str = "list";
@@ -12,12 +15,18 @@ pub const SUBCOMMAND: &str = "list";
 pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     let snapshot_or_pack = matches.value_of("snapshot_or_pack");
     let bytes = matches.is_present("bytes");
+    let filters = matches
+        .values_of("meta")
+        .into_iter()
+        .flatten()
+        .map(str::parse)
+        .collect::<Result<Vec<AttributeFilter>, _>>()?;
 
     let repo = open_repo_from_cwd()?;
 
     if let Some(snapshot_or_pack) = snapshot_or_pack {
         if let Some(pack_id) = repo.is_pack(snapshot_or_pack)? {
-            print_pack_summary(&repo, pack_id)?;
+            print_pack_summary(&repo, pack_id, &filters, matches.is_present("long"))?;
         } else {
             let snapshot = repo.find_snapshot(snapshot_or_pack)?;
             print_snapshot_summary(&repo, &snapshot, bytes)?;
@@ -46,6 +55,20 @@ pub fn get_app() -> App<'static, 'static> {
                 .long("--bytes")
                 .help("Print the sizes in bytes."),
         )
+        .arg(
+            Arg::with_name("meta")
+                .takes_value(true)
+                .long("meta")
+                .value_name("key[=value]")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only lists the snapshots of the pack which have the attribute (with the value, if given). Can be given multiple times."),
+        )
+        .arg(
+            Arg::with_name("long")
+                .long("long")
+                .help("Also prints the creation time, message and other attributes of the snapshots of the pack."),
+        )
 }
 
 fn print_repo_summary(repo: &Repository, bytes: bool) -> Result<(), Box<dyn Error>> {
@@ -81,20 +104,32 @@ fn print_repo_summary(repo: &Repository, bytes: bool) -> Result<(), Box<dyn Erro
     Ok(())
 }
 
-fn print_pack_summary(repo: &Repository, pack: PackId) -> Result<(), Box<dyn Error>> {
+fn print_pack_summary(
+    repo: &Repository,
+    pack: PackId,
+    filters: &[AttributeFilter],
+    long: bool,
+) -> Result<(), Box<dyn Error>> {
     let mut table = vec![];
 
-    repo.load_index(&pack)?
-        .for_each_snapshot(|snapshot, entries| {
-            let file_count = entries.len();
-            table.push([snapshot.to_owned(), file_count.to_string()]);
-            ControlFlow::<(), ()>::Continue(())
-        })?;
+    let index = repo.load_index(&pack)?;
+    index.for_each_snapshot(|snapshot, entries| {
+        let attributes = index.snapshot_attributes(snapshot).unwrap();
+        if filters.iter().all(|f| f.matches(attributes)) {
+            let mut row = vec![snapshot.to_owned(), entries.len().to_string()];
+            if long {
+                row.extend(format_snapshot_attributes(attributes));
+            }
+            table.push(row);
+        }
+        ControlFlow::<(), ()>::Continue(())
+    })?;
 
-    print_table(
-        Some(&["SNAPSHOT".to_owned(), "FILES".to_owned()]),
-        table.iter(),
-    );
+    let mut header = vec!["SNAPSHOT".to_owned(), "FILES".to_owned()];
+    if long {
+        header.extend(SNAPSHOT_ATTRIBUTES_HEADER.map(str::to_owned));
+    }
+    print_table(Some(header), table.into_iter());
     Ok(())
 }
 
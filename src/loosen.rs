@@ -1,9 +1,9 @@
-use std::error::Error;
+use std::{error::Error, str::FromStr};
 
 use clap::{App, Arg, ArgMatches};
 use log::info;
 
-use crate::packidx::FileEntry;
+use crate::packidx::PackError;
 use crate::repo::{ExtractOptions, PackId};
 
 use crate::utils::open_repo_from_cwd;
@@ -29,39 +29,42 @@ pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
 
     let mut opts = ExtractOptions::default();
     opts.set_verify(is_verify);
-    // opts.set_reset(is_reset);
     opts.set_force(is_force);
     opts.set_num_workers(threads);
 
-    let repo = open_repo_from_cwd()?;
+    let mut repo = open_repo_from_cwd()?;
 
     let pack_id = matches.value_of("pack").unwrap();
-
-    let pack_id = PackId::Pack(pack_id.to_owned());
-    let source_index = repo.load_index(&pack_id)?;
-
-    let mut entries: Vec<FileEntry> = vec![];
-
-    for snapshot in repo.load_index_snapshots(&pack_id)? {
-        let mut _entries = source_index
-            .resolve_snapshot(&snapshot)
-            .expect("failed to resolve snapshot"); // TODO: Temporary.
-        entries.append(&mut source_index.entries_from_handles(_entries.iter()).unwrap());
-    }
-
-    for entry in entries {
-        repo.rewrite_loose_object(entry.path, &entry.checksum)?;
+    let pack_id = PackId::from_str(pack_id)?;
+
+    let snapshots = repo.loosen_pack(&pack_id, &opts)?;
+    eprintln!("Loosened {} snapshot(s)", snapshots.len());
+
+    if matches.is_present("remove") {
+        if let (Some(head), _) = repo.read_head()? {
+            if head.pack() == &pack_id {
+                info!("Updating HEAD to point to the loose snapshot...");
+                let new_head = snapshots
+                    .into_iter()
+                    .find(|snapshot| snapshot.tag() == head.tag())
+                    .ok_or_else(|| PackError::SnapshotNotFound(head.to_string()))?;
+                let sparse_spec = repo.read_head_sparse_spec()?;
+                repo.update_head(&new_head, &sparse_spec, "loosen")?;
+            }
+        }
+        repo.remove_pack(&pack_id)?;
+        eprintln!("Removed pack {}", pack_id);
     }
 
-    let snapshot_count = repo.load_index_snapshots(&pack_id).unwrap().len();
-    eprintln!("Loosened {} snapshot(s)", snapshot_count);
-
     Ok(())
 }
 
 pub fn get_app() -> App<'static, 'static> {
     App::new(SUBCOMMAND)
-        .about("Can be used to loosen a pack.")
+        .about(
+            "Turns the snapshots of a pack back into loose snapshots, which can \
+            then be packed again together with other snapshots.",
+        )
         .arg(
             Arg::with_name("pack")
                 .required(true)
@@ -71,11 +74,11 @@ pub fn get_app() -> App<'static, 'static> {
         .arg(
             Arg::with_name("verify")
                 .long("verify")
-                .help("Enables SHA-1 verification of the extracted files. This has a small performance overhead."),
+                .help("Enables checksum verification of the extracted files. This has a small performance overhead."),
         )
         .arg(Arg::with_name("force")
                 .long("force")
-                .help("Disables certain runtime checks that aim to detect unexpected file modification and prevent data loss."))
+                .help("Replaces existing loose snapshots with the same tag but different contents."))
         .arg(Arg::with_name("threads")
                 .short("T")
                 .long("threads")
@@ -83,4 +86,7 @@ pub fn get_app() -> App<'static, 'static> {
                 .help("Use the specified number of worker threads for decompression. \
                       The number of threads used is proportional to the memory needed for decompression.")
                 .default_value("0"))
+        .arg(Arg::with_name("remove")
+                .long("remove")
+                .help("Removes the pack once its snapshots have been loosened."))
 }
@@ -8,7 +8,7 @@ use std::{error::Error, ops::ControlFlow, path::PathBuf, str::FromStr};
 use super::utils::{create_percentage_print_reporter, open_repo_from_cwd};
 use crate::{
     packidx::PackIndex,
-    repo::{PackId, PackOptions, SnapshotId, REPO_DIR},
+    repo::{ObjectOrdering, PackId, PackOptions, SnapshotId, REPO_DIR},
     utils::open_repo_with_separate_worktree_from,
 };
 
@@ -18,21 +18,27 @@ pub const SUBCOMMAND: &str = "pack";
 /// value of 28 == 256MiB window log. A configurable window log will require the
 /// user to specify the value during extract operations as well as pack
 /// operations.
-const DEFAULT_COMPRESSION_WINDOW_LOG: u32 = 28;
+pub const DEFAULT_COMPRESSION_WINDOW_LOG: u32 = 28;
 
+/// The largest --seek-interval accepted, in MiB. Larger Zstandard frames
+/// cannot be listed in the seek table.
+const MAX_SEEK_INTERVAL_MIB: u64 = 1024;
+
+/// Packs the given loose snapshots (all of them by default) into a new pack.
+/// A `num_workers` or `num_frames` of 0 in `opts` selects the number of
+/// threads or frames automatically.
 pub fn do_pack(
     data_dir_location: PathBuf,
     worktree_path: PathBuf,
     pack: &str,
-    compression_level: i32,
-    threads: u32,
-    frames: u32,
     indexes: Option<Vec<PackId>>,
+    opts: PackOptions,
 ) -> Result<(), Box<dyn Error>> {
     // Parse pack name
     let pack = PackId::from_str(pack)?;
 
     // Parse --compression-level
+    let compression_level = opts.compression_level;
     let compression_level_range = zstd::compression_level_range();
     if !compression_level_range.contains(&compression_level) {
         return Err(format!(
@@ -45,7 +51,7 @@ pub fn do_pack(
     }
 
     // Parse --threads
-    let threads = match threads {
+    let threads = match opts.num_workers {
         0 => {
             let phys_cores = num_cpus::get_physical();
             info!(
@@ -59,23 +65,56 @@ pub fn do_pack(
 
     let mut repo = open_repo_with_separate_worktree_from(&data_dir_location, &worktree_path)?;
 
-    let indexes = indexes
-        .map(Result::Ok)
-        .unwrap_or_else(|| repo.loose_packs())?;
+    let indexes = match indexes {
+        Some(indexes) => indexes,
+        // Loose snapshots are kept after packing, so those which are already
+        // in the pack are skipped.
+        None if opts.append => {
+            let packed = repo.load_index_snapshots(&pack)?;
+            let mut indexes = vec![];
+            for pack_id in repo.loose_packs()? {
+                let snapshots = repo.load_index_snapshots(&pack_id)?;
+                if !snapshots.iter().all(|snapshot| packed.contains(snapshot)) {
+                    indexes.push(pack_id);
+                }
+            }
+            indexes
+        }
+        None => repo.loose_packs()?,
+    };
 
     // No point in creating an empty pack.
     if indexes.is_empty() {
+        if opts.append {
+            return Err(format!("There are no loose snapshots to append to {}!", pack).into());
+        }
         return Err("There are no loose snapshots!".into());
     }
+    // The new pack replaces the existing file, so it cannot be read from.
+    if indexes.contains(&pack) {
+        if opts.append {
+            return Err(format!("Cannot append {} to itself!", pack).into());
+        }
+        return Err(format!("Cannot repack {} into itself!", pack).into());
+    }
 
     let mut new_index = PackIndex::new();
 
-    for pack_id in &indexes {
-        assert!(
-            repo.is_pack_loose(pack_id),
-            "packing non-loose indexes not yet supported"
-        );
+    for (i, pack_id) in indexes.iter().enumerate() {
         let index = repo.load_index(pack_id)?;
+        // The objects of the pack are all identified by the same algorithm.
+        if i == 0 {
+            new_index = PackIndex::with_hash_algorithm(index.hash_algorithm());
+        } else if index.hash_algorithm() != new_index.hash_algorithm() {
+            return Err(format!(
+                "Cannot pack snapshots with different hash algorithms ({} uses {}, {} uses {})!",
+                indexes[0],
+                new_index.hash_algorithm(),
+                pack_id,
+                index.hash_algorithm()
+            )
+            .into());
+        }
         eprintln!("Packing {} {}", pack_id, index.snapshot_tags().len());
         index.for_each_snapshot(|snapshot, entries| {
             if let Err(e) = new_index.push_snapshot(snapshot.to_owned(), entries.clone()) {
@@ -84,10 +123,11 @@ pub fn do_pack(
                 ControlFlow::Continue(())
             }
         })?;
+        new_index.copy_attributes_from(&index);
     }
 
     // Parse --frames
-    let frames = match frames {
+    let frames = match opts.num_frames {
         0 => {
             let loose_size = new_index.object_size_total();
             let frames = get_frame_size_hint(loose_size);
@@ -101,19 +141,17 @@ pub fn do_pack(
     let reporter = create_percentage_print_reporter("Compressing objects", 5);
 
     eprintln!("Compressing objects...");
-    // Create a pack using the ordered "loose" index.
-    repo.create_pack(
-        &pack,
-        new_index,
-        &PackOptions {
-            compression_level,
-            // We don't expose the windowLog option yet.
-            compression_window_log: DEFAULT_COMPRESSION_WINDOW_LOG,
-            num_workers: threads,
-            num_frames: frames,
-        },
-        &reporter,
-    )?;
+    let opts = PackOptions {
+        num_workers: threads,
+        num_frames: frames,
+        ..opts
+    };
+    if opts.append {
+        repo.append_to_pack(&pack, new_index, &indexes, &opts, &reporter)?;
+    } else {
+        // Create a pack using the ordered "loose" index.
+        repo.create_pack(&pack, new_index, &indexes, &opts, &reporter)?;
+    }
 
     if let (Some(head), _) = repo.read_head()? {
         if indexes.iter().any(|pack_id| head.pack() == pack_id) {
@@ -121,7 +159,7 @@ pub fn do_pack(
             // The current HEAD was referencing a snapshot an index which has
             // been packed. Update HEAD to point into the new pack.
             let new_head = SnapshotId::new(pack, head.tag()).unwrap();
-            repo.update_head(&new_head)?;
+            repo.update_head(&new_head, &repo.read_head_sparse_spec()?, "pack")?;
         }
     }
 
@@ -153,17 +191,40 @@ pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     // Parse --frames
     let frames: u32 = matches.value_of("frames").unwrap().parse()?;
 
+    // Parse --seek-interval
+    let seek_interval = match matches.value_of("seek-interval") {
+        Some(value) => {
+            let mib: u64 = value.parse()?;
+            if !(1..=MAX_SEEK_INTERVAL_MIB).contains(&mib) {
+                return Err(format!(
+                    "Invalid seek interval {} (value must be between 1 and {} MiB)!",
+                    mib, MAX_SEEK_INTERVAL_MIB
+                )
+                .into());
+            }
+            mib * 1024 * 1024
+        }
+        None => 0,
+    };
+
     do_pack(
         std::env::current_dir()?.join(REPO_DIR),
         std::env::current_dir()?,
         pack,
-        compression_level,
-        threads,
-        frames,
         indexes,
-    );
-
-    Ok(())
+        PackOptions {
+            compression_level,
+            // We don't expose the windowLog option yet.
+            compression_window_log: DEFAULT_COMPRESSION_WINDOW_LOG,
+            num_workers: threads,
+            num_frames: frames,
+            chunk_objects: matches.is_present("chunk"),
+            ordering: ObjectOrdering::from_str(matches.value_of("order").unwrap())?,
+            train_dictionary: matches.is_present("train-dictionary"),
+            append: matches.is_present("append"),
+            seek_interval,
+        },
+    )
 }
 
 pub fn get_app() -> App<'static, 'static> {
@@ -177,7 +238,7 @@ pub fn get_app() -> App<'static, 'static> {
                 .required(true)
                 .index(1)
                 .value_name("name")
-                .help("Specifies the name of the pack to create."),
+                .help("Specifies the name of the pack to create (or append to, with --append)."),
         )
         .arg(
             Arg::with_name("threads")
@@ -209,11 +270,64 @@ pub fn get_app() -> App<'static, 'static> {
                     auto-detect the appropriate number of frames to emit.")
                 .default_value("0")
         )
+        .arg(
+            Arg::with_name("chunk")
+                .long("chunk")
+                .help(
+                    "Split the objects into content-defined chunks, and store each \
+                    distinct chunk only once. This finds data shared by similar objects \
+                    which end up in different frames, at the cost of a larger index. \
+                    Packs created with this option cannot be read by older versions of elfshaker.")
+        )
+        .arg(
+            Arg::with_name("order")
+                .takes_value(true)
+                .long("order")
+                .possible_values(ObjectOrdering::NAMES)
+                .help(
+                    "The order in which objects are laid out in the frames: by size, \
+                    by the path of the files using them, by a fingerprint of their contents, \
+                    or by the first snapshot using them. Objects which are close to each \
+                    other compress better if they are similar.")
+                .default_value("size")
+        )
+        .arg(
+            Arg::with_name("train-dictionary")
+                .long("train-dictionary")
+                .help(
+                    "Train a Zstandard dictionary on a sample of the objects, and compress \
+                    all frames with it. This improves compression when there are many small \
+                    frames. Packs created with this option cannot be read by older versions \
+                    of elfshaker.")
+        )
+        .arg(
+            Arg::with_name("append")
+                .long("append")
+                .conflicts_with_all(&["chunk", "train-dictionary"])
+                .help(
+                    "Add the snapshots to the existing pack, instead of creating a new one. \
+                    The new objects are compressed into frames at the end of the pack, and \
+                    the existing frames are kept as they are. Packs created with --chunk \
+                    cannot be appended to.")
+        )
+        .arg(
+            Arg::with_name("seek-interval")
+                .takes_value(true)
+                .long("seek-interval")
+                .value_name("MiB")
+                .help(
+                    "Start a new Zstandard frame after every <MiB> of data in each frame, and \
+                    write a seek table listing them at the end of the pack. This lets extract \
+                    and show start decompressing close to an object, rather than at the start \
+                    of its frame, at a small cost in compression. With --append, a seek table \
+                    is added to the pack; the new frames of a pack which already has one are \
+                    split every 4 MiB by default.")
+        )
         .arg(
             Arg::with_name("indexes")
             .index(2)
                 .multiple(true)
-                .help("Specify the indexes of packs to include.")
+                .help("Specify the loose snapshots and packs to include. Defaults to all loose snapshots.")
         )
 }
 
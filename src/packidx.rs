@@ -3,18 +3,17 @@
 
 //! Contains types and function for parsing `.pack.idx` files created by
 //! elfshaker.
+pub use crate::checksum::{HashAlgorithm, ObjectChecksum};
 use crate::entrypool::{EntryPool, Handle};
 use crate::repo::{
     fs::{create_file, open_file},
     partition_by_u64,
 };
 
-use crypto::digest::Digest;
-use crypto::sha1::Sha1;
-use serde::de::{SeqAccess, Visitor};
+use serde::de::{IgnoredAny, SeqAccess, Visitor};
 use serde::{ser::SerializeTuple, Deserialize, Deserializer, Serialize, Serializer};
 use std::borrow::Cow;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::hash::Hash;
@@ -39,6 +38,8 @@ pub enum PackError {
     SerializeError(rmp_serde::encode::Error),
     BadMagic,
     BadPackVersion([u8; 4]),
+    UnknownHashAlgorithm(String),
+    UnknownFeatures(Vec<String>),
 }
 
 impl std::error::Error for PackError {}
@@ -77,6 +78,16 @@ impl std::fmt::Display for PackError {
                 "Pack version is too recent ({:?}), please upgrade elfshaker!",
                 v
             ),
+            PackError::UnknownHashAlgorithm(name) => write!(
+                f,
+                "The pack index uses an unknown hash algorithm ({}), please upgrade elfshaker!",
+                name
+            ),
+            PackError::UnknownFeatures(features) => write!(
+                f,
+                "The pack index uses unknown features ({}), please upgrade elfshaker!",
+                features.join(", ")
+            ),
         }
     }
 }
@@ -99,12 +110,54 @@ impl From<rmp_serde::encode::Error> for PackError {
     }
 }
 
-/// The content checksum of an object.
-pub type ObjectChecksum = [u8; 20];
 /// The offset used for [`ObjectEntry::offset`], when the object is loose (not
 /// in a pack file).
 pub const LOOSE_OBJECT_OFFSET: u64 = std::u64::MAX;
 
+/// The version of the index format, which follows the magic.
+const INDEX_VERSION: [u8; 4] = [0, 0, 0, 1];
+/// The version of indexes which record [`PackIndex::chunks`].
+const CHUNKED_INDEX_VERSION: [u8; 4] = [0, 0, 0, 2];
+/// The version of indexes which start with an [`IndexHeader`]. Indexes which
+/// the older formats can represent are still written in those, unless they
+/// are upgraded with [`PackIndex::upgrade_format`].
+const HEADER_INDEX_VERSION: [u8; 4] = [0, 0, 0, 3];
+
+/// Set in indexes which record [`PackIndex::snapshot_attributes`].
+const FEATURE_SNAPSHOT_ATTRIBUTES: &str = "snapshot-attributes";
+/// Set in indexes which record [`PackIndex::file_attributes`].
+const FEATURE_FILE_ATTRIBUTES: &str = "file-attributes";
+/// The features understood by this version of elfshaker.
+const KNOWN_FEATURES: &[&str] = &[FEATURE_SNAPSHOT_ATTRIBUTES, FEATURE_FILE_ATTRIBUTES];
+
+/// Follows the version of indexes in the [`HEADER_INDEX_VERSION`] format.
+///
+/// New fields are added at the end, and are ignored by older versions of
+/// elfshaker. Additions which older versions must not ignore are listed in
+/// [`IndexHeader::features`] instead.
+#[derive(Serialize)]
+struct IndexHeader {
+    /// The name of the [`HashAlgorithm`] of the checksums.
+    hash_algorithm: String,
+    /// Whether the index records [`PackIndex::chunks`].
+    chunked: bool,
+    /// The optional parts of the format used by the index. Indexes with
+    /// features which are not in [`KNOWN_FEATURES`] are rejected.
+    features: Vec<String>,
+    /// The version of the index format, which precedes the header.
+    #[serde(skip)]
+    version: [u8; 4],
+}
+
+/// Extensible key-value metadata of a snapshot or a file.
+pub type Attributes = BTreeMap<String, String>;
+
+/// The snapshot attribute holding the message given to `store --message`.
+pub const MESSAGE_ATTRIBUTE: &str = "message";
+/// The snapshot attribute holding the time the snapshot was created, in RFC
+/// 3339 format.
+pub const CREATED_ATTRIBUTE: &str = "created";
+
 /// A [`FileHandle`] identifies a file stored in a pack. It contains two
 /// handles: a path, which can be used to get the path of the file
 /// from the index path_pool, and an object, which can be used to get
@@ -273,7 +326,7 @@ impl FileEntry {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
 pub struct FileMetadata {
     pub last_modified: i64,
     pub last_modified_nanos: u32,
@@ -288,6 +341,19 @@ pub struct ObjectMetadata {
     pub size: u64,
 }
 
+/// A contiguous piece of the data stored in a chunked pack.
+///
+/// In a chunked pack, the objects are split into content-defined chunks, and
+/// each distinct chunk is only stored once. [`ObjectMetadata::offset`] then
+/// refers to a position in the stream of all objects laid out one after
+/// another, which is mapped onto the stored data by [`PackIndex::chunks`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Chunk {
+    /// The offset of the chunk in the (decompressed) stored data.
+    pub offset: u64,
+    pub size: u64,
+}
+
 /// Contains the metadata needed to extract files from a pack file.
 pub struct PackIndex {
     snapshot_tags: Vec<String>,
@@ -297,6 +363,18 @@ pub struct PackIndex {
     object_pool: EntryPool<ObjectChecksum>,
     object_metadata: BTreeMap<Handle, ObjectMetadata>,
     file_metadata: BTreeMap<OsString, FileMetadata>,
+    chunks: Vec<Chunk>,
+    /// The algorithm used to compute the checksums of the objects. Indexes
+    /// created by older versions of elfshaker use SHA-1.
+    hash_algorithm: HashAlgorithm,
+    /// The attributes of each snapshot, in the order of `snapshot_tags`.
+    snapshot_attributes: Vec<Attributes>,
+    /// The attributes of the files, by path. Files without attributes are
+    /// not listed.
+    file_attributes: BTreeMap<OsString, Attributes>,
+    /// Set to write the index with an [`IndexHeader`], even if the older
+    /// formats can represent it.
+    upgraded: bool,
 
     // When snapshots are pushed, maintain the current state of the filesystem.
     // Not stored on disk.
@@ -311,7 +389,15 @@ impl Default for PackIndex {
 }
 
 impl PackIndex {
+    /// Creates an empty index, for objects with checksums computed by the
+    /// default [`HashAlgorithm`].
     pub fn new() -> Self {
+        Self::with_hash_algorithm(HashAlgorithm::default())
+    }
+
+    /// Creates an empty index, for objects with checksums computed by the
+    /// given algorithm.
+    pub fn with_hash_algorithm(hash_algorithm: HashAlgorithm) -> Self {
         Self {
             snapshot_tags: Vec::new(),
             snapshot_deltas: Vec::new(),
@@ -320,11 +406,110 @@ impl PackIndex {
             object_pool: EntryPool::new(),
             object_metadata: BTreeMap::new(),
             file_metadata: BTreeMap::new(),
+            chunks: Vec::new(),
+            hash_algorithm,
+            snapshot_attributes: Vec::new(),
+            file_attributes: BTreeMap::new(),
+            upgraded: false,
 
             current: HashSet::new(),
         }
     }
 
+    /// The algorithm used to compute the checksums of the objects.
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    /// The version of the format the index is written in, see
+    /// [`PackIndex::write`].
+    pub fn format_version(&self) -> u32 {
+        let version = if self.upgraded
+            || self.hash_algorithm != HashAlgorithm::Sha1
+            || !self.features().is_empty()
+        {
+            HEADER_INDEX_VERSION
+        } else if self.chunks.is_empty() {
+            INDEX_VERSION
+        } else {
+            CHUNKED_INDEX_VERSION
+        };
+        u32::from_be_bytes(version)
+    }
+
+    /// Makes [`PackIndex::write`] use the latest format, which older versions
+    /// of elfshaker may not be able to read.
+    pub fn upgrade_format(&mut self) {
+        self.upgraded = true;
+    }
+
+    /// The features of the format used by the index.
+    fn features(&self) -> Vec<String> {
+        let mut features = vec![];
+        if self.snapshot_attributes.iter().any(|a| !a.is_empty()) {
+            features.push(FEATURE_SNAPSHOT_ATTRIBUTES.to_owned());
+        }
+        if !self.file_attributes.is_empty() {
+            features.push(FEATURE_FILE_ATTRIBUTES.to_owned());
+        }
+        features
+    }
+
+    fn has_header(&self) -> bool {
+        self.format_version() == u32::from_be_bytes(HEADER_INDEX_VERSION)
+    }
+
+    /// The attributes of the snapshot, or [`None`] if there is no such
+    /// snapshot.
+    pub fn snapshot_attributes(&self, tag: &str) -> Option<&Attributes> {
+        let index = self.snapshot_tags.iter().position(|t| t == tag)?;
+        Some(&self.snapshot_attributes[index])
+    }
+    pub fn set_snapshot_attributes(
+        &mut self,
+        tag: &str,
+        attributes: Attributes,
+    ) -> Result<(), PackError> {
+        let index = self
+            .snapshot_tags
+            .iter()
+            .position(|t| t == tag)
+            .ok_or_else(|| PackError::SnapshotNotFound(tag.to_owned()))?;
+        self.snapshot_attributes[index] = attributes;
+        Ok(())
+    }
+
+    /// The attributes of the file at `path`, which are shared by all
+    /// snapshots, like its [`FileMetadata`].
+    pub fn file_attributes(&self, path: &OsStr) -> Option<&Attributes> {
+        self.file_attributes.get(path)
+    }
+    pub fn set_file_attributes(&mut self, path: OsString, attributes: Attributes) {
+        if attributes.is_empty() {
+            self.file_attributes.remove(&path);
+        } else {
+            self.file_attributes.insert(path, attributes);
+        }
+    }
+
+    /// Copies the attributes of the snapshots and files of `other` which are
+    /// also in this index. Files keep their attributes, if they have any.
+    pub fn copy_attributes_from(&mut self, other: &PackIndex) {
+        for (tag, attributes) in other.snapshot_tags.iter().zip(&other.snapshot_attributes) {
+            if !attributes.is_empty() {
+                // Snapshots which are not in this index are skipped.
+                let _ = self.set_snapshot_attributes(tag, attributes.clone());
+            }
+        }
+        for (path, attributes) in &other.file_attributes {
+            if self.path_pool.get(path).is_some() {
+                self.file_attributes
+                    .entry(path.clone())
+                    .or_insert_with(|| attributes.clone());
+            }
+        }
+    }
+
     pub fn object_size_total(&self) -> u64 {
         self.object_metadata.values().map(|x| x.size).sum()
     }
@@ -344,32 +529,62 @@ impl PackIndex {
             self.object_metadata.get(handle).unwrap().size
         })
     }
-    pub fn compute_object_offsets_and_ordering(mut self) -> (Self, Vec<Handle>) {
-        let mut size_handle = self
-            .object_metadata
-            .iter()
-            .map(|(handle, metadata)| (metadata.size, *handle))
-            .collect::<Vec<_>>();
-
+    pub fn compute_object_offsets_and_ordering(self) -> (Self, Vec<Handle>) {
         // Heuristic for good compression: Sort objects by size. This happens to
-        // put similar objects next to each other. Can use a faster unstable
-        // sort because 'ties' in size are broken according to the order objects
-        // were added to the object pool.
-        size_handle.sort_unstable();
-
-        // Update object metadata to reference new offsets.
-        let mut offset = 0;
-        for (size, handle) in &size_handle {
-            self.object_metadata.get_mut(handle).unwrap().offset = offset;
-            offset += size;
+        // put similar objects next to each other.
+        self.compute_object_offsets_and_ordering_by(|_, metadata| metadata.size)
+    }
+    /// Lays out the objects one after another, sorted by the key returned by
+    /// `key`, and updates their offsets. Ties are broken according to the
+    /// order objects were added to the object pool.
+    pub fn compute_object_offsets_and_ordering_by<K, F>(mut self, mut key: F) -> (Self, Vec<Handle>)
+    where
+        K: Ord,
+        F: FnMut(Handle, &ObjectMetadata) -> K,
+    {
+        let mut handles = self.object_handles();
+        handles.sort_by_cached_key(|handle| key(*handle, &self.object_metadata[handle]));
+        self.set_object_offsets(&handles, 0);
+        (self, handles)
+    }
+    /// Lays out the objects one after another, in the given order, starting
+    /// at `offset`, and updates their offsets.
+    pub fn set_object_offsets(&mut self, handles: &[Handle], mut offset: u64) {
+        for handle in handles {
+            let metadata = self.object_metadata.get_mut(handle).unwrap();
+            metadata.offset = offset;
+            offset += metadata.size;
         }
+    }
+    /// The handles of the objects, in the order they were added to the object pool.
+    pub fn object_handles(&self) -> Vec<Handle> {
+        self.object_metadata.keys().copied().collect()
+    }
+    /// Returns, for each object, the index of the first snapshot containing
+    /// it, and the (smallest) path it is stored at in that snapshot.
+    pub fn object_first_occurrences(&self) -> HashMap<Handle, (usize, OsString)> {
+        let mut occurrences = HashMap::<Handle, (usize, OsString)>::new();
+        for (index, delta) in self.snapshot_deltas.iter().enumerate() {
+            for file in delta.added() {
+                let path = self.path_pool.lookup(file.path).unwrap();
+                let occurrence = occurrences
+                    .entry(file.object)
+                    .or_insert_with(|| (index, path.clone()));
+                if occurrence.0 == index && path < &occurrence.1 {
+                    occurrence.1 = path.clone();
+                }
+            }
+        }
+        occurrences
+    }
 
-        let handles = size_handle
-            .into_iter()
-            .map(|(_size, handle)| handle)
-            .collect();
-
-        (self, handles)
+    /// The chunks making up the objects of a chunked pack, in the order of
+    /// the objects. Empty if the objects are stored whole.
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+    pub fn set_chunks(&mut self, chunks: Vec<Chunk>) {
+        self.chunks = chunks;
     }
 
     pub fn handle_to_checksum(&self, h: Handle) -> &ObjectChecksum {
@@ -450,6 +665,7 @@ impl PackIndex {
         self.current = files;
         self.snapshot_tags.push(tag);
         self.snapshot_deltas.push(delta);
+        self.snapshot_attributes.push(Attributes::new());
         Ok(())
     }
     // Call the closure F with materialized file entries for each snapshot.
@@ -474,14 +690,14 @@ impl PackIndex {
         // Map FileHandle to FileEntry, which contains path and checksum
         let mut entries = self.entries_from_handles(handles.iter()).ok()?;
         entries.sort_by(|a, b| a.checksum.cmp(&b.checksum).then(a.path.cmp(&b.path)));
-        let mut hasher = entries.into_iter().fold(Sha1::new(), |mut hasher, entry| {
-            hasher.input(&os_str_as_bytes(&entry.path));
-            hasher.input(&entry.checksum);
-            hasher
-        });
-        let mut checksum: ObjectChecksum = [0; 20];
-        hasher.result(&mut checksum);
-        Some(checksum)
+        let hasher = entries
+            .into_iter()
+            .fold(self.hash_algorithm.hasher(), |mut hasher, entry| {
+                hasher.update(&os_str_as_bytes(&entry.path));
+                hasher.update(&entry.checksum);
+                hasher
+            });
+        Some(hasher.finalize())
     }
 }
 
@@ -503,30 +719,106 @@ impl PackIndex {
 
     pub fn parse<R: Read>(rd: R) -> Result<PackIndex, PackError> {
         let mut rd = BufReader::new(rd);
-        Self::read_magic(&mut rd)?;
+        let header = Self::read_header(&mut rd)?;
 
-        Ok(rmp_serde::decode::from_read(rd)?)
+        let mut index: PackIndex = rmp_serde::decode::from_read(rd)?;
+        index.hash_algorithm = header.hash_algorithm()?;
+        index.upgraded = header.version == HEADER_INDEX_VERSION;
+        Ok(index)
     }
 
     pub fn load_only_snapshots<P: AsRef<Path>>(p: P) -> Result<Vec<String>, PackError> {
         let rd = open_file(p.as_ref())?;
         let mut rd = BufReader::new(rd);
-        Self::read_magic(&mut rd)?;
+        Self::read_header(&mut rd)?;
         let mut d = rmp_serde::Deserializer::new(rd);
         Ok(PackIndex::deserialize_only_snapshots(&mut d)?.snapshot_tags)
     }
 
+    /// Loads only the tags and [`PackIndex::snapshot_attributes`] of the
+    /// snapshots in the index, in order.
+    pub fn load_only_snapshot_attributes<P: AsRef<Path>>(
+        p: P,
+    ) -> Result<Vec<(String, Attributes)>, PackError> {
+        Self::parse_only_snapshot_attributes(open_file(p.as_ref())?)
+    }
+
+    fn parse_only_snapshot_attributes<R: Read>(
+        rd: R,
+    ) -> Result<Vec<(String, Attributes)>, PackError> {
+        let mut rd = BufReader::new(rd);
+        let header = Self::read_header(&mut rd)?;
+        let load_mode = if header
+            .features
+            .iter()
+            .any(|f| f == FEATURE_SNAPSHOT_ATTRIBUTES)
+        {
+            LoadMode::OnlySnapshotAttributes
+        } else {
+            LoadMode::OnlySnapshots
+        };
+        let mut d = rmp_serde::Deserializer::new(rd);
+        let mut index = d.deserialize_seq(VisitPackIndex { load_mode })?;
+        index
+            .snapshot_attributes
+            .resize_with(index.snapshot_tags.len(), Attributes::new);
+        Ok(index
+            .snapshot_tags
+            .into_iter()
+            .zip(index.snapshot_attributes)
+            .collect())
+    }
+
+    /// Loads only the [`PackIndex::chunks`] of the index, which avoids
+    /// parsing the whole index when the pack is not chunked.
+    pub fn load_only_chunks<P: AsRef<Path>>(p: P) -> Result<Vec<Chunk>, PackError> {
+        let rd = open_file(p.as_ref())?;
+        let mut rd = BufReader::new(rd);
+        if !Self::read_header(&mut rd)?.chunked {
+            return Ok(vec![]);
+        }
+        let index: PackIndex = rmp_serde::decode::from_read(rd)?;
+        Ok(index.chunks)
+    }
+
+    /// Loads only the [`PackIndex::hash_algorithm`] of the index.
+    pub fn load_only_hash_algorithm<P: AsRef<Path>>(p: P) -> Result<HashAlgorithm, PackError> {
+        let rd = open_file(p.as_ref())?;
+        Self::read_header(&mut BufReader::new(rd))?.hash_algorithm()
+    }
+
     pub fn save<P: AsRef<Path>>(&self, p: P) -> Result<(), PackError> {
         // TODO: Use AtomicCreateFile.
         let wr = create_file(p.as_ref())?;
-        let mut wr = BufWriter::new(wr);
-        Self::write_magic(&mut wr)?;
+        self.write(BufWriter::new(wr))
+    }
+
+    /// Writes the index in the format read by [`PackIndex::parse`].
+    pub fn write<W: Write>(&self, mut wr: W) -> Result<(), PackError> {
+        // Older versions of elfshaker would silently ignore the chunks, and
+        // misread other checksums, so those indexes are marked with a newer
+        // version. Indexes which older versions can read are still written
+        // in the older formats.
+        let version = self.format_version().to_be_bytes();
+        Self::write_magic(&mut wr, version)?;
+        if version == HEADER_INDEX_VERSION {
+            let header = IndexHeader {
+                hash_algorithm: self.hash_algorithm.name().to_owned(),
+                chunked: !self.chunks.is_empty(),
+                features: self.features(),
+                version,
+            };
+            rmp_serde::encode::write(&mut wr, &header)?;
+        }
 
         rmp_serde::encode::write(&mut wr, self)?;
+        wr.flush()?;
         Ok(())
     }
 
-    fn read_magic(rd: &mut impl Read) -> Result<(), PackError> {
+    /// Reads the magic and the version of the index format, followed by the
+    /// [`IndexHeader`] if there is one.
+    fn read_header(rd: &mut impl Read) -> Result<IndexHeader, PackError> {
         let mut magic = [0; 4];
         rd.read_exact(&mut magic)?;
         if magic.ne(b"ELFS") {
@@ -534,19 +826,82 @@ impl PackIndex {
         }
         let mut version = [0; 4];
         rd.read_exact(&mut version)?;
-        if version.gt(&[0, 0, 0, 1]) {
+        if version.gt(&HEADER_INDEX_VERSION) {
             return Err(PackError::BadPackVersion(version));
         }
-        Ok(())
+        if version.ne(&HEADER_INDEX_VERSION) {
+            return Ok(IndexHeader {
+                hash_algorithm: HashAlgorithm::Sha1.name().to_owned(),
+                chunked: version.eq(&CHUNKED_INDEX_VERSION),
+                features: vec![],
+                version,
+            });
+        }
+
+        let header: IndexHeader = rmp_serde::decode::from_read(rd)?;
+        let unknown_features = header
+            .features
+            .iter()
+            .filter(|f| !KNOWN_FEATURES.contains(&f.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !unknown_features.is_empty() {
+            return Err(PackError::UnknownFeatures(unknown_features));
+        }
+        Ok(header)
     }
 
-    fn write_magic(wr: &mut impl Write) -> std::io::Result<()> {
+    fn write_magic(wr: &mut impl Write, version: [u8; 4]) -> std::io::Result<()> {
         wr.write_all(b"ELFS")?;
-        wr.write_all(&[0, 0, 0, 1])?;
+        wr.write_all(&version)?;
         Ok(())
     }
 }
 
+impl IndexHeader {
+    fn hash_algorithm(&self) -> Result<HashAlgorithm, PackError> {
+        self.hash_algorithm
+            .parse()
+            .map_err(|_| PackError::UnknownHashAlgorithm(self.hash_algorithm.clone()))
+    }
+}
+
+struct VisitIndexHeader;
+
+impl<'de> Visitor<'de> for VisitIndexHeader {
+    type Value = IndexHeader;
+
+    fn visit_seq<V>(self, mut seq: V) -> Result<IndexHeader, V::Error>
+    where
+        V: SeqAccess<'de>,
+    {
+        let hash_algorithm = next_expecting(&mut seq)?;
+        let chunked = next_expecting(&mut seq)?;
+        let features = seq.next_element()?.unwrap_or_default();
+        // Skip the fields added by newer versions, so that the index which
+        // follows can be read.
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(IndexHeader {
+            hash_algorithm,
+            chunked,
+            features,
+            version: HEADER_INDEX_VERSION,
+        })
+    }
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("IndexHeader")
+    }
+}
+
+impl<'de> Deserialize<'de> for IndexHeader {
+    fn deserialize<D>(deserializer: D) -> Result<IndexHeader, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(VisitIndexHeader)
+    }
+}
+
 struct VisitPackIndex {
     load_mode: LoadMode,
 }
@@ -555,6 +910,7 @@ struct VisitPackIndex {
 enum LoadMode {
     Full,
     OnlySnapshots,
+    OnlySnapshotAttributes,
 }
 
 fn next_expecting<'de, T, V, E>(seq: &mut V) -> Result<T, E>
@@ -576,8 +932,17 @@ impl<'de> Visitor<'de> for VisitPackIndex {
     {
         let mut result = PackIndex::new();
         result.snapshot_tags = next_expecting(&mut seq)?;
-        if self.load_mode == LoadMode::OnlySnapshots {
-            return Ok(result);
+        match self.load_mode {
+            LoadMode::Full => {}
+            LoadMode::OnlySnapshots => return Ok(result),
+            LoadMode::OnlySnapshotAttributes => {
+                // Skip everything up to the attributes, which follow the chunks.
+                for _ in 0..6 {
+                    next_expecting::<IgnoredAny, _, _>(&mut seq)?;
+                }
+                result.snapshot_attributes = next_expecting(&mut seq)?;
+                return Ok(result);
+            }
         }
         result.snapshot_deltas = next_expecting(&mut seq)?;
         result.path_pool = next_expecting(&mut seq)?;
@@ -590,6 +955,16 @@ impl<'de> Visitor<'de> for VisitPackIndex {
             .collect();
 
         result.file_metadata = next_expecting(&mut seq)?;
+        // Only present in chunked indexes, and in indexes with a header.
+        if let Some(chunks) = seq.next_element()? {
+            result.chunks = chunks;
+        }
+        // Only present in indexes with a header.
+        result.snapshot_attributes = seq.next_element()?.unwrap_or_default();
+        result
+            .snapshot_attributes
+            .resize_with(result.snapshot_tags.len(), Attributes::new);
+        result.file_attributes = seq.next_element()?.unwrap_or_default();
 
         Ok(result)
     }
@@ -625,7 +1000,14 @@ impl Serialize for PackIndex {
     where
         S: Serializer,
     {
-        let mut s = serializer.serialize_tuple(6)?;
+        let len = if self.has_header() {
+            9
+        } else if self.chunks.is_empty() {
+            6
+        } else {
+            7
+        };
+        let mut s = serializer.serialize_tuple(len)?;
         s.serialize_element(&self.snapshot_tags)?;
         s.serialize_element(&self.snapshot_deltas)?;
         s.serialize_element(&self.path_pool)?;
@@ -639,6 +1021,154 @@ impl Serialize for PackIndex {
                 .collect::<Vec<ObjectMetadata>>(),
         )?;
         s.serialize_element(&self.file_metadata)?;
+        if len > 6 {
+            s.serialize_element(&self.chunks)?;
+        }
+        if len > 7 {
+            s.serialize_element(&self.snapshot_attributes)?;
+            s.serialize_element(&self.file_attributes)?;
+        }
         s.end()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_entry(path: &str, checksum: u8, size: u64) -> FileEntry {
+        FileEntry::new(
+            path.into(),
+            ObjectChecksum::from([checksum; 20]),
+            ObjectMetadata { offset: 0, size },
+            FileMetadata::default(),
+        )
+    }
+
+    fn make_index() -> PackIndex {
+        let mut index = PackIndex::new();
+        index
+            .push_snapshot(
+                "s1".into(),
+                vec![make_entry("b", 1, 30), make_entry("a", 2, 10)],
+            )
+            .unwrap();
+        index
+            .push_snapshot(
+                "s2".into(),
+                vec![make_entry("b", 3, 20), make_entry("a", 2, 10)],
+            )
+            .unwrap();
+        index
+    }
+
+    fn offsets(index: &PackIndex) -> Vec<u64> {
+        (1..=3)
+            .map(|checksum| {
+                index
+                    .object_metadata(&ObjectChecksum::from([checksum; 20]))
+                    .offset
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compute_object_offsets_and_ordering_works() {
+        let (index, ordering) = make_index().compute_object_offsets_and_ordering();
+        assert_eq!(3, ordering.len());
+        // Sorted by size: 2, 3, 1
+        assert_eq!(vec![30, 0, 10], offsets(&index));
+    }
+
+    #[test]
+    fn compute_object_offsets_and_ordering_by_path_works() {
+        let index = make_index();
+        let occurrences = index.object_first_occurrences();
+        assert_eq!((0, OsString::from("a")), occurrences[&1]);
+        assert_eq!((1, OsString::from("b")), occurrences[&2]);
+
+        let (index, _) = index.compute_object_offsets_and_ordering_by(|handle, _| {
+            let (snapshot, path) = &occurrences[&handle];
+            (path.clone(), *snapshot)
+        });
+        // Sorted by path, then snapshot: 2 (a), 1 (b, s1), 3 (b, s2)
+        assert_eq!(vec![10, 0, 40], offsets(&index));
+    }
+
+    fn write_to_vec(index: &PackIndex) -> Vec<u8> {
+        let mut buf = vec![];
+        index.write(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn index_formats_roundtrip() {
+        let mut index = PackIndex::with_hash_algorithm(HashAlgorithm::Sha1);
+        index
+            .push_snapshot("s1".into(), vec![make_entry("a", 1, 10)])
+            .unwrap();
+        // Indexes which older versions can read are written in the old format.
+        assert_eq!(1, index.format_version());
+        let buf = write_to_vec(&index);
+        assert_eq!(b"ELFS\0\0\0\x01", &buf[..8]);
+        assert_eq!(
+            vec![("s1".to_owned(), Attributes::new())],
+            PackIndex::parse_only_snapshot_attributes(&buf[..]).unwrap()
+        );
+
+        index.upgrade_format();
+        let buf = write_to_vec(&index);
+        assert_eq!(b"ELFS\0\0\0\x03", &buf[..8]);
+        let mut index = PackIndex::parse(&buf[..]).unwrap();
+        assert_eq!(3, index.format_version());
+        assert_eq!(HashAlgorithm::Sha1, index.hash_algorithm());
+        assert_eq!(Some(&Attributes::new()), index.snapshot_attributes("s1"));
+
+        let attributes: Attributes = [("message".to_owned(), "Hello".to_owned())].into();
+        index
+            .set_snapshot_attributes("s1", attributes.clone())
+            .unwrap();
+        index.set_file_attributes("a".into(), attributes.clone());
+        assert!(index
+            .set_snapshot_attributes("s2", Attributes::new())
+            .is_err());
+        let index = PackIndex::parse(&write_to_vec(&index)[..]).unwrap();
+        assert_eq!(Some(&attributes), index.snapshot_attributes("s1"));
+        assert_eq!(Some(&attributes), index.file_attributes("a".as_ref()));
+        assert_eq!(None, index.file_attributes("b".as_ref()));
+        assert_eq!(
+            vec![("s1".to_owned(), attributes.clone())],
+            PackIndex::parse_only_snapshot_attributes(&write_to_vec(&index)[..]).unwrap()
+        );
+        assert_eq!(
+            1,
+            index
+                .entries_from_handles(index.resolve_snapshot("s1").unwrap().iter())
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn index_headers_are_extensible() {
+        let index = write_to_vec(&PackIndex::with_hash_algorithm(HashAlgorithm::Sha1));
+        let with_header = |header: &[u8]| {
+            let mut buf = b"ELFS\0\0\0\x03".to_vec();
+            buf.extend(header);
+            buf.extend(&index[8..]);
+            PackIndex::parse(&buf[..])
+        };
+
+        // Fields added by newer versions are skipped.
+        let header = ("blake3", false, Vec::<String>::new(), "new field");
+        let index = with_header(&rmp_serde::to_vec(&header).unwrap()).unwrap();
+        assert_eq!(HashAlgorithm::Blake3, index.hash_algorithm());
+
+        // Features added by newer versions are not.
+        let header = ("blake3", false, vec!["new-feature"]);
+        assert!(matches!(
+            with_header(&rmp_serde::to_vec(&header).unwrap()),
+            Err(PackError::UnknownFeatures(features)) if features == ["new-feature"]
+        ));
+    }
+}
@@ -0,0 +1,53 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use clap::{App, ArgMatches};
+use std::error::Error;
+
+use super::utils::{open_repo_from_cwd, print_table};
+
+pub const SUBCOMMAND: &str = "reflog";
+
+pub fn run(_matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let repo = open_repo_from_cwd()?;
+    let reflog = repo.reflog()?;
+
+    let mut table = vec![];
+    for (n, entry) in reflog.iter().rev().enumerate() {
+        table.push([
+            format!("@{{{}}}", n),
+            entry.new.to_string(),
+            entry.time.clone(),
+            entry.operation.clone(),
+        ]);
+    }
+    // The snapshot HEAD pointed to before the first recorded change.
+    if let Some(old) = reflog.first().and_then(|entry| entry.old.as_ref()) {
+        table.push([
+            format!("@{{{}}}", reflog.len()),
+            old.to_string(),
+            "-".to_owned(),
+            "-".to_owned(),
+        ]);
+    }
+
+    print_table(
+        Some([
+            "ENTRY".to_owned(),
+            "SNAPSHOT".to_owned(),
+            "TIME".to_owned(),
+            "OPERATION".to_owned(),
+        ]),
+        table.into_iter(),
+    );
+    Ok(())
+}
+
+pub fn get_app() -> App<'static, 'static> {
+    App::new(SUBCOMMAND).about(
+        "Prints the snapshots HEAD pointed to, most recent first, along with the time \
+        and the operation (store, extract, pack or loosen) which moved HEAD. The \
+        snapshots can be referred to as @{<n>} by the other commands, e.g. \
+        `extract @{1}` returns to the previous snapshot.",
+    )
+}
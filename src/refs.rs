@@ -0,0 +1,80 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::error::Error;
+
+use super::utils::{open_repo_from_cwd, print_table};
+use crate::packidx::PackError;
+
+pub const SUBCOMMAND: &str = "ref";
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let repo = open_repo_from_cwd()?;
+
+    match matches.subcommand() {
+        ("set", Some(matches)) => {
+            let name = matches.value_of("name").unwrap();
+            let snapshot = repo.find_snapshot(matches.value_of("snapshot").unwrap())?;
+            // Unlike the tag, the pack of the snapshot is not checked by find_snapshot.
+            if !repo
+                .load_index_snapshots(snapshot.pack())?
+                .iter()
+                .any(|tag| tag == snapshot.tag())
+            {
+                return Err(PackError::SnapshotNotFound(snapshot.to_string()).into());
+            }
+            repo.set_ref(name, &snapshot)?;
+        }
+        ("delete", Some(matches)) => {
+            for name in matches.values_of("names").unwrap() {
+                repo.delete_ref(name)?;
+            }
+        }
+        _ => {
+            let table = repo
+                .refs()?
+                .into_iter()
+                .map(|(name, snapshot)| [name, snapshot.to_string()]);
+            print_table(Some(["REF".to_owned(), "SNAPSHOT".to_owned()]), table);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn get_app() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .about(
+            "Manages refs: named pointers to snapshots, which can be used in place of \
+            the snapshot by the other commands. Lists the refs by default.",
+        )
+        .subcommand(
+            SubCommand::with_name("set")
+                .about("Points the ref to the snapshot, creating the ref if needed.")
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .index(1)
+                        .help("The name of the ref (e.g. known-good or nightly/x86_64)."),
+                )
+                .arg(
+                    Arg::with_name("snapshot")
+                        .required(true)
+                        .index(2)
+                        .help("The snapshot, as [<pack>:]<snapshot>, another ref or HEAD."),
+                ),
+        )
+        .subcommand(SubCommand::with_name("list").about("Lists the refs."))
+        .subcommand(
+            SubCommand::with_name("delete")
+                .about("Deletes the refs.")
+                .arg(
+                    Arg::with_name("names")
+                        .required(true)
+                        .index(1)
+                        .multiple(true)
+                        .help("The names of the refs to delete."),
+                ),
+        )
+}
@@ -0,0 +1,317 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+//! Binary search for the first bad snapshot, between a good and a bad one,
+//! in the order of [`Repository::snapshot_order`].
+use std::{collections::BTreeSet, fs, io, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::constants::{BISECT_FILE, TEMP_DIR};
+use super::error::Error;
+use super::fs::{ensure_dir, write_file_atomic};
+use super::navigation::position_in_order;
+use super::pack::SnapshotId;
+use super::repository::{ExtractOptions, Repository};
+
+/// The verdict on a snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BisectMark {
+    Good,
+    Bad,
+    /// The snapshot cannot be tested.
+    Skip,
+}
+
+/// What is left to do after a bisect step.
+#[derive(Debug, PartialEq)]
+pub enum BisectStep {
+    /// The snapshot was extracted, and should be tested next. There are
+    /// `remaining` snapshots left to test, including it.
+    Test {
+        snapshot: SnapshotId,
+        remaining: usize,
+    },
+    /// All the snapshots between the good and the bad ones were tested.
+    FirstBad(SnapshotId),
+    /// The first bad snapshot is one of these, but they were skipped.
+    Ambiguous(Vec<SnapshotId>),
+}
+
+/// The state of a bisection, persisted in `elfshaker_data/BISECT`.
+#[derive(Debug, Serialize, Deserialize)]
+struct BisectState {
+    /// The snapshots in order, from the good one to the bad one.
+    snapshots: Vec<String>,
+    /// The index of the latest known good snapshot.
+    good: usize,
+    /// The index of the earliest known bad snapshot.
+    bad: usize,
+    /// The indexes of the snapshots which cannot be tested.
+    skipped: BTreeSet<usize>,
+    /// The index of the snapshot being tested.
+    current: Option<usize>,
+    /// HEAD before the bisection started, restored by `bisect reset`.
+    original_head: Option<String>,
+    /// Whether files modified since the last extraction are overwritten.
+    force: bool,
+}
+
+impl BisectState {
+    fn snapshot(&self, index: usize) -> Result<SnapshotId, Error> {
+        Ok(SnapshotId::from_str(&self.snapshots[index])?)
+    }
+
+    /// The snapshots which might be the first bad one, besides `bad`.
+    fn untested(&self) -> Vec<usize> {
+        (self.good + 1..self.bad)
+            .filter(|i| !self.skipped.contains(i))
+            .collect()
+    }
+
+    fn next_step(&self) -> Result<BisectStep, Error> {
+        let untested = self.untested();
+        if untested.is_empty() {
+            // Only the skipped snapshots are left between good and bad.
+            return if self.good + 1 == self.bad {
+                Ok(BisectStep::FirstBad(self.snapshot(self.bad)?))
+            } else {
+                (self.good + 1..=self.bad)
+                    .map(|i| self.snapshot(i))
+                    .collect::<Result<_, _>>()
+                    .map(BisectStep::Ambiguous)
+            };
+        }
+        // The untested snapshot closest to the middle of the range.
+        let middle = self.good + (self.bad - self.good) / 2;
+        let next = *untested
+            .iter()
+            .min_by_key(|&&i| (i as isize - middle as isize).abs())
+            .unwrap();
+        Ok(BisectStep::Test {
+            snapshot: self.snapshot(next)?,
+            remaining: untested.len(),
+        })
+    }
+}
+
+impl Repository {
+    /// Starts a bisection between the `good` and the `bad` snapshot, which
+    /// must come after it, and extracts the first snapshot to test.
+    pub fn bisect_start(
+        &mut self,
+        good: &SnapshotId,
+        bad: &SnapshotId,
+        force: bool,
+    ) -> Result<BisectStep, Error> {
+        if self.load_bisect_state()?.is_some() {
+            return Err(Error::InvalidBisectStep(
+                "A bisection is already in progress, use bisect reset to end it".to_owned(),
+            ));
+        }
+        let order = self.snapshot_order()?;
+        let (good, bad) = (
+            position_in_order(&order, good)?,
+            position_in_order(&order, bad)?,
+        );
+        if good >= bad {
+            return Err(Error::InvalidBisectStep(
+                "The good snapshot must come before the bad one".to_owned(),
+            ));
+        }
+
+        let mut state = BisectState {
+            snapshots: order[good..=bad].iter().map(|s| s.to_string()).collect(),
+            good: 0,
+            bad: bad - good,
+            skipped: BTreeSet::new(),
+            current: None,
+            original_head: self.read_head()?.0.map(|head| head.to_string()),
+            force,
+        };
+        let step = self.bisect_step(&mut state)?;
+        self.save_bisect_state(&state)?;
+        Ok(step)
+    }
+
+    /// Marks the snapshot (or the one being tested, if [`None`]), and
+    /// extracts the next snapshot to test.
+    pub fn bisect_mark(
+        &mut self,
+        snapshot: Option<&SnapshotId>,
+        mark: BisectMark,
+    ) -> Result<BisectStep, Error> {
+        let mut state = self.load_bisect_state()?.ok_or(Error::NotBisecting)?;
+        let index = match snapshot {
+            Some(snapshot) => state
+                .snapshots
+                .iter()
+                .position(|s| *s == snapshot.to_string())
+                .or_else(|| {
+                    state.snapshots.iter().position(|s| {
+                        SnapshotId::from_str(s).is_ok_and(|s| s.tag() == snapshot.tag())
+                    })
+                })
+                .ok_or_else(|| {
+                    Error::InvalidBisectStep(format!(
+                        "The snapshot {} is not between the good and the bad snapshots",
+                        snapshot
+                    ))
+                })?,
+            None => state.current.ok_or_else(|| {
+                Error::InvalidBisectStep("No snapshot is being tested".to_owned())
+            })?,
+        };
+
+        match mark {
+            BisectMark::Good if index >= state.bad => {
+                return Err(Error::InvalidBisectStep(format!(
+                    "The snapshot {} cannot be good, since it is not before the bad snapshot {}",
+                    state.snapshots[index], state.snapshots[state.bad]
+                )))
+            }
+            BisectMark::Bad if index <= state.good => {
+                return Err(Error::InvalidBisectStep(format!(
+                    "The snapshot {} cannot be bad, since it is not after the good snapshot {}",
+                    state.snapshots[index], state.snapshots[state.good]
+                )))
+            }
+            BisectMark::Good => state.good = state.good.max(index),
+            BisectMark::Bad => state.bad = state.bad.min(index),
+            BisectMark::Skip => {
+                state.skipped.insert(index);
+            }
+        }
+
+        let step = self.bisect_step(&mut state)?;
+        self.save_bisect_state(&state)?;
+        Ok(step)
+    }
+
+    /// Ends the bisection, and extracts the snapshot which was at HEAD when
+    /// it started. Returns that snapshot, if there was one.
+    pub fn bisect_reset(&mut self) -> Result<Option<SnapshotId>, Error> {
+        let state = self.load_bisect_state()?.ok_or(Error::NotBisecting)?;
+        let original_head = state
+            .original_head
+            .as_deref()
+            .map(SnapshotId::from_str)
+            .transpose()?;
+        if let Some(head) = &original_head {
+            self.extract_snapshot(head.clone(), self.bisect_extract_options(&state)?)?;
+        }
+        fs::remove_file(self.data_dir().join(BISECT_FILE))?;
+        Ok(original_head)
+    }
+
+    /// Returns the snapshot being tested, if a bisection is in progress.
+    pub fn bisect_current(&self) -> Result<Option<SnapshotId>, Error> {
+        match self.load_bisect_state()? {
+            Some(BisectState {
+                current: Some(current),
+                snapshots,
+                ..
+            }) => Ok(Some(SnapshotId::from_str(&snapshots[current])?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Extracts the next snapshot to test, if there is one.
+    fn bisect_step(&mut self, state: &mut BisectState) -> Result<BisectStep, Error> {
+        let step = state.next_step()?;
+        state.current = None;
+        if let BisectStep::Test { snapshot, .. } = &step {
+            // Incremental, since the snapshots are usually similar.
+            self.extract_snapshot(snapshot.clone(), self.bisect_extract_options(state)?)?;
+            state.current = state
+                .snapshots
+                .iter()
+                .position(|s| *s == snapshot.to_string());
+        }
+        Ok(step)
+    }
+
+    /// Extracts the same files as HEAD, like `extract` does.
+    fn bisect_extract_options(&self, state: &BisectState) -> Result<ExtractOptions, Error> {
+        let sparse_spec = self.read_head_sparse_spec()?;
+        let mut opts = ExtractOptions::default();
+        opts.set_force(state.force);
+        opts.set_include(sparse_spec.include);
+        opts.set_exclude(sparse_spec.exclude);
+        Ok(opts)
+    }
+
+    fn load_bisect_state(&self) -> Result<Option<BisectState>, Error> {
+        match fs::read(self.data_dir().join(BISECT_FILE)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+            Ok(buf) => Ok(Some(serde_json::from_slice(&buf).map_err(|e| {
+                Error::InvalidBisectStep(format!("The bisect state is corrupt ({})", e))
+            })?)),
+        }
+    }
+
+    fn save_bisect_state(&self, state: &BisectState) -> Result<(), Error> {
+        let temp_dir = self.data_dir().join(TEMP_DIR);
+        ensure_dir(&temp_dir)?;
+        let buf = serde_json::to_vec(state).expect("the state always serializes");
+        write_file_atomic(&buf[..], &temp_dir, &self.data_dir().join(BISECT_FILE))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_state(len: usize) -> BisectState {
+        BisectState {
+            snapshots: (0..len).map(|i| format!("pack:s{}", i)).collect(),
+            good: 0,
+            bad: len - 1,
+            skipped: BTreeSet::new(),
+            current: None,
+            original_head: None,
+            force: false,
+        }
+    }
+
+    fn test_step(state: &BisectState) -> String {
+        match state.next_step().unwrap() {
+            BisectStep::Test { snapshot, .. } => snapshot.tag().to_owned(),
+            step => panic!("unexpected step {:?}", step),
+        }
+    }
+
+    #[test]
+    fn bisect_halves_the_range() {
+        let mut state = make_state(9);
+        assert_eq!("s4", test_step(&state));
+        state.good = 4;
+        assert_eq!("s6", test_step(&state));
+        state.bad = 6;
+        assert_eq!("s5", test_step(&state));
+        state.bad = 5;
+        assert_eq!(
+            BisectStep::FirstBad(SnapshotId::from_str("pack:s5").unwrap()),
+            state.next_step().unwrap()
+        );
+    }
+
+    #[test]
+    fn bisect_avoids_skipped_snapshots() {
+        let mut state = make_state(4);
+        state.skipped.insert(1);
+        assert_eq!("s2", test_step(&state));
+        state.skipped.insert(2);
+        assert_eq!(
+            BisectStep::Ambiguous(
+                ["pack:s1", "pack:s2", "pack:s3"]
+                    .iter()
+                    .map(|s| SnapshotId::from_str(s).unwrap())
+                    .collect()
+            ),
+            state.next_step().unwrap()
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+//! Content-defined chunking, used to store the objects of chunked packs.
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+
+use super::error::Error;
+use crate::packidx::{Chunk, HashAlgorithm, ObjectChecksum};
+
+/// Chunks are never smaller than this, unless the object is.
+const MIN_CHUNK_SIZE: usize = 16 * 1024;
+/// Chunks are cut at this size, even if no boundary was found.
+const MAX_CHUNK_SIZE: usize = 256 * 1024;
+/// A boundary is found when these (high) bits of the hash are zero, which
+/// gives an average chunk size of 64 KiB on top of the minimum size.
+const BOUNDARY_MASK: u64 = 0xffff << 48;
+/// The total size of the chunks kept by a [`ChunkCache`].
+const CHUNK_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Random values mixed into the rolling hash, one per byte value.
+const GEAR: [u64; 256] = gear_table();
+
+/// Generates the [`GEAR`] table using the SplitMix64 generator, so that the
+/// chunk boundaries never change between builds.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < table.len() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Splits the data into content-defined chunks and returns their sizes.
+///
+/// The boundaries only depend on the bytes right before them, so inserting
+/// or removing bytes only changes the chunks around the edit, and the
+/// remaining chunks are shared with the original data.
+pub fn chunk_sizes(data: &[u8]) -> Vec<usize> {
+    let mut sizes = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let size = next_boundary(rest);
+        sizes.push(size);
+        rest = &rest[size..];
+    }
+    sizes
+}
+
+/// Returns the size of the first chunk of the data (FastCDC's gear hash).
+fn next_boundary(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+    let end = std::cmp::min(data.len(), MAX_CHUNK_SIZE);
+    let mut hash = 0u64;
+    for (i, &byte) in data[..end].iter().enumerate().skip(MIN_CHUNK_SIZE) {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        if hash & BOUNDARY_MASK == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Computes a fingerprint of the data for finding similar objects: the
+/// smallest value of the rolling hash over the data (a MinHash). Objects
+/// which share much of their data are likely to have the same fingerprint.
+pub fn similarity_hash(data: &[u8]) -> u64 {
+    let mut hash = 0u64;
+    let mut min = u64::MAX;
+    for &byte in data {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        // Mix the bits, so that the minimum does not favour runs of bytes
+        // with small gear values.
+        let mut z = hash;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        min = std::cmp::min(min, z ^ (z >> 31));
+    }
+    min
+}
+
+/// Splits objects into chunks and writes each distinct chunk to the
+/// underlying writer once, building the data stored in a chunked pack.
+pub struct ChunkWriter<W: Write> {
+    writer: W,
+    /// The offsets of the chunks written so far, by checksum.
+    offsets: HashMap<ObjectChecksum, u64>,
+    /// The sizes of the chunks written so far, in order.
+    chunk_sizes: Vec<u64>,
+    /// The number of bytes written so far.
+    size: u64,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            offsets: HashMap::new(),
+            chunk_sizes: vec![],
+            size: 0,
+        }
+    }
+
+    /// Adds the object and returns the chunks it is made of.
+    pub fn add_object(&mut self, data: &[u8]) -> io::Result<Vec<Chunk>> {
+        let mut chunks = vec![];
+        let mut rest = data;
+        for size in chunk_sizes(data) {
+            let (chunk, tail) = rest.split_at(size);
+            rest = tail;
+
+            let checksum = HashAlgorithm::Blake3.checksum(chunk);
+
+            let offset = match self.offsets.get(&checksum) {
+                Some(&offset) => offset,
+                None => {
+                    self.writer.write_all(chunk)?;
+                    let offset = self.size;
+                    self.offsets.insert(checksum, offset);
+                    self.chunk_sizes.push(size as u64);
+                    self.size += size as u64;
+                    offset
+                }
+            };
+            chunks.push(Chunk {
+                offset,
+                size: size as u64,
+            });
+        }
+        Ok(chunks)
+    }
+
+    /// The sizes of the distinct chunks, in the order they were written.
+    pub fn chunk_sizes(&self) -> &[u64] {
+        &self.chunk_sizes
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Maps the objects of a chunked pack onto the chunks they are made of.
+pub struct ChunkMap {
+    chunks: Vec<Chunk>,
+    /// The offset of each chunk in the stream of objects.
+    starts: Vec<u64>,
+}
+
+impl ChunkMap {
+    /// Creates the map from the chunks of the objects, in the order of the
+    /// objects (see [`crate::packidx::PackIndex::chunks`]).
+    pub fn new(chunks: Vec<Chunk>) -> Self {
+        let mut offset = 0;
+        let starts = chunks
+            .iter()
+            .map(|chunk| {
+                let start = offset;
+                offset += chunk.size;
+                start
+            })
+            .collect();
+        Self { chunks, starts }
+    }
+
+    /// Returns the pieces of the stored data making up the `size` bytes at
+    /// `offset` in the stream of objects.
+    pub fn resolve(&self, offset: u64, size: u64) -> Result<Vec<Chunk>, Error> {
+        let end = offset.checked_add(size).ok_or(Error::CorruptPack)?;
+        let first = self.starts.partition_point(|&start| start <= offset);
+        let mut pieces = vec![];
+        let mut position = offset;
+        for (chunk, &start) in self.chunks.iter().zip(&self.starts).skip(first.max(1) - 1) {
+            if position == end {
+                break;
+            }
+            let skip = position - start;
+            if skip >= chunk.size {
+                return Err(Error::CorruptPack);
+            }
+            let len = std::cmp::min(chunk.size - skip, end - position);
+            pieces.push(Chunk {
+                offset: chunk.offset + skip,
+                size: len,
+            });
+            position += len;
+        }
+        if position != end {
+            return Err(Error::CorruptPack);
+        }
+        Ok(pieces)
+    }
+}
+
+/// Keeps the chunks which were read most recently. Similar objects are
+/// stored next to each other, so the chunks they share are usually still
+/// in the cache.
+pub struct ChunkCache {
+    chunks: HashMap<Chunk, Vec<u8>>,
+    order: VecDeque<Chunk>,
+    size: usize,
+}
+
+impl ChunkCache {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            order: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    pub fn get(&self, chunk: &Chunk) -> Option<&[u8]> {
+        self.chunks.get(chunk).map(|data| &data[..])
+    }
+
+    /// Adds the chunk, evicting the oldest chunks if the cache is full.
+    pub fn insert(&mut self, chunk: Chunk, data: Vec<u8>) -> &[u8] {
+        while self.size + data.len() > CHUNK_CACHE_SIZE {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    self.size -= self.chunks.remove(&oldest).map_or(0, |data| data.len());
+                }
+                None => break,
+            }
+        }
+        self.size += data.len();
+        self.order.push_back(chunk.clone());
+        self.chunks.entry(chunk).or_insert(data)
+    }
+}
+
+impl Default for ChunkCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunk_sizes_works() {
+        assert!(chunk_sizes(&[]).is_empty());
+        assert_eq!(vec![100], chunk_sizes(&[0; 100]));
+
+        let data = pseudo_random_bytes(4 * 1024 * 1024, 1);
+        let sizes = chunk_sizes(&data);
+        assert_eq!(data.len(), sizes.iter().sum::<usize>());
+        assert!(sizes.iter().all(|&size| size <= MAX_CHUNK_SIZE));
+        assert!(sizes[..sizes.len() - 1]
+            .iter()
+            .all(|&size| size > MIN_CHUNK_SIZE));
+
+        // An insertion near the start only affects the chunks around it.
+        let mut edited = data[..1000].to_vec();
+        edited.extend_from_slice(b"inserted");
+        edited.extend_from_slice(&data[1000..]);
+        let edited_sizes = chunk_sizes(&edited);
+        assert_eq!(sizes[0] + 8, edited_sizes[0]);
+        assert_eq!(sizes[1..], edited_sizes[1..]);
+    }
+
+    #[test]
+    fn similarity_hash_works() {
+        let data = pseudo_random_bytes(64 * 1024, 3);
+        let mut edited = data.clone();
+        edited[1000..1010].copy_from_slice(b"0123456789");
+        let other = pseudo_random_bytes(64 * 1024, 4);
+
+        // A small edit is unlikely to touch the window with the minimum hash.
+        assert_eq!(similarity_hash(&data), similarity_hash(&edited));
+        assert_ne!(similarity_hash(&data), similarity_hash(&other));
+        assert_eq!(u64::MAX, similarity_hash(&[]));
+    }
+
+    #[test]
+    fn chunk_writer_deduplicates_chunks() {
+        let data = pseudo_random_bytes(1024 * 1024, 2);
+        let mut edited = data.clone();
+        edited[900 * 1024] ^= 0xff;
+
+        let mut writer = ChunkWriter::new(vec![]);
+        let chunks = writer.add_object(&data).unwrap();
+        let size_before = writer.chunk_sizes().iter().sum::<u64>();
+        let edited_chunks = writer.add_object(&edited).unwrap();
+        let size_after = writer.chunk_sizes().iter().sum::<u64>();
+        let stored = writer.into_inner().unwrap();
+
+        assert_eq!(data.len() as u64, size_before);
+        // Only the chunk containing the edit is stored again.
+        assert!(size_after - size_before <= MAX_CHUNK_SIZE as u64);
+        assert_eq!(stored.len() as u64, size_after);
+
+        // The objects can be reassembled from the stored data.
+        let map = ChunkMap::new(chunks.into_iter().chain(edited_chunks).collect());
+        let read = |offset, size| {
+            map.resolve(offset, size)
+                .unwrap()
+                .iter()
+                .flat_map(|piece| {
+                    stored[piece.offset as usize..(piece.offset + piece.size) as usize].to_vec()
+                })
+                .collect::<Vec<_>>()
+        };
+        let len = data.len() as u64;
+        assert_eq!(data, read(0, len));
+        assert_eq!(edited, read(len, len));
+        assert_eq!(&edited[1000..3000], &read(len + 1000, 2000)[..]);
+        assert!(map.resolve(len, len + 1).is_err());
+    }
+}
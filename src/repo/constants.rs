@@ -9,6 +9,12 @@ pub const REPO_DIR: &str = "elfshaker_data";
 pub const INDEX_FILE: &str = "index";
 /// A pointer to the extracted snapshot.
 pub const HEAD_FILE: &str = "HEAD";
+/// A directory containing the named refs, each a pointer to a snapshot.
+pub const REFS_DIR: &str = "refs";
+/// A directory containing the reflog of HEAD, in a file named like it.
+pub const LOGS_DIR: &str = "logs";
+/// The state of the bisection in progress, if any.
+pub const BISECT_FILE: &str = "BISECT";
 /// A directory containing a list of .pack and .pack.idx files
 pub const PACKS_DIR: &str = "packs";
 /// A directory containing the .esi files
@@ -29,3 +35,14 @@ pub const TEMP_DIR: &str = "trash";
 pub const DEFAULT_WINDOW_LOG_MAX: u32 = 30;
 /// Valid pack headers have this value set in the [`PackHeader::magic`] field.
 pub const PACK_HEADER_MAGIC: u64 = 848629801635942891;
+/// The maximum size of the dictionaries trained by `pack --train-dictionary`
+/// (the default of the zstd command-line tool).
+pub const DICTIONARY_SIZE: usize = 110 * 1024;
+/// Only the beginning of each object, up to this size, is used for training.
+pub const DICTIONARY_SAMPLE_SIZE: usize = 128 * 1024;
+/// The total size of the samples to train the dictionary on (about 100 times
+/// the size of the dictionary, as recommended by zstd).
+pub const DICTIONARY_SAMPLES_SIZE: usize = 100 * DICTIONARY_SIZE;
+/// The amount of input after which `pack --seek-interval` starts a new
+/// Zstandard frame, when appending to a pack with a seek table.
+pub const DEFAULT_SEEK_INTERVAL: u64 = 4 * 1024 * 1024;
@@ -2,19 +2,20 @@
 //! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
 
 use std::ffi::OsString;
-use std::{fmt::Display, io};
+use std::{fmt::Display, io, path::PathBuf};
 
 use crate::packidx::PackError;
 use crate::repo::pack::IdError;
 use crate::repo::remote::RemoteIndexFormatError;
 
-use super::PackId;
+use super::{PackId, SnapshotId};
 
 /// The type of error used by repository operations.
 #[derive(Debug)]
 pub enum Error {
     IOError(io::Error),
     WalkDirError(walkdir::Error),
+    GlobError(globset::Error),
     Utf8Error(OsString),
     PackError(PackError),
     IdError(IdError),
@@ -28,16 +29,34 @@ pub enum Error {
     CorruptPack,
     /// Multiple or none snapshots match the specified description
     AmbiguousSnapshotMatch(String, Vec<PackId>),
+    /// The name is both a ref to the snapshot and a snapshot tag in the packs
+    AmbiguousRef(String, SnapshotId, Vec<PackId>),
     /// The working directory contains unexpected files
     DirtyWorkDir,
+    /// The extraction destination is a non-empty directory
+    DestinationNotEmpty(PathBuf),
     /// The .pack file is not available in packs/
     PackNotFound(String),
+    /// Snapshots cannot be appended to the pack, for the given reason
+    CannotAppend(PackId, &'static str),
     /// The directory is not a repository
     RepositoryNotFound,
     /// The .esi file is corrupted.
     BadRemoteIndexFormat(RemoteIndexFormatError),
     /// A type-erased error resulting from an HTTP operation.
     HttpError(Box<dyn std::error::Error + Send + Sync>),
+    /// The name cannot be used for a ref
+    InvalidRefName(String),
+    /// There is no ref with the name
+    RefNotFound(String),
+    /// The file of the ref does not contain a snapshot
+    CorruptRef(String),
+    /// There is no snapshot at the offset from the snapshot
+    SnapshotOffsetOutOfRange(String, isize),
+    /// A bisect command was used when no bisection is in progress
+    NotBisecting,
+    /// The bisect command cannot be applied to the bisection in progress
+    InvalidBisectStep(String),
 }
 
 impl From<walkdir::Error> for Error {
@@ -46,6 +65,12 @@ impl From<walkdir::Error> for Error {
     }
 }
 
+impl From<globset::Error> for Error {
+    fn from(err: globset::Error) -> Self {
+        Self::GlobError(err)
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -57,6 +82,7 @@ impl Display for Error {
             Self::PackError(packerr) => packerr.fmt(f),
             Self::IdError(iderr) => iderr.fmt(f),
             Self::WalkDirError(wderr) => wderr.fmt(f),
+            Self::GlobError(globerr) => globerr.fmt(f),
             Self::Utf8Error(s) => write!(f, "Unable to interpret path as utf8: {:?}", s),
             Self::CorruptHead => write!(f, "HEAD is corrupt!"),
             Self::BrokenHeadRef(e) => write!(f, "Broken HEAD: {}", e),
@@ -71,19 +97,54 @@ impl Display for Error {
                     snapshot, packs
                 )
             }
+            Self::AmbiguousRef(name, snapshot, packs) => write!(
+                f,
+                "The name '{}' is both a ref to {} and a snapshot in the packs {:?}! \
+                 Use {} or <pack>:{} to select a snapshot.",
+                name, snapshot, packs, snapshot, name
+            ),
             Self::DirtyWorkDir => write!(
                 f,
                 "Some files in the repository have been removed or modified unexpectedly! \
                  You can use --force to skip this check, but this might result in DATA LOSS!"
             ),
+            Self::DestinationNotEmpty(p) => write!(
+                f,
+                "The destination directory {} is not empty! \
+                 You can use --force to extract into it anyway.",
+                p.display()
+            ),
             Self::PackNotFound(p) => write!(
                 f,
                 "The specified pack file '{}' could not be found in the repository index!",
                 p,
             ),
+            Self::CannotAppend(pack, reason) => {
+                write!(f, "Cannot append to the pack {}: {}!", pack, reason)
+            }
             Self::RepositoryNotFound => write!(f, "The directory is not an elfshaker repository!"),
             Self::HttpError(e) => e.fmt(f),
             Self::BadRemoteIndexFormat(e) => e.fmt(f),
+            Self::InvalidRefName(name) => write!(
+                f,
+                "Invalid ref name '{}'! Ref names cannot contain <>:\"|?* or control characters, \
+                 or empty, . and .. path components, and cannot be HEAD!",
+                name
+            ),
+            Self::RefNotFound(name) => write!(f, "The ref '{}' does not exist!", name),
+            Self::CorruptRef(name) => write!(f, "The ref '{}' is corrupt!", name),
+            Self::SnapshotOffsetOutOfRange(snapshot, offset) => write!(
+                f,
+                "There is no snapshot {} snapshot(s) {} {}!",
+                offset.unsigned_abs(),
+                if *offset < 0 { "before" } else { "after" },
+                snapshot
+            ),
+            Self::NotBisecting => write!(
+                f,
+                "No bisection is in progress! Use bisect start to begin one."
+            ),
+            Self::InvalidBisectStep(msg) => write!(f, "{}!", msg),
         }
     }
 }
@@ -0,0 +1,487 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    fs,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::info;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use super::algo::run_in_parallel;
+use super::constants::{
+    HEAD_FILE, LOOSE_DIR, PACKS_DIR, PACK_EXTENSION, PACK_INDEX_EXTENSION, REMOTES_DIR,
+    REMOTE_INDEX_EXTENSION,
+};
+use super::error::Error;
+use super::pack::{
+    compute_frame_decompressed_offset, read_pack_header, verify_object, PackFrame, PackId,
+};
+use super::remote::RemoteIndex;
+use super::repository::Repository;
+use crate::packidx::{
+    FileEntry, FileMetadata, HashAlgorithm, ObjectChecksum, ObjectMetadata, PackIndex,
+};
+
+/// The kind of item which an [`FsckIssue`] refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FsckItemKind {
+    Pack,
+    PackIndex,
+    Object,
+    LooseIndex,
+    LooseObject,
+    Head,
+    RemoteIndex,
+}
+
+impl Display for FsckItemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::Pack => "pack",
+            Self::PackIndex => "pack-index",
+            Self::Object => "object",
+            Self::LooseIndex => "loose-index",
+            Self::LooseObject => "loose-object",
+            Self::Head => "head",
+            Self::RemoteIndex => "remote-index",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A corrupt item found by [`Repository::fsck`].
+#[derive(Clone, Debug, Serialize)]
+pub struct FsckIssue {
+    pub kind: FsckItemKind,
+    /// The path of the corrupt file, relative to the data directory.
+    pub path: PathBuf,
+    /// The checksum of the corrupt object, if the issue is about an object.
+    pub object: Option<String>,
+    pub message: String,
+}
+
+/// The result of a [`Repository::fsck`] run.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FsckReport {
+    pub checked_packs: u32,
+    pub checked_indexes: u32,
+    pub checked_objects: u64,
+    pub checked_loose_objects: u64,
+    pub issues: Vec<FsckIssue>,
+}
+
+impl FsckReport {
+    /// Returns true if no corrupt items were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn add_issue<P: AsRef<Path>, M: Display>(
+        &mut self,
+        kind: FsckItemKind,
+        path: P,
+        object: Option<&ObjectChecksum>,
+        message: M,
+    ) {
+        self.issues.push(FsckIssue {
+            kind,
+            path: path.as_ref().to_owned(),
+            object: object.map(hex::encode),
+            message: message.to_string(),
+        });
+    }
+}
+
+impl Repository {
+    /// Checks the integrity of the repository. This decodes all pack headers
+    /// and indexes, and re-hashes every object stored in the repository, both
+    /// packed and loose. Packs which are only available on a remote are not
+    /// fetched. Corrupt items are listed in the returned report, errors are
+    /// only returned if the check itself cannot proceed.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_workers` - Number of decompression and hashing threads (this is an upper-limit).
+    pub fn fsck(&self, num_workers: u32) -> Result<FsckReport, Error> {
+        let mut report = FsckReport::default();
+
+        self.fsck_head(&mut report);
+        self.fsck_remotes(&mut report)?;
+        for pack_id in self.packs()? {
+            if self.is_pack_loose(&pack_id) {
+                self.fsck_loose_index(&pack_id, &mut report);
+            } else {
+                self.fsck_pack(&pack_id, num_workers, &mut report)?;
+            }
+        }
+        self.fsck_loose_objects(num_workers, &mut report)?;
+
+        Ok(report)
+    }
+
+    fn fsck_head(&self, report: &mut FsckReport) {
+        let head = match self.read_head() {
+            Ok((head, _)) => head,
+            Err(e) => return report.add_issue(FsckItemKind::Head, HEAD_FILE, None, e),
+        };
+        if let Err(e) = self
+            .read_head_sparse_spec()
+            .and_then(|spec| spec.matcher().map(|_| ()))
+        {
+            report.add_issue(FsckItemKind::Head, HEAD_FILE, None, e);
+        }
+        if let Some(head) = head {
+            match self.load_index_snapshots(head.pack()) {
+                Ok(tags) if tags.iter().any(|tag| tag == head.tag()) => {}
+                Ok(_) => report.add_issue(
+                    FsckItemKind::Head,
+                    HEAD_FILE,
+                    None,
+                    format!("HEAD references {}, which does not exist!", head),
+                ),
+                Err(e) => report.add_issue(
+                    FsckItemKind::Head,
+                    HEAD_FILE,
+                    None,
+                    format!("HEAD references {}: {}", head, e),
+                ),
+            }
+        }
+    }
+
+    fn fsck_remotes(&self, report: &mut FsckReport) -> Result<(), Error> {
+        let remotes_dir = self.data_dir().join(REMOTES_DIR);
+        if !remotes_dir.exists() {
+            return Ok(());
+        }
+        for dirent in fs::read_dir(&remotes_dir)? {
+            let path = dirent?.path();
+            if path.extension() != Some(OsStr::new(REMOTE_INDEX_EXTENSION)) {
+                continue;
+            }
+            if let Err(e) = RemoteIndex::load(&path) {
+                report.add_issue(
+                    FsckItemKind::RemoteIndex,
+                    self.relative_path(&path),
+                    None,
+                    e,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn fsck_loose_index(&self, pack_id: &PackId, report: &mut FsckReport) {
+        let index_path = self.index_path(pack_id);
+        report.checked_indexes += 1;
+        let index = match self.load_index(pack_id).and_then(check_snapshots) {
+            Ok(index) => index,
+            Err(e) => return report.add_issue(FsckItemKind::LooseIndex, index_path, None, e),
+        };
+        for checksum in index.object_checksums() {
+            if !self.loose_object_path(checksum).exists() {
+                report.add_issue(
+                    FsckItemKind::LooseIndex,
+                    &index_path,
+                    Some(checksum),
+                    "The object is missing from the loose object store!",
+                );
+            }
+        }
+    }
+
+    fn fsck_pack(
+        &self,
+        pack_id: &PackId,
+        num_workers: u32,
+        report: &mut FsckReport,
+    ) -> Result<(), Error> {
+        let index_path = self.index_path(pack_id);
+        report.checked_indexes += 1;
+        let index = match self.load_index(pack_id).and_then(check_snapshots) {
+            Ok(index) => index,
+            Err(e) => {
+                report.add_issue(FsckItemKind::PackIndex, index_path, None, e);
+                return Ok(());
+            }
+        };
+
+        let PackId::Pack(pack_name) = pack_id;
+        let pack_path = Path::new(PACKS_DIR).join(format!("{}.{}", pack_name, PACK_EXTENSION));
+        if !self.data_dir().join(&pack_path).exists() {
+            info!("{} is not available locally, skipping...", pack_id);
+            return Ok(());
+        }
+        report.checked_packs += 1;
+
+        let frames = match read_pack_header(&self.data_dir().join(&pack_path)) {
+            Ok(Some((file_size, header_size, header))) => {
+                if !header.is_valid() {
+                    report.add_issue(FsckItemKind::Pack, pack_path, None, "Bad pack magic!");
+                    return Ok(());
+                }
+                let seek_table_size = header.seek_table().map_or(0, |t| t.frame_size());
+                let sizes = header
+                    .frames()
+                    .iter()
+                    .try_fold(0u64, |sum, f| sum.checked_add(f.frame_size))
+                    .and_then(|frames_size| {
+                        let total = frames_size
+                            .checked_add(header_size)?
+                            .checked_add(seek_table_size)?;
+                        Some((frames_size, total))
+                    });
+                let (frames_size, total_size) = match sizes {
+                    Some(sizes) => sizes,
+                    None => {
+                        report.add_issue(
+                            FsckItemKind::Pack,
+                            pack_path,
+                            None,
+                            "The frame sizes overflow!",
+                        );
+                        return Ok(());
+                    }
+                };
+                if total_size != file_size {
+                    report.add_issue(
+                        FsckItemKind::Pack,
+                        pack_path,
+                        None,
+                        format!(
+                            "The header ({} B), frames ({} B) and seek table ({} B) do not add up to the file size ({} B)!",
+                            header_size, frames_size, seek_table_size, file_size
+                        ),
+                    );
+                    return Ok(());
+                }
+                header.frames().to_vec()
+            }
+            Ok(None) => {
+                info!("{} is in the legacy pack format", pack_id);
+                vec![PackFrame {
+                    frame_size: fs::metadata(self.data_dir().join(&pack_path))?.len(),
+                    decompressed_size: u64::MAX,
+                }]
+            }
+            Err(e) => {
+                report.add_issue(
+                    FsckItemKind::Pack,
+                    pack_path,
+                    None,
+                    format!("Unable to read the pack header: {}", e),
+                );
+                return Ok(());
+            }
+        };
+
+        // Only objects which lie within a frame can be read.
+        let frame_offsets = compute_frame_decompressed_offset(&frames);
+        // In chunked packs, it is the chunks which lie within the frames, and
+        // the objects which lie within the chunks.
+        let chunks = index.chunks();
+        for chunk in chunks {
+            let md = ObjectMetadata {
+                offset: chunk.offset,
+                size: chunk.size,
+            };
+            if !fits_in_frame(&frames, &frame_offsets, &md) {
+                report.add_issue(
+                    FsckItemKind::PackIndex,
+                    &index_path,
+                    None,
+                    format!(
+                        "The chunk (offset {}, size {}) does not fit in any frame of the pack!",
+                        chunk.offset, chunk.size
+                    ),
+                );
+                return Ok(());
+            }
+        }
+        let chunks_size = chunks.iter().map(|chunk| chunk.size).sum::<u64>();
+        let fits = |md: &ObjectMetadata| {
+            if chunks.is_empty() {
+                fits_in_frame(&frames, &frame_offsets, md)
+            } else {
+                matches!(md.offset.checked_add(md.size), Some(end) if end <= chunks_size)
+            }
+        };
+        let mut entries = vec![];
+        for checksum in index.object_checksums() {
+            let md = index.object_metadata(checksum);
+            if fits(md) {
+                entries.push(FileEntry::new(
+                    OsString::new(),
+                    *checksum,
+                    md.clone(),
+                    FileMetadata::default(),
+                ));
+            } else {
+                report.add_issue(
+                    FsckItemKind::Object,
+                    &index_path,
+                    Some(checksum),
+                    format!(
+                        "The object (offset {}, size {}) does not fit in any frame of the pack!",
+                        md.offset, md.size
+                    ),
+                );
+            }
+        }
+
+        report.checked_objects += entries.len() as u64;
+        let mismatches = Mutex::new(vec![]);
+        let hash_algorithm = index.hash_algorithm();
+        let result = self.open_pack(pack_id).and_then(|pack| {
+            pack.read_entries(&entries, false, num_workers, |entry, buf| {
+                if let Err(e) = verify_object(buf, &entry.checksum, hash_algorithm) {
+                    mismatches.lock().unwrap().push((entry.checksum, e));
+                }
+                Ok(())
+            })
+        });
+        let mut mismatches = mismatches.into_inner().unwrap();
+        mismatches.sort_by_key(|(checksum, _)| *checksum);
+        for (checksum, e) in mismatches {
+            report.add_issue(FsckItemKind::Object, &pack_path, Some(&checksum), e);
+        }
+        if let Err(e) = result {
+            report.add_issue(
+                FsckItemKind::Pack,
+                pack_path,
+                None,
+                format!("Unable to decompress the pack: {}", e),
+            );
+        }
+        Ok(())
+    }
+
+    fn fsck_loose_objects(&self, num_workers: u32, report: &mut FsckReport) -> Result<(), Error> {
+        let loose_dir = self.data_dir().join(LOOSE_DIR);
+        if !loose_dir.exists() {
+            return Ok(());
+        }
+
+        let mut objects = vec![];
+        for dirent in WalkDir::new(&loose_dir).sort_by_file_name() {
+            let dirent = dirent?;
+            if !dirent.file_type().is_file() {
+                continue;
+            }
+            match self.loose_object_checksum(dirent.path()) {
+                Some(checksum) => objects.push((dirent.into_path(), checksum)),
+                None => report.add_issue(
+                    FsckItemKind::LooseObject,
+                    self.relative_path(dirent.path()),
+                    None,
+                    "Unexpected file in the loose object store!",
+                ),
+            }
+        }
+
+        report.checked_loose_objects += objects.len() as u64;
+        let results = run_in_parallel(
+            num_workers as usize,
+            objects.into_iter(),
+            |(path, checksum)| {
+                let result = fs::read(&path)
+                    .map_err(Error::from)
+                    .and_then(|buf| verify_loose_object(&buf, &checksum));
+                (path, checksum, result)
+            },
+        );
+        for (path, checksum, result) in results {
+            if let Err(e) = result {
+                report.add_issue(
+                    FsckItemKind::LooseObject,
+                    self.relative_path(&path),
+                    Some(&checksum),
+                    e,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The path of the .pack.idx, relative to the data directory.
+    fn index_path(&self, pack_id: &PackId) -> PathBuf {
+        let PackId::Pack(pack_name) = pack_id;
+        Path::new(PACKS_DIR).join(format!("{}.{}", pack_name, PACK_INDEX_EXTENSION))
+    }
+
+    fn relative_path(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(self.data_dir())
+            .unwrap_or(path)
+            .to_owned()
+    }
+}
+
+/// Verifies a loose object, which is shared by the loose snapshots and does
+/// not record its hash algorithm. Any algorithm computing checksums of the
+/// right size is accepted.
+fn verify_loose_object(buf: &[u8], checksum: &ObjectChecksum) -> Result<(), Error> {
+    let mut result = Ok(());
+    for &hash_algorithm in HashAlgorithm::ALL {
+        if hash_algorithm.checksum_size() == checksum.len() {
+            result = verify_object(buf, checksum, hash_algorithm);
+            if result.is_ok() {
+                break;
+            }
+        }
+    }
+    result
+}
+
+/// Checks that all snapshots in the index can be resolved.
+fn check_snapshots(index: PackIndex) -> Result<PackIndex, Error> {
+    index.for_each_snapshot(|_, _| ControlFlow::<()>::Continue(()))?;
+    Ok(index)
+}
+
+/// Checks that the object lies within a single frame.
+fn fits_in_frame(frames: &[PackFrame], frame_offsets: &[u64], md: &ObjectMetadata) -> bool {
+    let frame_index = match frame_offsets.iter().rposition(|&x| x <= md.offset) {
+        Some(frame_index) => frame_index,
+        None => return false,
+    };
+    let frame_end =
+        frame_offsets[frame_index].saturating_add(frames[frame_index].decompressed_size);
+    matches!(md.offset.checked_add(md.size), Some(end) if end <= frame_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_in_frame_works() {
+        let frames = [
+            PackFrame {
+                frame_size: 5,
+                decompressed_size: 10,
+            },
+            PackFrame {
+                frame_size: 5,
+                decompressed_size: 10,
+            },
+        ];
+        let frame_offsets = compute_frame_decompressed_offset(&frames);
+        let md = |offset, size| ObjectMetadata { offset, size };
+
+        assert!(fits_in_frame(&frames, &frame_offsets, &md(0, 10)));
+        assert!(fits_in_frame(&frames, &frame_offsets, &md(12, 8)));
+        // Objects are not split across frames.
+        assert!(!fits_in_frame(&frames, &frame_offsets, &md(8, 4)));
+        assert!(!fits_in_frame(&frames, &frame_offsets, &md(15, 10)));
+        assert!(!fits_in_frame(&frames, &frame_offsets, &md(u64::MAX, 1)));
+        assert!(!fits_in_frame(&[], &[], &md(0, 0)));
+    }
+}
@@ -3,21 +3,38 @@
 
 //! Contains core types for interfacing with elfshaker repositories.
 mod algo;
+mod bisect;
+mod chunk;
 pub mod constants;
 mod error;
+mod fsck;
 #[doc(hidden)]
 pub mod fs;
+mod navigation;
 mod pack;
+mod reflog;
+mod refs;
 mod remote;
 mod repository;
+mod seektable;
+mod sparse;
 
 #[doc(hidden)]
 pub use algo::{partition_by_u64, run_in_parallel};
+pub use bisect::{BisectMark, BisectStep};
 pub use constants::{
-    HEAD_FILE, INDEX_FILE, LOOSE_DIR, PACKS_DIR, PACK_EXTENSION, PACK_INDEX_EXTENSION, REPO_DIR,
+    HEAD_FILE, INDEX_FILE, LOOSE_DIR, PACKS_DIR, PACK_EXTENSION, PACK_INDEX_EXTENSION, REFS_DIR,
+    REPO_DIR,
 };
 pub use error::Error;
+pub use fsck::{FsckIssue, FsckItemKind, FsckReport};
+pub use navigation::{split_snapshot_offset, FIRST_SNAPSHOT, LAST_SNAPSHOT};
 #[doc(hidden)]
 pub use pack::write_skippable_frame;
 pub use pack::{Pack, PackFrame, PackHeader, PackId, SnapshotId};
-pub use repository::{repo_bridge, ExtractOptions, ExtractResult, PackOptions, Repository};
+pub use reflog::{parse_reflog_selector, ReflogEntry};
+pub use refs::is_valid_ref_name;
+pub use repository::{
+    repo_bridge, ExtractOptions, ExtractResult, GcReport, ObjectOrdering, PackOptions, Repository,
+};
+pub use sparse::{SparseMatcher, SparseSpec};
@@ -0,0 +1,151 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+//! Navigation between snapshots, in the order they were added to packs
+//! (e.g. `tag~1` for the snapshot before `tag`, `pack:@last`).
+use std::{collections::HashSet, convert::TryFrom};
+
+use super::error::Error;
+use super::pack::SnapshotId;
+use super::repository::Repository;
+use crate::packidx::PackError;
+
+/// The tag which refers to the first snapshot of a pack (`pack:@first`).
+pub const FIRST_SNAPSHOT: &str = "@first";
+/// The tag which refers to the last snapshot of a pack (`pack:@last`).
+pub const LAST_SNAPSHOT: &str = "@last";
+
+/// Splits the offsets off the end of a snapshot: `~<n>` (or `~`, for 1) and
+/// `^-<n>` go back `n` snapshots, `^+<n>` goes forward. Several offsets can be
+/// combined (e.g. `tag~2^+1`). Returns [`None`] if there are none.
+pub fn split_snapshot_offset(s: &str) -> Option<(&str, isize)> {
+    fn parse_count(count: &str) -> Option<isize> {
+        if count.is_empty() || !count.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        count.parse().ok()
+    }
+
+    let mut base = s;
+    let mut offset: isize = 0;
+    while let Some(i) = base.rfind(['~', '^']) {
+        let (rest, suffix) = base.split_at(i);
+        let step = if let Some(count) = suffix.strip_prefix('~') {
+            if count.is_empty() {
+                Some(-1)
+            } else {
+                parse_count(count).map(|n| -n)
+            }
+        } else if let Some(count) = suffix.strip_prefix("^+") {
+            parse_count(count)
+        } else if let Some(count) = suffix.strip_prefix("^-") {
+            parse_count(count).map(|n| -n)
+        } else {
+            None
+        };
+        match step.and_then(|step| offset.checked_add(step)) {
+            Some(new_offset) if !rest.is_empty() => {
+                offset = new_offset;
+                base = rest;
+            }
+            _ => break,
+        }
+    }
+    (base.len() < s.len()).then_some((base, offset))
+}
+
+/// Finds the snapshot in the [`Repository::snapshot_order`].
+pub(super) fn position_in_order(
+    order: &[SnapshotId],
+    snapshot: &SnapshotId,
+) -> Result<usize, Error> {
+    // Loose snapshots which were packed are only listed in their pack.
+    Ok(order
+        .iter()
+        .position(|s| s == snapshot)
+        .or_else(|| order.iter().position(|s| s.tag() == snapshot.tag()))
+        .ok_or_else(|| PackError::SnapshotNotFound(snapshot.to_string()))?)
+}
+
+impl Repository {
+    /// Lists all snapshots in order: the snapshots of each pack in the order
+    /// they were added to it, with the packs ordered by name, followed by the
+    /// loose snapshots which are not in any pack, oldest first.
+    pub fn snapshot_order(&self) -> Result<Vec<SnapshotId>, Error> {
+        let mut order = vec![];
+        let mut packed_tags = HashSet::new();
+        for pack_id in self.packs()? {
+            if self.is_pack_loose(&pack_id) {
+                continue;
+            }
+            for tag in self.load_index_snapshots(&pack_id)? {
+                order.push(SnapshotId::new(pack_id.clone(), &tag)?);
+                packed_tags.insert(tag);
+            }
+        }
+        for pack_id in self.loose_packs()? {
+            for tag in self.load_index_snapshots(&pack_id)? {
+                if !packed_tags.contains(&tag) {
+                    order.push(SnapshotId::new(pack_id.clone(), &tag)?);
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Returns the snapshot `offset` snapshots after (or before, if negative)
+    /// `snapshot`, in the [`Self::snapshot_order`].
+    pub fn offset_snapshot(
+        &self,
+        snapshot: &SnapshotId,
+        offset: isize,
+    ) -> Result<SnapshotId, Error> {
+        let order = self.snapshot_order()?;
+        let position = position_in_order(&order, snapshot)?;
+        (position as isize)
+            .checked_add(offset)
+            .and_then(|i| usize::try_from(i).ok())
+            .and_then(|i| order.get(i))
+            .cloned()
+            .ok_or_else(|| Error::SnapshotOffsetOutOfRange(snapshot.to_string(), offset))
+    }
+
+    /// Resolves `@first` and `@last` to the first and last snapshots of the
+    /// pack. Other tags are returned as they are.
+    pub(super) fn resolve_pack_position(&self, snapshot: SnapshotId) -> Result<SnapshotId, Error> {
+        let tags = match snapshot.tag() {
+            FIRST_SNAPSHOT | LAST_SNAPSHOT => self.load_index_snapshots(snapshot.pack())?,
+            _ => return Ok(snapshot),
+        };
+        let tag = if snapshot.tag() == FIRST_SNAPSHOT {
+            tags.first()
+        } else {
+            tags.last()
+        };
+        match tag {
+            Some(tag) => Ok(SnapshotId::new(snapshot.pack().clone(), tag)?),
+            None => Err(PackError::SnapshotNotFound(snapshot.to_string()).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_offsets_are_split() {
+        assert_eq!(Some(("tag", -1)), split_snapshot_offset("tag~"));
+        assert_eq!(Some(("tag", -3)), split_snapshot_offset("tag~3"));
+        assert_eq!(Some(("pack:tag", 3)), split_snapshot_offset("pack:tag^+3"));
+        assert_eq!(Some(("tag", -1)), split_snapshot_offset("tag~2^+1"));
+        assert_eq!(
+            Some(("pack:@last", -2)),
+            split_snapshot_offset("pack:@last^-2")
+        );
+        assert_eq!(Some(("a~b", 1)), split_snapshot_offset("a~b^+1"));
+        assert_eq!(None, split_snapshot_offset("tag"));
+        assert_eq!(None, split_snapshot_offset("tag^3"));
+        assert_eq!(None, split_snapshot_offset("~1"));
+    }
+}
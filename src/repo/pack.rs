@@ -1,35 +1,38 @@
 //! SPDX-License-Identifier: Apache-2.0
 //! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
 
-use serde::{Deserialize, Serialize};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 use std::{
-    fmt, fs,
-    fs::File,
-    io,
+    fmt, fs, io,
     io::{BufReader, Read, Write},
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 use std::{fmt::Display, str::FromStr};
 
 use filetime::{set_file_mtime, FileTime};
 
-use crypto::digest::Digest;
-use crypto::sha1::Sha1;
-
 use log::info;
 
 use zstd::stream::raw::DParameter;
 use zstd::Decoder;
 
+use super::chunk::{ChunkCache, ChunkMap};
 use super::constants::{
     DEFAULT_WINDOW_LOG_MAX, PACKS_DIR, PACK_EXTENSION, PACK_HEADER_MAGIC, PACK_INDEX_EXTENSION,
 };
 use super::error::Error;
 use super::fs::{create_file, open_file};
+use super::seektable::{SeekPoint, SeekTable};
 use super::REPO_DIR;
 use super::{algo::run_in_parallel, constants::DOT_PACK_INDEX_EXTENSION};
-use crate::{pack, packidx::{FileEntry, FileMetadata, ObjectChecksum, PackError}};
 use crate::{log::measure_ok, packidx::ObjectMetadata};
+use crate::{
+    pack,
+    packidx::{
+        Chunk, FileEntry, FileMetadata, HashAlgorithm, ObjectChecksum, PackError, PackIndex,
+    },
+};
 
 #[cfg(target_family = "unix")]
 use std::os::unix::fs::PermissionsExt;
@@ -230,24 +233,212 @@ pub fn write_skippable_frame(mut writer: impl Write, buf: &[u8]) -> io::Result<u
     Ok((std::mem::size_of::<u32>() * 2 + buf.len()) as u64)
 }
 
+/// The size of the buffer used to stream objects to a [`Write`] sink.
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// A reader over the bytes of a .pack file.
+trait SeekRead: Read + io::Seek + Send {}
+
+impl<T: Read + io::Seek + Send> SeekRead for T {}
+
+/// The bytes of a .pack file. Each frame of a pack is read with a separate
+/// reader, so the source has to be able to open any number of them.
+trait PackSource: Send + Sync {
+    /// Opens a new reader, positioned at the start of the .pack file.
+    fn open(&self) -> io::Result<Box<dyn SeekRead>>;
+    /// The size of the .pack file (in bytes).
+    fn len(&self) -> io::Result<u64>;
+}
+
+/// A .pack file in the filesystem.
+struct FileSource(PathBuf);
+
+impl PackSource for FileSource {
+    fn open(&self) -> io::Result<Box<dyn SeekRead>> {
+        Ok(Box::new(open_file(&self.0)?))
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(open_file(&self.0)?.metadata()?.len())
+    }
+}
+
+/// A .pack file held in memory, or mapped into memory.
+struct BytesSource<B>(Arc<B>);
+
+/// The bytes of a [`BytesSource`], shared by its readers.
+struct SharedBytes<B>(Arc<B>);
+
+impl<B: AsRef<[u8]>> AsRef<[u8]> for SharedBytes<B> {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref().as_ref()
+    }
+}
+
+impl<B: AsRef<[u8]> + Send + Sync + 'static> PackSource for BytesSource<B> {
+    fn open(&self) -> io::Result<Box<dyn SeekRead>> {
+        Ok(Box::new(io::Cursor::new(SharedBytes(self.0.clone()))))
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.0.as_ref().as_ref().len() as u64)
+    }
+}
+
+/// A .pack file read through a single reader, which is shared by the readers
+/// of the frames.
+struct ReaderSource<R> {
+    reader: Arc<Mutex<R>>,
+    len: u64,
+}
+
+/// A reader of a [`ReaderSource`], which keeps its own position in the
+/// shared reader.
+struct SharedReader<R> {
+    reader: Arc<Mutex<R>>,
+    position: u64,
+    len: u64,
+}
+
+impl<R: Read + io::Seek + Send + 'static> PackSource for ReaderSource<R> {
+    fn open(&self) -> io::Result<Box<dyn SeekRead>> {
+        Ok(Box::new(SharedReader {
+            reader: self.reader.clone(),
+            position: 0,
+            len: self.len,
+        }))
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+}
+
+impl<R: Read + io::Seek> Read for SharedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut reader = self.reader.lock().unwrap();
+        reader.seek(io::SeekFrom::Start(self.position))?;
+        let n = reader.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R> io::Seek for SharedReader<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            io::SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// Moves the readers of the frames of a pack forward, starting from the
+/// closest seek point (see [`SeekTable`]) when that is ahead of the reader.
+#[derive(Clone, Copy)]
+struct FrameSeeker<'a> {
+    source: &'a dyn PackSource,
+    dictionary: Option<&'a [u8]>,
+    /// The seek points of the pack, in order.
+    seek_points: &'a [SeekPoint],
+}
+
+impl FrameSeeker<'_> {
+    /// Moves `reader` to `local_offset` in the frame starting at
+    /// `frame_offset` in the .pack file and at `frame_start` in the
+    /// decompressed data. The reader is reopened if it is already past the
+    /// offset.
+    fn seek(
+        &self,
+        reader: &mut PackReader,
+        frame_offset: u64,
+        frame_start: u64,
+        local_offset: u64,
+    ) -> Result<(), Error> {
+        let offset = frame_start + local_offset;
+        let next = self
+            .seek_points
+            .partition_point(|point| point.decompressed_offset <= offset);
+        let (start_offset, position) = match next.checked_sub(1).map(|i| &self.seek_points[i]) {
+            Some(point) if point.decompressed_offset >= frame_start => {
+                (point.offset, point.decompressed_offset - frame_start)
+            }
+            _ => (frame_offset, 0),
+        };
+        if reader.position() > local_offset || reader.position() < position {
+            *reader = PackReader::open_at(self.source, start_offset, position, self.dictionary)?;
+        }
+        reader.seek(local_offset - reader.position())?;
+        Ok(())
+    }
+}
+
 /// The unidirectional stream of data stored in the pack.
 enum PackReader {
-    Compressed(Decoder<'static, BufReader<File>>),
+    Compressed {
+        decoder: Decoder<'static, BufReader<Box<dyn SeekRead>>>,
+        /// The number of bytes read from the decompressed stream so far.
+        position: u64,
+    },
 }
 
 impl PackReader {
-    /// Consumes the specified number of bytes from the reader.
-    fn seek(&mut self, bytes: u64) -> io::Result<()> {
+    /// Opens the Zstandard frame starting at `offset` in the .pack file. The
+    /// frame is decompressed with the dictionary of the pack, if it has one.
+    fn open(
+        source: &dyn PackSource,
+        offset: u64,
+        dictionary: Option<&[u8]>,
+    ) -> Result<Self, Error> {
+        Self::open_at(source, offset, 0, dictionary)
+    }
+
+    /// Opens the Zstandard frame starting at `offset` in the .pack file,
+    /// which is `position` bytes into the decompressed stream of a frame of
+    /// the pack (see [`SeekTable`]).
+    fn open_at(
+        source: &dyn PackSource,
+        offset: u64,
+        position: u64,
+        dictionary: Option<&[u8]>,
+    ) -> Result<Self, Error> {
+        let mut reader = source.open()?;
+        reader.seek(io::SeekFrom::Start(offset))?;
+        let mut decoder =
+            Decoder::with_dictionary(BufReader::new(reader), dictionary.unwrap_or(&[]))?;
+        decoder.set_parameter(DParameter::WindowLogMax(DEFAULT_WINDOW_LOG_MAX))?;
+        Ok(Self::Compressed { decoder, position })
+    }
+
+    /// The number of bytes read from the decompressed stream so far.
+    fn position(&self) -> u64 {
         match self {
-            Self::Compressed(decoder) => {
-                io::copy(&mut decoder.by_ref().take(bytes), &mut io::sink()).map(|_| {})
-            }
+            Self::Compressed { position, .. } => *position,
         }
     }
-    // Reads the exact number of bytes into `buf`.
-    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+
+    /// Consumes the specified number of bytes from the reader.
+    fn seek(&mut self, bytes: u64) -> io::Result<()> {
+        io::copy(&mut self.by_ref().take(bytes), &mut io::sink()).map(|_| {})
+    }
+}
+
+impl Read for PackReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
-            Self::Compressed(decoder) => decoder.read_exact(buf),
+            Self::Compressed { decoder, position } => {
+                let n = decoder.read(buf)?;
+                *position += n as u64;
+                Ok(n)
+            }
         }
     }
 }
@@ -262,12 +453,53 @@ pub struct PackFrame {
 }
 
 /// Represents the custom header in the beginning of a .pack file.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct PackHeader {
     /// Valid pack headers have this value set to [`PACK_HEADER_MAGIC`].
     magic: u64,
     /// The list of frames in the pack file, sorted by their byte offsets.
     frames: Vec<PackFrame>,
+    /// Whether the header is followed by a skippable frame containing the
+    /// Zstandard dictionary used to compress the frames. The field is left
+    /// out for packs without a dictionary, so that their headers are the same
+    /// as before dictionaries were supported.
+    #[serde(default)]
+    has_dictionary: bool,
+    /// Whether the pack file ends with a seek table. Like
+    /// [`Self::has_dictionary`], the field is left out when it is not set.
+    #[serde(default)]
+    has_seek_table: bool,
+    /// The dictionary, which is stored in its own frame, see [`Self::has_dictionary`].
+    #[serde(skip)]
+    dictionary: Option<Vec<u8>>,
+    /// The seek table at the end of the pack file, see [`Self::has_seek_table`].
+    /// It is read along with the header, but written after the frames.
+    #[serde(skip)]
+    seek_table: Option<SeekTable>,
+}
+
+impl Serialize for PackHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // The fields are stored in order, without their names, so the flags
+        // which are not set can only be left out from the end.
+        let len = if self.has_seek_table {
+            4
+        } else if self.has_dictionary {
+            3
+        } else {
+            2
+        };
+        let mut state = serializer.serialize_struct("PackHeader", len)?;
+        state.serialize_field("magic", &self.magic)?;
+        state.serialize_field("frames", &self.frames)?;
+        if len > 2 {
+            state.serialize_field("has_dictionary", &self.has_dictionary)?;
+        }
+        if len > 3 {
+            state.serialize_field("has_seek_table", &self.has_seek_table)?;
+        }
+        state.end()
+    }
 }
 
 impl PackHeader {
@@ -276,21 +508,114 @@ impl PackHeader {
         Self {
             magic: PACK_HEADER_MAGIC,
             frames,
+            has_dictionary: false,
+            has_seek_table: false,
+            dictionary: None,
+            seek_table: None,
+        }
+    }
+    /// Create a new pack header, for frames compressed with the dictionary.
+    pub fn with_dictionary(frames: Vec<PackFrame>, dictionary: Option<Vec<u8>>) -> Self {
+        Self {
+            has_dictionary: dictionary.is_some(),
+            dictionary,
+            ..Self::new(frames)
         }
     }
     /// Verifies the header magic.
     pub fn is_valid(&self) -> bool {
         self.magic == PACK_HEADER_MAGIC
     }
+    /// The list of frames in the pack file, sorted by their byte offsets.
+    pub fn frames(&self) -> &[PackFrame] {
+        &self.frames
+    }
+    /// The dictionary used to compress the frames, if any.
+    pub fn dictionary(&self) -> Option<&[u8]> {
+        self.dictionary.as_deref()
+    }
+    /// The seek table at the end of the pack file, if any.
+    pub fn seek_table(&self) -> Option<&SeekTable> {
+        self.seek_table.as_ref()
+    }
+    /// Records whether the pack file ends with a seek table, which is
+    /// written after the frames.
+    pub fn set_has_seek_table(&mut self, has_seek_table: bool) {
+        self.has_seek_table = has_seek_table;
+    }
 }
 
 impl Default for PackHeader {
     fn default() -> Self {
-        Self {
-            magic: PACK_HEADER_MAGIC,
-            frames: vec![],
+        Self::new(vec![])
+    }
+}
+
+/// Writes the header of a pack file, followed by the frame containing the
+/// dictionary, if there is one. Returns the number of bytes written, which
+/// is where the first frame of the pack starts.
+pub(super) fn write_pack_header(mut writer: impl Write, header: &PackHeader) -> io::Result<u64> {
+    let header_bytes = rmp_serde::encode::to_vec(header).expect("Serialization failed!");
+    let mut size = write_skippable_frame(&mut writer, &header_bytes)?;
+    if let Some(dictionary) = &header.dictionary {
+        size += write_skippable_frame(&mut writer, dictionary)?;
+    }
+    Ok(size)
+}
+
+/// Reads the size of the pack file, and the size and contents of its header.
+/// The size of the header includes the frame containing the dictionary, if
+/// there is one. The seek table at the end of the file is read as well, if
+/// the header records one.
+/// Returns [`None`] if the file does not start with a skippable frame, which
+/// is the case for packs in the legacy format.
+pub(super) fn read_pack_header(pack_path: &Path) -> Result<Option<(u64, u64, PackHeader)>, Error> {
+    read_pack_header_from(&FileSource(pack_path.to_owned()))
+}
+
+/// Implements [`read_pack_header`] for any source of the .pack file.
+fn read_pack_header_from(source: &dyn PackSource) -> Result<Option<(u64, u64, PackHeader)>, Error> {
+    let file_size = source.len()?;
+    let mut reader = io::BufReader::new(source.open()?);
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if u32::from_le_bytes(magic) & SKIPPABLE_MAGIC_MASK != SKIPPABLE_MAGIC_MASK {
+        return Ok(None);
+    }
+    io::Seek::seek(&mut reader, io::SeekFrom::Start(0))?;
+
+    let mut header = vec![];
+    let mut header_size = read_skippable_frame(&mut reader, &mut header)?;
+    let mut header: PackHeader =
+        rmp_serde::decode::from_read(&header[..]).map_err(|_| Error::CorruptPack)?;
+    if header.has_dictionary {
+        let mut dictionary = vec![];
+        header_size += read_skippable_frame(&mut reader, &mut dictionary)?;
+        header.dictionary = Some(dictionary);
+    }
+    if header.has_seek_table {
+        header.seek_table =
+            Some(SeekTable::read(&mut reader, file_size)?.ok_or(Error::CorruptPack)?);
+    }
+    if let Some(seek_table) = &header.seek_table {
+        // The seek table must describe the rest of the file exactly.
+        let entries = seek_table.entries();
+        let compressed_size: u64 = entries.iter().map(|e| e.compressed_size as u64).sum();
+        let decompressed_size: u64 = entries.iter().map(|e| e.decompressed_size as u64).sum();
+        let frames_size = header
+            .frames
+            .iter()
+            .try_fold(0u64, |sum, f| sum.checked_add(f.decompressed_size))
+            .ok_or(Error::CorruptPack)?;
+        if compressed_size + seek_table.frame_size() != file_size
+            || decompressed_size != frames_size
+        {
+            return Err(Error::CorruptPack);
         }
     }
+
+    Ok(Some((file_size, header_size, header)))
 }
 
 /// Represents an pack file.
@@ -299,10 +624,22 @@ pub struct Pack {
     name: String,
     /// The header of the pack.
     header: PackHeader,
+    /// The bytes of the .pack file.
+    source: Arc<dyn PackSource>,
+    /// The offsets of the frames in the .pack file.
+    frame_offsets: Vec<u64>,
     /// PackReader instances for each frame in the .pack file.
     frame_readers: Vec<PackReader>,
     /// The file size of the pack (in bytes).
     file_size: u64,
+    /// The chunks making up the objects, if the pack is chunked.
+    chunks: Option<Arc<ChunkMap>>,
+    /// The chunks read most recently, if the pack is chunked.
+    chunk_cache: ChunkCache,
+    /// The positions at which decompression can start, from the seek table.
+    seek_points: Arc<Vec<SeekPoint>>,
+    /// The algorithm used to compute the checksums of the objects.
+    hash_algorithm: HashAlgorithm,
 }
 
 impl Pack {
@@ -321,55 +658,147 @@ impl Pack {
         let pack_index_path = packs_data.join(format!("{}.{}", pack_name, PACK_INDEX_EXTENSION));
         let pack_path = packs_data.join(format!("{}.{}", pack_name, PACK_EXTENSION));
         info!("Opening pack file {:?}...", pack_path);
-        let (file_size, header, frame_readers) =
-            Self::open_pack(&pack_path).or_else(|_| Self::open_pack_legacy(&pack_path))?;
+        // The objects of chunked packs can only be found using the index.
+        let (chunks, hash_algorithm) = if pack_index_path.exists() {
+            (
+                PackIndex::load_only_chunks(&pack_index_path)?,
+                PackIndex::load_only_hash_algorithm(&pack_index_path)?,
+            )
+        } else {
+            (vec![], HashAlgorithm::Sha1)
+        };
+        Self::from_source(
+            pack_name,
+            Arc::new(FileSource(pack_path)),
+            chunks,
+            hash_algorithm,
+        )
+    }
+
+    /// Opens a pack from a reader over the contents of a .pack file. The
+    /// reader is shared by the frames of the pack, which seek to their own
+    /// position before reading from it. The index of the pack can be read
+    /// from any reader using [`PackIndex::parse`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pack_name` - The name of the pack.
+    /// * `reader` - The contents of the .pack file.
+    /// * `index` - The index of the pack.
+    pub fn from_reader<R>(pack_name: &str, mut reader: R, index: &PackIndex) -> Result<Self, Error>
+    where
+        R: Read + io::Seek + Send + 'static,
+    {
+        let len = reader.seek(io::SeekFrom::End(0))?;
+        let source = ReaderSource {
+            reader: Arc::new(Mutex::new(reader)),
+            len,
+        };
+        Self::from_source(
+            pack_name,
+            Arc::new(source),
+            index.chunks().to_vec(),
+            index.hash_algorithm(),
+        )
+    }
+
+    /// Opens a pack from the contents of a .pack file held in memory, such as
+    /// a [`Vec<u8>`] or a memory-mapped file. Unlike [`Pack::from_reader`],
+    /// the frames of the pack can be read concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `pack_name` - The name of the pack.
+    /// * `bytes` - The contents of the .pack file.
+    /// * `index` - The index of the pack.
+    pub fn from_bytes<B>(pack_name: &str, bytes: B, index: &PackIndex) -> Result<Self, Error>
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        let source = BytesSource(Arc::new(bytes));
+        Self::from_source(
+            pack_name,
+            Arc::new(source),
+            index.chunks().to_vec(),
+            index.hash_algorithm(),
+        )
+    }
+
+    fn from_source(
+        pack_name: &str,
+        source: Arc<dyn PackSource>,
+        chunks: Vec<Chunk>,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Self, Error> {
+        let (file_size, header, frame_offsets) = Self::open_pack(source.as_ref())
+            .or_else(|_| Self::open_pack_legacy(source.as_ref()))?;
+        let frame_readers = frame_offsets
+            .iter()
+            .map(|&offset| PackReader::open(source.as_ref(), offset, header.dictionary()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let chunks = Some(chunks)
+            .filter(|chunks| !chunks.is_empty())
+            .map(|chunks| Arc::new(ChunkMap::new(chunks)));
+        let seek_points = header
+            .seek_table()
+            .map_or_else(Vec::new, SeekTable::seek_points);
 
         Ok(Pack {
             name: pack_name.to_owned(),
             header,
+            source,
+            frame_offsets,
             frame_readers,
             file_size,
+            chunks,
+            chunk_cache: ChunkCache::new(),
+            seek_points: Arc::new(seek_points),
+            hash_algorithm,
         })
     }
 
-    /// Opens the pack file for reading.
-    fn open_pack(pack_path: &Path) -> Result<(u64, PackHeader, Vec<PackReader>), Error> {
-        let file = open_file(pack_path)?;
-        let file_size = file.metadata()?.len();
-        let mut reader = io::BufReader::new(file);
-
-        let mut header = vec![];
-        let header_size = read_skippable_frame(&mut reader, &mut header)?;
-        let header: PackHeader =
-            rmp_serde::decode::from_read(&header[..]).map_err(|_| Error::CorruptPack)?;
+    /// Opens the pack again, with separate readers for the frames.
+    fn try_clone(&self) -> Result<Self, Error> {
+        let frame_readers = self
+            .frame_offsets
+            .iter()
+            .map(|&offset| PackReader::open(self.source.as_ref(), offset, self.header.dictionary()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Pack {
+            name: self.name.clone(),
+            header: self.header.clone(),
+            source: self.source.clone(),
+            frame_offsets: self.frame_offsets.clone(),
+            frame_readers,
+            file_size: self.file_size,
+            chunks: self.chunks.clone(),
+            chunk_cache: ChunkCache::new(),
+            seek_points: self.seek_points.clone(),
+            hash_algorithm: self.hash_algorithm,
+        })
+    }
 
-        drop(reader);
+    /// Reads the header of the pack file, and returns it along with the
+    /// offsets of the frames in the file.
+    fn open_pack(source: &dyn PackSource) -> Result<(u64, PackHeader, Vec<u64>), Error> {
+        let (file_size, header_size, header) =
+            read_pack_header_from(source)?.ok_or(Error::CorruptPack)?;
 
         if !header.is_valid() {
             return Err(Error::CorruptPack);
         }
 
-        let frame_offsets = compute_frame_offsets(&header.frames);
-        let frame_readers = frame_offsets
-            .iter()
-            .map(|offset| -> Result<_, Error> {
-                let mut reader = open_file(pack_path)?;
-                io::Seek::seek(&mut reader, io::SeekFrom::Start(header_size + offset))?;
-                let mut reader = Decoder::new(reader)?;
-                reader.set_parameter(DParameter::WindowLogMax(DEFAULT_WINDOW_LOG_MAX))?;
-                // Wrap in a `PackReader`.
-                let reader = PackReader::Compressed(reader);
-                Ok(reader)
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+        let frame_offsets = compute_frame_offsets(&header.frames)
+            .into_iter()
+            .map(|offset| header_size + offset)
+            .collect();
 
-        Ok((file_size, header, frame_readers))
+        Ok((file_size, header, frame_offsets))
     }
 
     /// Backwards-compatible open_pack for the legacy pack format (no skippable frame/no header).
-    fn open_pack_legacy(pack_path: &Path) -> Result<(u64, PackHeader, Vec<PackReader>), Error> {
-        let file = open_file(pack_path)?;
-        let file_size = file.metadata()?.len();
+    fn open_pack_legacy(source: &dyn PackSource) -> Result<(u64, PackHeader, Vec<u64>), Error> {
+        let file_size = source.len()?;
 
         // This manufactured pack header works for the current implementation. We might
         // change how/whether we support the legacy pack format in the future...
@@ -378,11 +807,7 @@ impl Pack {
             decompressed_size: u64::MAX,
         }]);
 
-        let mut reader = Decoder::new(file)?;
-        reader.set_parameter(DParameter::WindowLogMax(DEFAULT_WINDOW_LOG_MAX))?;
-        let frame_reader = PackReader::Compressed(reader);
-
-        Ok((file_size, header, vec![frame_reader]))
+        Ok((file_size, header, vec![0]))
     }
 
     /// The base filename ([`Path::file_stem`]) of the pack.
@@ -395,6 +820,96 @@ impl Pack {
         self.file_size
     }
 
+    /// Returns true if the objects in the pack are split into chunks, see
+    /// [`PackIndex::chunks`].
+    pub fn is_chunked(&self) -> bool {
+        self.chunks.is_some()
+    }
+
+    /// Returns the frame reader positioned at `offset` in the decompressed
+    /// data of the pack, along with the number of bytes left in that frame.
+    fn frame_reader_at(&mut self, offset: u64) -> Result<(&mut PackReader, u64), Error> {
+        let frame_decompressed_offset = compute_frame_decompressed_offset(&self.header.frames);
+        let frame_index = frame_decompressed_offset
+            .iter()
+            .rposition(|&x| x <= offset)
+            .ok_or(Error::CorruptPack)?;
+        let local_offset = offset - frame_decompressed_offset[frame_index];
+        let remaining = self.header.frames[frame_index]
+            .decompressed_size
+            .saturating_sub(local_offset);
+
+        let seeker = FrameSeeker {
+            source: self.source.as_ref(),
+            dictionary: self.header.dictionary(),
+            seek_points: &self.seek_points,
+        };
+        let reader = &mut self.frame_readers[frame_index];
+        seeker.seek(
+            reader,
+            self.frame_offsets[frame_index],
+            frame_decompressed_offset[frame_index],
+            local_offset,
+        )?;
+        Ok((reader, remaining))
+    }
+
+    /// Reads the piece of a chunk, going through the cache.
+    fn read_chunk(&mut self, chunk: &Chunk) -> Result<&[u8], Error> {
+        if self.chunk_cache.get(chunk).is_none() {
+            let (reader, remaining) = self.frame_reader_at(chunk.offset)?;
+            // Chunks are never split across frames.
+            if chunk.size > remaining {
+                return Err(Error::CorruptPack);
+            }
+            let mut data = vec![0; chunk.size as usize];
+            reader.read_exact(&mut data)?;
+            return Ok(self.chunk_cache.insert(chunk.clone(), data));
+        }
+        Ok(self.chunk_cache.get(chunk).unwrap())
+    }
+
+    /// Writes the contents of the entry to `writer`, decompressing the frame
+    /// containing it only up to the end of the object. Unlike
+    /// [`Self::extract_entries`], this does not consume the pack, and the
+    /// contents are streamed instead of being buffered in memory.
+    ///
+    /// Reading entries in order of increasing offset is the most efficient, as
+    /// frames can only be read forward and are otherwise decompressed again from
+    /// the start.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry to write. This *must* be an entry contained in the pack index.
+    /// * `verify` - Enable/disable checksum verification. Since the checksum is
+    ///   computed while writing, a mismatch is only reported once the whole object
+    ///   has been written.
+    /// * `writer` - The sink to write the contents to.
+    pub fn write_object<W>(
+        &mut self,
+        entry: &FileEntry,
+        verify: bool,
+        writer: &mut W,
+    ) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let metadata = &entry.obj_metadata;
+        let verify = verify.then_some(self.hash_algorithm);
+        if let Some(chunks) = &self.chunks {
+            let pieces = chunks.resolve(metadata.offset, metadata.size)?.into();
+            let reader = ChunkReader {
+                pack: self,
+                pieces,
+                position: 0,
+            };
+            return copy_object(reader, metadata.size, &entry.checksum, verify, writer);
+        }
+
+        let (reader, _) = self.frame_reader_at(metadata.offset)?;
+        copy_object(reader, metadata.size, &entry.checksum, verify, writer)
+    }
+
     /// Extracts the specified entries from the pack into the specified directory.
     /// This operation consumes the pack, since [`Pack`] objects contain a unidirectional
     /// data stream that becomes unusable after it is read.
@@ -404,10 +919,8 @@ impl Pack {
     /// * `entries` - The list of entries to extract. These *must* be entries contained in the pack index.
     /// * `output_dir` - The directory relative to which the files will be extracted.
     /// * `verify` - Enable/disable checksum verification.
-    #[allow(unused_mut)]
-    #[allow(clippy::needless_collect)]
     pub fn extract_entries<P>(
-        mut self,
+        self,
         entries: &[FileEntry],
         output_dir: P,
         verify: bool,
@@ -415,10 +928,40 @@ impl Pack {
     ) -> Result<(), Error>
     where
         P: AsRef<Path> + Sync,
+    {
+        self.read_entries(entries, verify, num_workers, |entry, buf| {
+            let path = output_dir.as_ref().join(&entry.path);
+            write_object(buf, &path, &entry.obj_metadata, &entry.file_metadata)
+        })
+    }
+
+    /// Reads the objects of the specified entries from the pack and passes
+    /// their contents to `visit`, one entry at a time. Entries are visited in
+    /// no particular order, and possibly from multiple threads at once.
+    /// This operation consumes the pack, see [`Pack::extract_entries`].
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The list of entries to read. These *must* be entries contained in the pack index.
+    /// * `verify` - Enable/disable checksum verification.
+    /// * `visit` - Called with each entry and the contents of its object.
+    #[allow(clippy::needless_collect)]
+    pub fn read_entries<F>(
+        self,
+        entries: &[FileEntry],
+        verify: bool,
+        num_workers: u32,
+        visit: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&FileEntry, &[u8]) -> Result<(), Error> + Sync,
     {
         if entries.is_empty() {
             return Ok(());
         }
+        if self.is_chunked() {
+            return self.read_chunked_entries(entries, verify, num_workers, visit);
+        }
 
         let num_frames = self.header.frames.len();
         assert_ne!(0, num_workers);
@@ -450,10 +993,22 @@ impl Pack {
             bytes_to_decompress as f64 / 1024f64 / 1024f64
         );
 
+        let verify = verify.then_some(self.hash_algorithm);
+        let seeker = FrameSeeker {
+            source: self.source.as_ref(),
+            dictionary: self.header.dictionary(),
+            seek_points: &self.seek_points,
+        };
+        // The offsets of the frames in the .pack file and in the decompressed data.
+        let frames = self
+            .frame_offsets
+            .into_iter()
+            .zip(compute_frame_decompressed_offset(&self.header.frames));
         // Collect required for run_in_parallel ExactSizeIterator argument.
         let tasks = self
             .frame_readers
             .into_iter()
+            .zip(frames)
             .zip(frame_to_entries.into_iter())
             // Skip empty frames.
             .filter(|(_, entries)| !entries.is_empty())
@@ -464,7 +1019,15 @@ impl Pack {
         let results = run_in_parallel(
             num_workers as usize,
             tasks.into_iter(),
-            |(frame_reader, entries)| extract_files(frame_reader, &entries, &output_dir, verify),
+            |((frame_reader, frame), entries)| {
+                read_objects(
+                    frame_reader,
+                    &entries,
+                    verify,
+                    |reader, offset| seeker.seek(reader, frame.0, frame.1, offset),
+                    &visit,
+                )
+            },
         );
 
         // Collect stats
@@ -491,15 +1054,123 @@ impl Pack {
         );
         Ok(())
     }
+
+    /// Implements [`Pack::read_entries`] for chunked packs. The objects are
+    /// read in order by each worker, since each of them can be made of chunks
+    /// from any of the frames.
+    fn read_chunked_entries<F>(
+        self,
+        entries: &[FileEntry],
+        verify: bool,
+        num_workers: u32,
+        visit: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&FileEntry, &[u8]) -> Result<(), Error> + Sync,
+    {
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|entry| entry.obj_metadata.offset);
+
+        let entries_per_worker = entries.len().div_ceil(num_workers as usize);
+        let tasks = entries
+            .chunks(entries_per_worker)
+            .map(|entries| Ok((self.try_clone()?, entries)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        info!(
+            "Reading {} objects from chunked pack {} with {} workers...",
+            entries.len(),
+            self.name,
+            tasks.len()
+        );
+
+        let results = run_in_parallel(tasks.len(), tasks.into_iter(), |(mut pack, entries)| {
+            let mut buf = vec![];
+            for entry in entries {
+                buf.clear();
+                pack.write_object(entry, verify, &mut buf)?;
+                visit(entry, &buf)?;
+            }
+            Ok(())
+        });
+        results.into_iter().collect()
+    }
+}
+
+/// Reads an object of a chunked pack, piece by piece.
+struct ChunkReader<'a> {
+    pack: &'a mut Pack,
+    /// The pieces of the object which have not been read completely.
+    pieces: std::collections::VecDeque<Chunk>,
+    /// The number of bytes read from the first piece.
+    position: usize,
+}
+
+impl Read for ChunkReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let piece = match self.pieces.front() {
+            Some(piece) => piece.clone(),
+            None => return Ok(0),
+        };
+        let data = self
+            .pack
+            .read_chunk(&piece)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        let n = std::cmp::min(buf.len(), data.len() - self.position);
+        buf[..n].copy_from_slice(&data[self.position..self.position + n]);
+        self.position += n;
+        if self.position == data.len() {
+            self.pieces.pop_front();
+            self.position = 0;
+        }
+        Ok(n)
+    }
+}
+
+/// Copies the object of `size` bytes from `reader` to `writer` in chunks.
+/// When `verify` is set, the checksum is computed along the way with that
+/// algorithm, and checked once the whole object has been written.
+pub(super) fn copy_object(
+    reader: impl Read,
+    size: u64,
+    exp_checksum: &ObjectChecksum,
+    verify: Option<HashAlgorithm>,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let mut reader = reader.take(size);
+    let mut hasher = verify.map(HashAlgorithm::hasher);
+    let mut buf = vec![0u8; std::cmp::min(size as usize, COPY_BUFFER_SIZE)];
+    let mut copied = 0;
+    while copied < size {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&buf[..n]);
+        }
+        writer.write_all(&buf[..n])?;
+        copied += n as u64;
+    }
+
+    if let Some(hasher) = hasher {
+        let checksum = hasher.finalize();
+        if &checksum != exp_checksum {
+            return Err(PackError::ChecksumMismatch(*exp_checksum, checksum).into());
+        }
+    }
+    Ok(())
 }
 
 /// Verifies that the object has the expected checksum.
-fn verify_object(buf: &[u8], exp_checksum: &ObjectChecksum) -> Result<(), Error> {
+pub(super) fn verify_object(
+    buf: &[u8],
+    exp_checksum: &ObjectChecksum,
+    hash_algorithm: HashAlgorithm,
+) -> Result<(), Error> {
     // Verify checksum
-    let mut checksum = [0u8; 20];
-    let mut hasher = Sha1::new();
-    hasher.input(buf);
-    hasher.result(&mut checksum);
+    let checksum = hash_algorithm.checksum(buf);
     if &checksum != exp_checksum {
         return Err(PackError::ChecksumMismatch(*exp_checksum, checksum).into());
     }
@@ -598,7 +1269,7 @@ fn compute_frame_offsets(frames: &[PackFrame]) -> Vec<u64> {
 
 /// Returns a list of the data offsets, computed using the order and
 /// decompressed sizes of the given frames.
-fn compute_frame_decompressed_offset(frames: &[PackFrame]) -> Vec<u64> {
+pub(super) fn compute_frame_decompressed_offset(frames: &[PackFrame]) -> Vec<u64> {
     let mut frame_decompressed_offset: Vec<_> = vec![0; frames.len()];
     for i in 1..frame_decompressed_offset.len() {
         frame_decompressed_offset[i] =
@@ -704,14 +1375,19 @@ impl std::iter::Sum<ExtractStats> for ExtractStats {
     }
 }
 
-/// Extracts the given entries from the pack reader into the specified output directory.
+/// Reads the given entries from the pack reader and passes their contents to `visit`.
 /// Checksum verification can be toggled on/off.
-fn extract_files(
+fn read_objects<S, F>(
     mut reader: PackReader,
     entries: &[FileEntry],
-    output_dir: impl AsRef<Path>,
-    verify: bool,
-) -> Result<ExtractStats, Error> {
+    verify: Option<HashAlgorithm>,
+    seek: S,
+    visit: F,
+) -> Result<ExtractStats, Error>
+where
+    S: Fn(&mut PackReader, u64) -> Result<(), Error>,
+    F: Fn(&FileEntry, &[u8]) -> Result<(), Error>,
+{
     let mut entries: Vec<FileEntry> = entries.to_vec();
     // Sort objects to allow for forward-only seeking
     entries.sort_by(|x, y| {
@@ -725,7 +1401,6 @@ fn extract_files(
     let total_time = measure_ok(|| -> Result<(), Error> {
         // Decompression buffer
         let mut buf = vec![];
-        let mut path_buf = PathBuf::new();
         let mut pos = 0;
         for entry in entries {
             let metadata = &entry.obj_metadata;
@@ -735,9 +1410,11 @@ fn extract_files(
             // are sorted by offset, and the current position is set to the offset at the
             // end of each object, after that object is consumed.
             if pos <= metadata.offset {
-                // Seek forward
-                let discard_bytes = metadata.offset - pos;
-                stats.seek_time += measure_ok(|| reader.seek(discard_bytes))?.0.as_secs_f64();
+                // Seek forward (the reader is reopened if it was already
+                // read past the object by [`Pack::write_object`]).
+                stats.seek_time += measure_ok(|| seek(&mut reader, metadata.offset))?
+                    .0
+                    .as_secs_f64();
                 // Resize buf
                 buf.resize(metadata.size as usize, 0);
                 // Read object
@@ -745,27 +1422,15 @@ fn extract_files(
                     .0
                     .as_secs_f64();
                 pos = metadata.offset + metadata.size;
-                if verify {
-                    stats.verify_time += measure_ok(|| verify_object(&buf[..], &entry.checksum))?
-                        .0
-                        .as_secs_f64();
+                if let Some(hash_algorithm) = verify {
+                    stats.verify_time +=
+                        measure_ok(|| verify_object(&buf[..], &entry.checksum, hash_algorithm))?
+                            .0
+                            .as_secs_f64();
                 }
             }
 
-            // Output path
-            path_buf.clear();
-            path_buf.push(&output_dir);
-            path_buf.push(&entry.path);
-            stats.write_time += measure_ok(|| {
-                write_object(
-                    &buf[..],
-                    &path_buf,
-                    &entry.obj_metadata,
-                    &entry.file_metadata,
-                )
-            })?
-            .0
-            .as_secs_f64();
+            stats.write_time += measure_ok(|| visit(&entry, &buf[..]))?.0.as_secs_f64();
         }
 
         Ok(())
@@ -778,6 +1443,7 @@ fn extract_files(
 
 #[cfg(test)]
 mod tests {
+    use super::super::seektable::SeekTableEntry;
     use super::*;
 
     fn make_md(offset: u64, size: u64) -> ObjectMetadata {
@@ -880,13 +1546,13 @@ mod tests {
         let entries = [
             FileEntry::new(
                 "A".into(),
-                [0; 20],
+                [0; 20].into(),
                 make_md(50, 1),
                 make_file_md(0, 0, 0, false, "A".into()),
             ),
             FileEntry::new(
                 "B".into(),
-                [1; 20],
+                [1; 20].into(),
                 make_md(50, 1),
                 make_file_md(0, 0, 0, false, "B".into()),
             ),
@@ -910,13 +1576,13 @@ mod tests {
         let entries = [
             FileEntry::new(
                 "A".into(),
-                [0; 20],
+                [0; 20].into(),
                 make_md(800, 200),
                 make_file_md(0, 0, 0, false, "A".into()),
             ),
             FileEntry::new(
                 "B".into(),
-                [1; 20],
+                [1; 20].into(),
                 make_md(1200, 200),
                 make_file_md(0, 0, 0, false, "B".into()),
             ),
@@ -925,7 +1591,7 @@ mod tests {
             // Offset is same
             FileEntry::new(
                 "A".into(),
-                [0; 20],
+                [0; 20].into(),
                 make_md(800, 200),
                 make_file_md(0, 0, 0, false, "A".into()),
             ),
@@ -934,7 +1600,7 @@ mod tests {
             // Offset 1200 -> 200
             FileEntry::new(
                 "B".into(),
-                [1; 20],
+                [1; 20].into(),
                 make_md(200, 200),
                 make_file_md(0, 0, 0, false, "B".into()),
             ),
@@ -944,4 +1610,182 @@ mod tests {
         assert_eq!(&frame_1_entries, result[0].as_slice());
         assert_eq!(&frame_2_entries, result[1].as_slice());
     }
+
+    #[test]
+    fn copy_object_works() {
+        let data = b"0123456789";
+        let verify = Some(HashAlgorithm::Blake3);
+        let checksum = HashAlgorithm::Blake3.checksum(&data[..4]);
+
+        // Only the first `size` bytes are copied.
+        let mut out = vec![];
+        copy_object(&data[..], 4, &checksum, verify, &mut out).unwrap();
+        assert_eq!(b"0123", out.as_slice());
+
+        // Checksum mismatches are detected.
+        let mut out = vec![];
+        assert!(matches!(
+            copy_object(&data[..], 5, &checksum, verify, &mut out),
+            Err(Error::PackError(PackError::ChecksumMismatch(..)))
+        ));
+        let mut out = vec![];
+        copy_object(&data[..], 5, &checksum, None, &mut out).unwrap();
+        assert_eq!(b"01234", out.as_slice());
+
+        // Truncated objects are detected.
+        let mut out = vec![];
+        assert!(copy_object(&data[..], 11, &checksum, None, &mut out).is_err());
+    }
+
+    #[test]
+    fn pack_header_flags_roundtrip() {
+        let frames = vec![PackFrame {
+            frame_size: 10,
+            decompressed_size: 20,
+        }];
+
+        // Headers without a dictionary are the same as before dictionaries
+        // were supported: an array of the magic and the frames.
+        let mut plain = vec![];
+        let size = write_pack_header(&mut plain, &PackHeader::new(frames.clone())).unwrap();
+        assert_eq!(plain.len() as u64, size);
+        assert_eq!(0x92, plain[8]);
+
+        let mut buf = vec![];
+        let size = write_pack_header(
+            &mut buf,
+            &PackHeader::with_dictionary(frames, Some(b"dictionary".to_vec())),
+        )
+        .unwrap();
+        assert_eq!(buf.len() as u64, size);
+        assert_eq!(0x93, buf[8]);
+
+        let mut reader = &buf[..];
+        let mut header = vec![];
+        read_skippable_frame(&mut reader, &mut header).unwrap();
+        let header: PackHeader = rmp_serde::decode::from_read(&header[..]).unwrap();
+        assert!(header.has_dictionary);
+        let mut dictionary = vec![];
+        read_skippable_frame(&mut reader, &mut dictionary).unwrap();
+        assert_eq!(b"dictionary", &dictionary[..]);
+        assert!(reader.is_empty());
+
+        // The dictionary flag is kept before the seek table flag, even when
+        // there is no dictionary.
+        let mut header = PackHeader::new(vec![]);
+        header.set_has_seek_table(true);
+        let mut buf = vec![];
+        write_pack_header(&mut buf, &header).unwrap();
+        assert_eq!(0x94, buf[8]);
+        let header: PackHeader = rmp_serde::decode::from_read(&buf[8..]).unwrap();
+        assert!(!header.has_dictionary);
+        assert!(header.has_seek_table);
+    }
+
+    #[test]
+    fn pack_header_with_overflowing_sizes_is_corrupt() {
+        let frame = PackFrame {
+            frame_size: 1,
+            decompressed_size: u64::MAX,
+        };
+        let mut header = PackHeader::new(vec![frame; 2]);
+        header.set_has_seek_table(true);
+        let mut pack = vec![];
+        let header_size = write_pack_header(&mut pack, &header).unwrap();
+        pack.extend([0u8; 2]);
+        let entries = vec![
+            SeekTableEntry {
+                compressed_size: header_size as u32,
+                decompressed_size: 0,
+            },
+            SeekTableEntry {
+                compressed_size: 2,
+                decompressed_size: 0,
+            },
+        ];
+        SeekTable::new(entries).write(&mut pack).unwrap();
+
+        let source = BytesSource(Arc::new(pack));
+        assert!(matches!(
+            read_pack_header_from(&source),
+            Err(Error::CorruptPack)
+        ));
+    }
+
+    /// Builds a pack with one frame per object, and its index, in memory.
+    fn make_pack_in_memory(objects: &[&[u8]]) -> (Vec<u8>, PackIndex) {
+        let mut frames = vec![];
+        let mut frame_bufs = vec![];
+        let mut entries = vec![];
+        let mut offset = 0;
+        for (i, object) in objects.iter().enumerate() {
+            let frame_buf = zstd::encode_all(*object, 0).unwrap();
+            frames.push(PackFrame {
+                frame_size: frame_buf.len() as u64,
+                decompressed_size: object.len() as u64,
+            });
+            frame_bufs.extend(frame_buf);
+
+            let checksum = HashAlgorithm::default().checksum(object);
+            entries.push(FileEntry::new(
+                format!("file{}", i).into(),
+                checksum,
+                make_md(offset, object.len() as u64),
+                make_file_md(0, 0, 0o644, false, PathBuf::new()),
+            ));
+            offset += object.len() as u64;
+        }
+
+        let mut pack = vec![];
+        write_pack_header(&mut pack, &PackHeader::new(frames)).unwrap();
+        pack.extend(frame_bufs);
+        let mut index = PackIndex::new();
+        index.push_snapshot("snapshot".to_owned(), entries).unwrap();
+        (pack, index)
+    }
+
+    #[test]
+    fn pack_from_bytes_and_reader_works() {
+        let objects: [&[u8]; 3] = [b"first object", b"second object", b"third object"];
+        let (pack_bytes, index) = make_pack_in_memory(&objects);
+
+        // The index survives a roundtrip through any writer and reader.
+        let mut index_bytes = vec![];
+        index.write(&mut index_bytes).unwrap();
+        let index = PackIndex::parse(&index_bytes[..]).unwrap();
+        let mut entries = index
+            .entries_from_handles(index.resolve_snapshot("snapshot").unwrap().iter())
+            .unwrap();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let read_all = |pack: Pack| {
+            let contents = Mutex::new(vec![]);
+            pack.read_entries(&entries, true, 2, |entry, buf| {
+                contents
+                    .lock()
+                    .unwrap()
+                    .push((entry.path.clone(), buf.to_vec()));
+                Ok(())
+            })
+            .unwrap();
+            let mut contents = contents.into_inner().unwrap();
+            contents.sort();
+            contents.into_iter().map(|(_, buf)| buf).collect::<Vec<_>>()
+        };
+
+        let mut pack = Pack::from_bytes("in-memory", pack_bytes.clone(), &index).unwrap();
+        assert_eq!(pack_bytes.len() as u64, pack.file_size());
+        let mut out = vec![];
+        pack.write_object(&entries[1], true, &mut out).unwrap();
+        assert_eq!(objects[1], &out[..]);
+        assert_eq!(objects.to_vec(), read_all(pack));
+
+        let reader = io::Cursor::new(pack_bytes);
+        let mut pack = Pack::from_reader("in-memory", reader, &index).unwrap();
+        let mut out = vec![];
+        pack.write_object(&entries[2], true, &mut out).unwrap();
+        pack.write_object(&entries[0], true, &mut out).unwrap();
+        assert_eq!(b"third objectfirst object", &out[..]);
+        assert_eq!(objects.to_vec(), read_all(pack));
+    }
 }
@@ -0,0 +1,177 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+//! The reflog: a history of the snapshots HEAD pointed to, which lets
+//! `@{<n>}` refer to the n-th previous one.
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    str::FromStr,
+};
+
+use chrono::{offset::Utc, SecondsFormat};
+use log::warn;
+
+use super::constants::{HEAD_FILE, LOGS_DIR};
+use super::error::Error;
+use super::fs::ensure_dir;
+use super::pack::SnapshotId;
+use super::repository::Repository;
+
+/// A change of HEAD, as recorded in `elfshaker_data/logs/HEAD`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReflogEntry {
+    /// When HEAD was changed, in RFC 3339 format.
+    pub time: String,
+    /// The snapshot HEAD pointed to before, if any.
+    pub old: Option<SnapshotId>,
+    /// The snapshot HEAD points to since.
+    pub new: SnapshotId,
+    /// The operation which changed HEAD (e.g. store or extract).
+    pub operation: String,
+}
+
+impl ReflogEntry {
+    /// Each entry is a line of tab-separated fields, which cannot appear in
+    /// snapshot ids.
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            self.time,
+            self.old
+                .as_ref()
+                .map_or_else(|| "-".to_owned(), |s| s.to_string()),
+            self.new,
+            self.operation.replace('\n', " "),
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, '\t');
+        let time = fields.next()?.to_owned();
+        let old = match fields.next()? {
+            "-" => None,
+            old => Some(SnapshotId::from_str(old).ok()?),
+        };
+        let new = SnapshotId::from_str(fields.next()?).ok()?;
+        let operation = fields.next()?.to_owned();
+        Some(Self {
+            time,
+            old,
+            new,
+            operation,
+        })
+    }
+}
+
+/// Parses `@{<n>}` or `HEAD@{<n>}`, which refers to the snapshot HEAD pointed
+/// to `n` changes ago.
+pub fn parse_reflog_selector(s: &str) -> Option<usize> {
+    s.strip_prefix(HEAD_FILE)
+        .unwrap_or(s)
+        .strip_prefix("@{")?
+        .strip_suffix('}')?
+        .parse()
+        .ok()
+}
+
+impl Repository {
+    /// Reads the changes of HEAD, from oldest to newest.
+    pub fn reflog(&self) -> Result<Vec<ReflogEntry>, Error> {
+        let text = match fs::read_to_string(self.data_dir().join(LOGS_DIR).join(HEAD_FILE)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            result => result?,
+        };
+        Ok(text
+            .lines()
+            .filter_map(|line| {
+                let entry = ReflogEntry::from_line(line);
+                if entry.is_none() {
+                    warn!("Skipping corrupt reflog entry: {}", line);
+                }
+                entry
+            })
+            .collect())
+    }
+
+    /// Returns the snapshot HEAD pointed to `n` changes ago (`@{<n>}`), where
+    /// `@{0}` is the current HEAD.
+    pub fn resolve_reflog(&self, n: usize) -> Result<SnapshotId, Error> {
+        let reflog = self.reflog()?;
+        let snapshot = if reflog.is_empty() {
+            // HEAD was last changed before the reflog was kept.
+            (n == 0)
+                .then(|| self.read_head())
+                .transpose()?
+                .and_then(|h| h.0)
+        } else if n < reflog.len() {
+            Some(reflog[reflog.len() - 1 - n].new.clone())
+        } else if n == reflog.len() {
+            reflog[0].old.clone()
+        } else {
+            None
+        };
+        snapshot.ok_or_else(|| Error::RefNotFound(format!("@{{{}}}", n)))
+    }
+
+    /// Appends the change of HEAD from `old` to `new` to the reflog.
+    pub(super) fn append_reflog(
+        &self,
+        old: Option<SnapshotId>,
+        new: &SnapshotId,
+        operation: &str,
+    ) -> Result<(), Error> {
+        let entry = ReflogEntry {
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            old,
+            new: new.clone(),
+            operation: operation.to_owned(),
+        };
+        let logs_dir = self.data_dir().join(LOGS_DIR);
+        ensure_dir(&logs_dir)?;
+        // A single write of a small line, so concurrent appends do not interleave.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(logs_dir.join(HEAD_FILE))?
+            .write_all(entry.to_line().as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reflog_entries_roundtrip() {
+        let entry = ReflogEntry {
+            time: "2021-01-01T00:00:00Z".to_owned(),
+            old: None,
+            new: SnapshotId::from_str("loose/a b:a b").unwrap(),
+            operation: "store".to_owned(),
+        };
+        let line = entry.to_line();
+        assert_eq!(
+            Some(&entry),
+            ReflogEntry::from_line(line.trim_end()).as_ref()
+        );
+
+        let entry = ReflogEntry {
+            old: Some(SnapshotId::from_str("pack:a").unwrap()),
+            ..entry
+        };
+        let line = entry.to_line();
+        assert_eq!(Some(entry), ReflogEntry::from_line(line.trim_end()));
+        assert_eq!(None, ReflogEntry::from_line("garbage"));
+    }
+
+    #[test]
+    fn reflog_selectors_are_parsed() {
+        assert_eq!(Some(0), parse_reflog_selector("@{0}"));
+        assert_eq!(Some(12), parse_reflog_selector("HEAD@{12}"));
+        assert_eq!(None, parse_reflog_selector("@{-1}"));
+        assert_eq!(None, parse_reflog_selector("tag@{1}"));
+        assert_eq!(None, parse_reflog_selector("@{1"));
+    }
+}
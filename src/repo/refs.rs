@@ -0,0 +1,128 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+//! Named refs: movable pointers to snapshots, stored as `pack:tag` in
+//! `elfshaker_data/refs/<name>`.
+use std::{fs, io, path::Path, str::FromStr};
+
+use walkdir::WalkDir;
+
+use super::constants::{HEAD_FILE, REFS_DIR, TEMP_DIR};
+use super::error::Error;
+use super::fs::{ensure_dir, write_file_atomic};
+use super::pack::SnapshotId;
+use super::repository::Repository;
+
+/// Ref names follow the rules of pack names: any chars except `<>:"|?*` and
+/// control characters, with `/` separating non-empty components other than
+/// `.` and `..`. `HEAD` is reserved for the extracted snapshot.
+pub fn is_valid_ref_name(name: &str) -> bool {
+    name != HEAD_FILE
+        && !name.contains('\\')
+        && name
+            .split('/')
+            .all(|c| !c.is_empty() && c != "." && c != "..")
+        && SnapshotId::from_str(&format!("{}:tag", name)).is_ok()
+}
+
+impl Repository {
+    /// Lists the refs and the snapshots they point to, sorted by name.
+    pub fn refs(&self) -> Result<Vec<(String, SnapshotId)>, Error> {
+        let root = self.data_dir().join(REFS_DIR);
+        if !root.exists() {
+            return Ok(vec![]);
+        }
+        let mut refs = vec![];
+        for entry in WalkDir::new(&root).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = entry
+                .path()
+                .strip_prefix(&root)
+                .unwrap() // has prefix by construction.
+                .components()
+                .map(|c| c.as_os_str().to_str())
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| Error::Utf8Error(entry.path().as_os_str().to_owned()))?
+                .join("/");
+            let snapshot = self.read_ref_file(&name, entry.path())?;
+            refs.push((name, snapshot));
+        }
+        Ok(refs)
+    }
+
+    /// Returns the snapshot the ref points to, or [`None`] if there is no
+    /// such ref.
+    pub fn read_ref(&self, name: &str) -> Result<Option<SnapshotId>, Error> {
+        if !is_valid_ref_name(name) {
+            return Err(Error::InvalidRefName(name.to_owned()));
+        }
+        let path = self.data_dir().join(REFS_DIR).join(name);
+        if path.is_dir() {
+            // Only groups other refs, e.g. nightly for nightly/x86_64.
+            return Ok(None);
+        }
+        match self.read_ref_file(name, &path) {
+            Err(Error::IOError(e)) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    /// Points the ref to the snapshot, creating the ref if needed.
+    pub fn set_ref(&self, name: &str, snapshot: &SnapshotId) -> Result<(), Error> {
+        if !is_valid_ref_name(name) {
+            return Err(Error::InvalidRefName(name.to_owned()));
+        }
+        let path = self.data_dir().join(REFS_DIR).join(name);
+        ensure_dir(path.parent().unwrap())?;
+        let temp_dir = self.data_dir().join(TEMP_DIR);
+        ensure_dir(&temp_dir)?;
+        write_file_atomic(format!("{}\n", snapshot).as_bytes(), &temp_dir, &path)?;
+        Ok(())
+    }
+
+    /// Deletes the ref, along with the directories left empty by it.
+    pub fn delete_ref(&self, name: &str) -> Result<(), Error> {
+        if !is_valid_ref_name(name) {
+            return Err(Error::InvalidRefName(name.to_owned()));
+        }
+        let root = self.data_dir().join(REFS_DIR);
+        let path = root.join(name);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::RefNotFound(name.to_owned()))
+            }
+            result => result?,
+        }
+        for dir in path.ancestors().skip(1).take_while(|dir| *dir != root) {
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_ref_file(&self, name: &str, path: &Path) -> Result<SnapshotId, Error> {
+        let text = fs::read_to_string(path)?;
+        SnapshotId::from_str(text.trim_end()).map_err(|_| Error::CorruptRef(name.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ref_names_are_validated() {
+        for name in ["known-good", "nightly/x86_64", "v1.0"] {
+            assert!(is_valid_ref_name(name), "{}", name);
+        }
+        for name in [
+            "", "HEAD", "a:b", "a//b", "/a", "a/", "../a", "a/./b", "a\\b",
+        ] {
+            assert!(!is_valid_ref_name(name), "{}", name);
+        }
+    }
+}
@@ -1,5 +1,4 @@
 use std::{
-    convert::TryInto,
     ffi::OsStr,
     fs, io,
     io::{BufRead, BufReader, Read, Write},
@@ -8,15 +7,13 @@ use std::{
 };
 
 use chrono::{offset::Utc, DateTime};
-use crypto::digest::Digest;
-use crypto::sha1::Sha1;
 use ureq::Agent;
 use url::Url;
 
 use super::constants::{PACK_EXTENSION, REMOTE_INDEX_EXTENSION};
 use super::error::Error;
 use super::fs::{create_file, open_file};
-use crate::packidx::{ObjectChecksum, PackIndex};
+use crate::packidx::{HashAlgorithm, ObjectChecksum, PackIndex};
 use crate::progress::{ProgressReporter, ProgressWriter};
 
 const HTTP_STATUS_OK: u16 = 200;
@@ -77,10 +74,34 @@ impl<T> ReifyRemoteResult for Result<T, Error> {
     }
 }
 
+/// The checksum of a file listed in a .esi, written as `<algorithm>:<hex>`,
+/// or just `<hex>` for SHA-1 checksums.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileChecksum {
+    pub hash_algorithm: HashAlgorithm,
+    pub checksum: ObjectChecksum,
+}
+
+impl FileChecksum {
+    /// Returns [`None`] if the algorithm is unknown, or if the checksum is
+    /// not valid hex of the size computed by the algorithm.
+    fn parse(s: &str) -> Option<Self> {
+        let (hash_algorithm, checksum) = match s.split_once(':') {
+            Some((name, checksum)) => (name.parse().ok()?, checksum),
+            None => (HashAlgorithm::Sha1, s),
+        };
+        let checksum = ObjectChecksum::from_slice(&hex::decode(checksum).ok()?)?;
+        (checksum.len() == hash_algorithm.checksum_size()).then_some(Self {
+            hash_algorithm,
+            checksum,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct RemotePack {
-    pub index_checksum: ObjectChecksum,
-    pub pack_checksum: ObjectChecksum,
+    pub index_checksum: FileChecksum,
+    pub pack_checksum: FileChecksum,
     pub url: String,
 }
 
@@ -203,35 +224,16 @@ impl RemoteIndex {
                 .into());
             }
 
-            let index_checksum = hex::decode(index_checksum)
-                .map_err(|_| {
-                    RemoteIndexFormatError::new(format!(
-                        "Bad pack index checksum format on line {}",
-                        line_no
-                    ))
-                })?
-                .try_into()
-                .map_err(|_| {
-                    RemoteIndexFormatError::new(format!(
-                        "The value for pack index checksum on line {} is not the right length",
-                        line_no
-                    ))
-                })?;
+            let index_checksum = FileChecksum::parse(index_checksum).ok_or_else(|| {
+                RemoteIndexFormatError::new(format!(
+                    "Bad pack index checksum format on line {}",
+                    line_no
+                ))
+            })?;
 
-            let pack_checksum = hex::decode(pack_checksum)
-                .map_err(|_| {
-                    RemoteIndexFormatError::new(format!(
-                        "Bad pack checksum format on line {}",
-                        line_no
-                    ))
-                })?
-                .try_into()
-                .map_err(|_| {
-                    RemoteIndexFormatError::new(format!(
-                        "The value for pack checksum on line {} is not the right length",
-                        line_no
-                    ))
-                })?;
+            let pack_checksum = FileChecksum::parse(pack_checksum).ok_or_else(|| {
+                RemoteIndexFormatError::new(format!("Bad pack checksum format on line {}", line_no))
+            })?;
 
             packs.push(RemotePack {
                 url: absolute_url.as_str().to_owned(),
@@ -400,12 +402,8 @@ pub fn update_remote_pack(
         let mut writer = ProgressWriter::with_known_size(&mut data, reporter, content_length);
         io::copy(&mut reader, &mut writer)?;
 
-        let mut checksum = [0u8; 20];
-        let mut hasher = Sha1::new();
-        hasher.input(&data);
-        hasher.result(&mut checksum);
-
-        if checksum == remote_pack.pack_checksum {
+        let checksum = remote_pack.pack_checksum.hash_algorithm.checksum(&data);
+        if checksum == remote_pack.pack_checksum.checksum {
             create_file(pack_path)?.write_all(&data)?;
         } else {
             log::error!(
@@ -531,31 +529,16 @@ pub fn update_remote(agent: &Agent, remote: &RemoteIndex) -> Result<RemoteIndex,
 
 /// A convenience function which verifies the checksum of the file
 /// and coerces ENOENT to false.
-fn verify_checksum(path: &Path, checksum: &ObjectChecksum) -> io::Result<bool> {
-    match compute_checksum(path) {
-        Ok(actual_checksum) => Ok(actual_checksum == *checksum),
+fn verify_checksum(path: &Path, checksum: &FileChecksum) -> io::Result<bool> {
+    match compute_checksum(path, checksum.hash_algorithm) {
+        Ok(actual_checksum) => Ok(actual_checksum == checksum.checksum),
         Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
         Err(e) => Err(e),
     }
 }
 
-fn compute_checksum(path: &Path) -> io::Result<ObjectChecksum> {
-    let mut reader = io::BufReader::new(fs::File::open(path)?);
-    let mut sha1 = Sha1::new();
-    loop {
-        let buf = reader.fill_buf()?;
-        let len = buf.len();
-        if len == 0 {
-            break;
-        }
-        sha1.input(buf);
-        reader.consume(len);
-    }
-
-    let mut checksum = [0u8; 20];
-    sha1.result(&mut checksum);
-
-    Ok(checksum)
+fn compute_checksum(path: &Path, hash_algorithm: HashAlgorithm) -> io::Result<ObjectChecksum> {
+    hash_algorithm.checksum_reader(fs::File::open(path)?)
 }
 
 /// Formats a [`SystemTime`] as an HTTP date string. HTTP dates are always in
@@ -602,11 +585,11 @@ f3a50129b7ac872b63585f884f1a73e013d51f85\td8a41c1859d6276f0dd74fdd7e4513d89f6860
             "https://gitlab.com/elfshaker/releases/download/A.pack"
         );
         assert_eq!(
-            r.packs[0].index_checksum.to_vec(),
+            r.packs[0].index_checksum.checksum.to_vec(),
             hex::decode(b"90765d432f15eda9b42e0ed747ceaa9b5f8237de").unwrap()
         );
         assert_eq!(
-            r.packs[0].pack_checksum.to_vec(),
+            r.packs[0].pack_checksum.checksum.to_vec(),
             hex::decode(b"3fc6c1b427b19217cdd9c4eecf0c74943fa4adb2").unwrap()
         );
         assert_eq!(
@@ -614,17 +597,48 @@ f3a50129b7ac872b63585f884f1a73e013d51f85\td8a41c1859d6276f0dd74fdd7e4513d89f6860
             "https://github.com/elfshaker/releases/download/B.pack"
         );
         assert_eq!(
-            r.packs[1].index_checksum.to_vec(),
+            r.packs[1].index_checksum.checksum.to_vec(),
             hex::decode(b"f3a50129b7ac872b63585f884f1a73e013d51f85").unwrap()
         );
         assert_eq!(
-            r.packs[1].pack_checksum.to_vec(),
+            r.packs[1].pack_checksum.checksum.to_vec(),
             hex::decode(b"d8a41c1859d6276f0dd74fdd7e4513d89f68600f").unwrap()
         );
 
         Ok(())
     }
 
+    #[test]
+    fn test_remote_index_checksum_algorithms_read_works() -> Result<(), Error> {
+        let r = RemoteIndex::read(BufReader::new(
+            "\
+meta\tv1
+url\thttps://github.com/elfshaker/releases/download/index.esi
+blake3:6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85 \
+sha1:3fc6c1b427b19217cdd9c4eecf0c74943fa4adb2\tA.pack"
+                .as_bytes(),
+        ))?;
+
+        assert_eq!(r.packs.len(), 1);
+        assert_eq!(
+            r.packs[0].index_checksum,
+            FileChecksum {
+                hash_algorithm: HashAlgorithm::Blake3,
+                checksum: HashAlgorithm::Blake3.checksum(b"abc"),
+            }
+        );
+        assert_eq!(HashAlgorithm::Sha1, r.packs[0].pack_checksum.hash_algorithm);
+
+        // The checksum must have the size computed by the algorithm.
+        RemoteIndex::read(BufReader::new(
+            "meta\tv1\nurl\thttps://\nsha256:3fc6c1b427b19217cdd9c4eecf0c74943fa4adb2 \
+            3fc6c1b427b19217cdd9c4eecf0c74943fa4adb2\tA.pack"
+                .as_bytes(),
+        ))
+        .unwrap_err();
+        Ok(())
+    }
+
     #[test]
     fn test_remote_index_no_packs_read_works() -> Result<(), Error> {
         let r = RemoteIndex::read(BufReader::new(
@@ -3,35 +3,50 @@
 
 use super::{constants::*, pack::IdError};
 
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::io::Cursor;
 use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
+    convert::TryInto,
     fs, io,
     io::{Read, Write},
+    ops::ControlFlow,
     path::{Path, PathBuf},
     str::FromStr,
     time::SystemTime,
 };
 
+use chrono::{offset::Utc, SecondsFormat};
 use filetime::{set_file_mtime, FileTime};
 
-use crypto::digest::Digest;
-use crypto::sha1::Sha1;
 use log::{error, info, warn};
 use walkdir::WalkDir;
 
-use super::algo::run_in_parallel;
+use super::algo::{partition_by_u64, run_in_parallel};
+use super::chunk::{similarity_hash, ChunkWriter};
 use super::constants::REPO_DIR;
 use super::error::Error;
 use super::fs::{
     create_file, create_temp_path, ensure_dir, get_last_modified, open_file, write_file_atomic,
     EmptyDirectoryCleanupQueue,
 };
-use super::pack::{write_skippable_frame, Pack, PackFrame, PackHeader, PackId, SnapshotId};
+use super::navigation::split_snapshot_offset;
+use super::pack::{
+    copy_object, read_pack_header, write_pack_header, Pack, PackFrame, PackHeader, PackId,
+    SnapshotId,
+};
+use super::reflog::parse_reflog_selector;
+use super::refs::is_valid_ref_name;
 use super::remote;
-use crate::packidx::{FileEntry, FileMetadata, ObjectChecksum, PackError, PackIndex};
+use super::seektable::{SeekTable, SeekTableEntry};
+use super::sparse::SparseSpec;
+use crate::atomicfile::AtomicCreateFile;
+use crate::entrypool::Handle;
+use crate::packidx::{
+    Attributes, FileEntry, FileMetadata, HashAlgorithm, ObjectChecksum, PackError, PackIndex,
+    CREATED_ATTRIBUTE,
+};
 use crate::progress::ProgressReporter;
 use crate::repo;
 use crate::{
@@ -55,6 +70,10 @@ pub mod repo_bridge {
         force: bool,
         /// Number of decompression threads (this is an upper-limit).
         num_workers: u32,
+        /// Only extract the files matching one of these glob patterns (all files if empty).
+        include: Vec<String>,
+        /// Do not extract the files matching one of these glob patterns.
+        exclude: Vec<String>,
     }
 
     /// A struct specifying the the packing options.
@@ -64,6 +83,42 @@ pub mod repo_bridge {
         pub compression_level: i32,
         pub num_workers: u32,
         pub num_frames: u32,
+        /// Split the objects into content-defined chunks, and store each
+        /// distinct chunk only once, so that similar objects in different
+        /// frames share their common data.
+        pub chunk_objects: bool,
+        /// The order in which the objects are laid out in the frames.
+        pub ordering: ObjectOrdering,
+        /// Train a Zstandard dictionary on a sample of the objects, and
+        /// compress all the frames with it. This helps when the frames are
+        /// small, since each of them starts without any history.
+        pub train_dictionary: bool,
+        /// Add the snapshots to an existing pack, see [`Repository::append_to_pack`].
+        pub append: bool,
+        /// If non-zero, start a new Zstandard frame after this many bytes of
+        /// input, and list the frames in a seek table at the end of the
+        /// pack, so that objects can be read without decompressing their
+        /// frame from the start (0 to disable).
+        pub seek_interval: u64,
+    }
+
+    /// The order in which [`Repository::create_pack`] lays out the objects.
+    /// Objects which are close to each other compress better, since they
+    /// are likely to share data, and they are likely to end up in the same
+    /// frame.
+    #[derive(Debug)]
+    pub enum ObjectOrdering {
+        /// Sort the objects by size, which happens to put similar objects
+        /// next to each other.
+        Size,
+        /// Sort the objects by the path of the first file using them, so
+        /// that the versions of each file across snapshots are adjacent.
+        Path,
+        /// Sort the objects by a fingerprint of their contents, which is
+        /// likely to be the same for objects sharing much of their data.
+        Similarity,
+        /// Keep the objects in the order of the first snapshot using them.
+        Snapshot,
     }
 
     #[derive(Clone, Debug)]
@@ -76,6 +131,38 @@ pub mod repo_bridge {
 
 pub use repo_bridge::*;
 
+impl ObjectOrdering {
+    /// The names of the orderings, as accepted by [`ObjectOrdering::from_str`].
+    pub const NAMES: &'static [&'static str] = &["size", "path", "similarity", "snapshot"];
+}
+
+impl FromStr for ObjectOrdering {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "size" => Ok(Self::Size),
+            "path" => Ok(Self::Path),
+            "similarity" => Ok(Self::Similarity),
+            "snapshot" => Ok(Self::Snapshot),
+            _ => Err(format!("Unknown object ordering '{}'!", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for ObjectOrdering {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match *self {
+            Self::Size => "size",
+            Self::Path => "path",
+            Self::Similarity => "similarity",
+            Self::Snapshot => "snapshot",
+            _ => "unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 impl ExtractOptions {
     /// Toggle checksum verification.
     pub fn verify(&self) -> bool {
@@ -109,6 +196,26 @@ impl ExtractOptions {
     pub fn set_num_workers(&mut self, value: u32) {
         self.num_workers = value;
     }
+    /// Only extract the files matching one of these glob patterns (all files if empty).
+    pub fn include(&self) -> &[String] {
+        &self.include
+    }
+    /// Only extract the files matching one of these glob patterns (all files if empty).
+    pub fn set_include(&mut self, value: Vec<String>) {
+        self.include = value;
+    }
+    /// Do not extract the files matching one of these glob patterns.
+    pub fn exclude(&self) -> &[String] {
+        &self.exclude
+    }
+    /// Do not extract the files matching one of these glob patterns.
+    pub fn set_exclude(&mut self, value: Vec<String>) {
+        self.exclude = value;
+    }
+    /// The files to extract, as selected by [`Self::include`] and [`Self::exclude`].
+    pub fn sparse_spec(&self) -> SparseSpec {
+        SparseSpec::new(self.include.clone(), self.exclude.clone())
+    }
 }
 
 impl Default for ExtractOptions {
@@ -122,8 +229,94 @@ impl Default for ExtractOptions {
             force: false,
             // Default to single-thread decompression.
             num_workers: 1,
+            // Extract all files by default.
+            include: vec![],
+            exclude: vec![],
+        }
+    }
+}
+
+/// The result of a [`Repository::gc`] run.
+#[derive(Clone, Debug, Default)]
+pub struct GcReport {
+    /// The number of loose objects not referenced by any loose snapshot.
+    pub removed_object_count: u32,
+    /// The number of stale temporary files.
+    pub removed_temp_file_count: u32,
+    /// The total size of the removed files, in bytes.
+    pub reclaimed_bytes: u64,
+}
+
+/// The data compressed into one frame by [`Repository::create_pack`].
+enum FrameInput<'a> {
+    /// The objects with these handles, one after another.
+    Objects(&'a [Handle]),
+    /// The range (offset and size) of the chunks in this file, see
+    /// [`PackOptions::chunk_objects`].
+    Chunks(&'a Path, u64, u64),
+}
+
+/// A frame compressed by [`Repository::compress_frames`].
+struct CompressedFrame {
+    frame: PackFrame,
+    /// The Zstandard frames making up the frame, see
+    /// [`PackOptions::seek_interval`].
+    zstd_frames: Vec<batch::FrameSize>,
+    /// The temporary file containing the frame.
+    path: PathBuf,
+}
+
+/// Writes the frames stored in the temporary files created by
+/// [`Repository::compress_frames`] one after another, and removes the files.
+fn write_frame_files(mut writer: impl Write, frames: &[CompressedFrame]) -> io::Result<()> {
+    for frame in frames {
+        io::copy(&mut open_file(&frame.path)?, &mut writer)?;
+        fs::remove_file(&frame.path)?;
+    }
+    Ok(())
+}
+
+/// Builds the seek table of a pack with a header of the given size, followed
+/// by the Zstandard frames. Returns [`None`] if the size of one of the
+/// frames does not fit in the seek table.
+fn build_seek_table(
+    header_size: u64,
+    zstd_frames: impl Iterator<Item = batch::FrameSize>,
+) -> Option<SeekTable> {
+    let header_entry = SeekTableEntry {
+        compressed_size: header_size.try_into().ok()?,
+        decompressed_size: 0,
+    };
+    let mut entries = vec![header_entry];
+    for frame in zstd_frames {
+        entries.push(SeekTableEntry {
+            compressed_size: frame.compressed.try_into().ok()?,
+            decompressed_size: frame.decompressed.try_into().ok()?,
+        });
+    }
+    Some(SeekTable::new(entries))
+}
+
+/// Encodes the header of a pack, and builds its seek table if the sizes of
+/// the Zstandard frames are given. The header records whether the pack has a
+/// seek table, so it is encoded without one if the frames do not fit in it.
+fn encode_pack_header(
+    mut header: PackHeader,
+    zstd_frames: Option<Vec<batch::FrameSize>>,
+) -> io::Result<(Vec<u8>, Option<SeekTable>)> {
+    let mut header_bytes = vec![];
+    if let Some(zstd_frames) = zstd_frames {
+        header.set_has_seek_table(true);
+        write_pack_header(&mut header_bytes, &header)?;
+        match build_seek_table(header_bytes.len() as u64, zstd_frames.into_iter()) {
+            Some(seek_table) => return Ok((header_bytes, Some(seek_table))),
+            None => warn!("The frames are too large for a seek table, not writing one!"),
         }
+        header.set_has_seek_table(false);
+        header_bytes.clear();
     }
+    write_pack_header(&mut header_bytes, &header)?;
+    Ok((header_bytes, None))
 }
 
 /// Contains methods for interfacing with elfshaker repositories, including
@@ -207,6 +400,8 @@ impl Repository {
                 file.read_to_end(&mut buf)?;
 
                 let text = std::str::from_utf8(&buf).map_err(|_| Error::CorruptHead)?;
+                // Any lines following the snapshot hold the sparse spec.
+                let text = text.lines().next().unwrap_or_default();
                 let snapshot = SnapshotId::from_str(text).map_err(|_| Error::CorruptHead)?;
                 (Some(snapshot), time)
             }
@@ -223,6 +418,17 @@ impl Repository {
         Ok((head, mtime))
     }
 
+    /// Reads the [`SparseSpec`] which was used to extract HEAD. The spec is
+    /// empty if all files were extracted or nothing has been extracted so far.
+    pub fn read_head_sparse_spec(&self) -> Result<SparseSpec, Error> {
+        match fs::read_to_string(self.data_dir.join(HEAD_FILE)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SparseSpec::default()),
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => Err(Error::CorruptHead),
+            Err(e) => Err(e.into()),
+            Ok(text) => SparseSpec::from_head_lines(text.lines().skip(1)),
+        }
+    }
+
     /// The base path of the repository.
     pub fn path(&self) -> &Path {
         &self.path
@@ -274,6 +480,32 @@ impl Repository {
         })
     }
 
+    /// The hash algorithm of new snapshots, unless another one is selected:
+    /// the algorithm of the snapshot at HEAD, or else of the most recent loose
+    /// snapshot or pack, so that new snapshots can be packed with the existing
+    /// ones. Empty repositories use [`HashAlgorithm::default`].
+    pub fn default_hash_algorithm(&self) -> Result<HashAlgorithm, Error> {
+        let head = self.read_head()?.0.map(|head| head.pack().clone());
+        let mut packs = self.packs()?;
+        packs.retain(|p| !self.is_pack_loose(p));
+        let candidates = head
+            .into_iter()
+            .chain(self.loose_packs()?.into_iter().rev())
+            .chain(packs.into_iter().rev());
+        for pack_id in candidates {
+            let PackId::Pack(name) = &pack_id;
+            let pack_index_path = self
+                .data_dir
+                .join(PACKS_DIR)
+                .join(format!("{}.{}", name, PACK_INDEX_EXTENSION));
+            // HEAD can point to a loose snapshot which was packed since.
+            if pack_index_path.exists() {
+                return Ok(PackIndex::load_only_hash_algorithm(pack_index_path)?);
+            }
+        }
+        Ok(HashAlgorithm::default())
+    }
+
     fn pack_index_mtime(&self, pack_id: &PackId) -> Result<SystemTime, Error> {
         let pack_index_path = match pack_id {
             PackId::Pack(name) => self
@@ -286,11 +518,53 @@ impl Repository {
     }
 
     // Find snapshot parses a [pack_id:]snapshot_tag string, where the pack_id
-    // is optional. If pack_id is specified, it is an error if the snapshot is
-    // not found or is found in more than one pack.
+    // is optional. If pack_id is not specified, the string can also name a ref
+    // (see [`Self::refs`]), HEAD or a previous HEAD (`@{<n>}`, see
+    // [`Self::resolve_reflog`]), which take precedence over snapshot tags.
+    // Otherwise, it is an error if the snapshot is not found or is found in
+    // more than one pack.
+    //
+    // The snapshot can be followed by offsets in the order of the snapshots
+    // (see [`Self::snapshot_order`]), such as `tag~1` or `tag^+3`, and the
+    // snapshot tag can be `@first` or `@last` (e.g. `pack:@last`).
     pub fn find_snapshot(&self, maybe_canonical_snapshot_tag: &str) -> Result<SnapshotId, Error> {
+        if let Some((base, offset)) = split_snapshot_offset(maybe_canonical_snapshot_tag) {
+            match self.find_snapshot_without_offset(base) {
+                Ok(base) => return self.offset_snapshot(&base, offset),
+                // Snapshot tags can also end with what looks like an offset.
+                Err(Error::PackError(PackError::SnapshotNotFound(_))) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.find_snapshot_without_offset(maybe_canonical_snapshot_tag)
+    }
+
+    fn find_snapshot_without_offset(
+        &self,
+        maybe_canonical_snapshot_tag: &str,
+    ) -> Result<SnapshotId, Error> {
         if let Ok(s) = SnapshotId::from_str(maybe_canonical_snapshot_tag) {
-            Ok(s) // Given string specified the pack.
+            // Given string specified the pack.
+            self.resolve_pack_position(s)
+        } else if let Some(n) = parse_reflog_selector(maybe_canonical_snapshot_tag) {
+            self.resolve_reflog(n)
+        } else if maybe_canonical_snapshot_tag == HEAD_FILE {
+            self.read_head()?
+                .0
+                .ok_or_else(|| Error::RefNotFound(HEAD_FILE.to_owned()))
+        } else if let Some(s) = is_valid_ref_name(maybe_canonical_snapshot_tag)
+            .then(|| self.read_ref(maybe_canonical_snapshot_tag))
+            .transpose()?
+            .flatten()
+        {
+            // A ref does not hide the snapshots with the same tag.
+            let name = maybe_canonical_snapshot_tag;
+            let packs = self.packs_with_snapshot(name)?;
+            if packs.iter().all(|pack| pack == s.pack() && s.tag() == name) {
+                Ok(s)
+            } else {
+                Err(Error::AmbiguousRef(name.to_owned(), s, packs))
+            }
         } else {
             // Search packs.
             let tag = maybe_canonical_snapshot_tag;
@@ -303,16 +577,7 @@ impl Repository {
     /// with the given name, or if there is more than one pack with the given
     /// name (in which case the snapshot is ambiguous).
     pub fn find_pack_with_snapshot(&self, snapshot: &str) -> Result<PackId, Error> {
-        let packs = self
-            .packs()?
-            .into_iter()
-            .filter_map(|pack_id| {
-                self.load_index_snapshots(&pack_id)
-                    .map(|idx| idx.iter().any(|x| x == snapshot).then(|| pack_id))
-                    .transpose()
-            })
-            .collect::<Result<Vec<PackId>, Error>>()?;
-
+        let packs = self.packs_with_snapshot(snapshot)?;
         match packs.len() {
             0 => Err(Error::PackError(PackError::SnapshotNotFound(
                 snapshot.to_owned(),
@@ -322,6 +587,18 @@ impl Repository {
         }
     }
 
+    /// Returns the packs which contain a snapshot with the given name.
+    fn packs_with_snapshot(&self, snapshot: &str) -> Result<Vec<PackId>, Error> {
+        self.packs()?
+            .into_iter()
+            .filter_map(|pack_id| {
+                self.load_index_snapshots(&pack_id)
+                    .map(|idx| idx.iter().any(|x| x == snapshot).then(|| pack_id))
+                    .transpose()
+            })
+            .collect()
+    }
+
     pub fn is_pack(&self, pack_id: &str) -> Result<Option<PackId>, IdError> {
         let pack_index_file_name = format!("{pack_id}.{}", PACK_INDEX_EXTENSION);
         let pack_index_path = self
@@ -367,6 +644,15 @@ impl Repository {
         Ok(PackIndex::load(pack_index_path)?)
     }
 
+    /// Loads the list of files in the snapshot.
+    pub fn load_snapshot_entries(&self, snapshot: &SnapshotId) -> Result<Vec<FileEntry>, Error> {
+        let index = self.load_index(snapshot.pack())?;
+        let handles = index
+            .resolve_snapshot(snapshot.tag())
+            .ok_or_else(|| PackError::SnapshotNotFound(snapshot.to_string()))?;
+        Ok(index.entries_from_handles(handles.iter())?)
+    }
+
     pub fn load_index_snapshots(&self, pack_id: &PackId) -> Result<Vec<String>, Error> {
         let pack_index_path = match pack_id {
             PackId::Pack(name) => {
@@ -380,11 +666,71 @@ impl Repository {
         Ok(PackIndex::load_only_snapshots(pack_index_path)?)
     }
 
+    /// Loads the tags of the snapshots in the pack, along with their
+    /// attributes (see [`PackIndex::snapshot_attributes`]).
+    pub fn load_index_snapshot_attributes(
+        &self,
+        pack_id: &PackId,
+    ) -> Result<Vec<(String, Attributes)>, Error> {
+        let PackId::Pack(name) = pack_id;
+        let pack_index_path = self
+            .data_dir
+            .join(PACKS_DIR)
+            .join(format!("{}.{}", name, PACK_INDEX_EXTENSION));
+        Ok(PackIndex::load_only_snapshot_attributes(pack_index_path)?)
+    }
+
+    /// Rewrites the index of the pack in the latest format, see
+    /// [`PackIndex::upgrade_format`]. Returns the versions of the format it
+    /// was written in before and after, or [`None`] if it already used the
+    /// latest one.
+    pub fn upgrade_index(&self, pack_id: &PackId) -> Result<Option<(u32, u32)>, Error> {
+        let mut index = self.load_index(pack_id)?;
+        let version = index.format_version();
+        index.upgrade_format();
+        if index.format_version() == version {
+            return Ok(None);
+        }
+
+        let PackId::Pack(name) = pack_id;
+        let pack_index_path = self
+            .data_dir
+            .join(PACKS_DIR)
+            .join(format!("{}.{}", name, PACK_INDEX_EXTENSION));
+        let temp_dir = self.temp_dir();
+        ensure_dir(&temp_dir)?;
+        let temp_path = create_temp_path(&temp_dir);
+        index.save(&temp_path)?;
+        // Loose snapshots are ordered by the modification time of their index.
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(&pack_index_path)?);
+        set_file_mtime(&temp_path, mtime)?;
+        fs::rename(&temp_path, &pack_index_path)?;
+        Ok(Some((version, index.format_version())))
+    }
+
+    /// Whether the pack was fetched from a remote, in which case its index is
+    /// replaced by the one of the remote on update.
+    pub fn is_pack_remote(&self, pack_id: &PackId) -> bool {
+        let PackId::Pack(name) = pack_id;
+        match Path::new(name).components().next() {
+            Some(remote) if Path::new(name).components().count() > 1 => self
+                .data_dir
+                .join(REMOTES_DIR)
+                .join(remote)
+                .with_extension(REMOTE_INDEX_EXTENSION)
+                .exists(),
+            _ => false,
+        }
+    }
+
     /// Checks-out the specified snapshot.
     ///
     /// # Arguments
     ///
     /// * `snapshot_id` - The snapshot to extract.
+    /// * `opts` - Only the files selected by [`ExtractOptions::sparse_spec`] are
+    ///   extracted. The spec is recorded in HEAD, and files which were selected
+    ///   by the previous spec but not by this one are removed.
     pub fn extract_snapshot(
         &mut self,
         snapshot_id: SnapshotId,
@@ -397,18 +743,23 @@ impl Repository {
             return Err(Error::DirtyWorkDir);
         }
 
+        let sparse_spec = opts.sparse_spec();
+        let matcher = sparse_spec.matcher()?;
+
         // Open the pack and find the snapshot specified in SnapshotId.
         let source_index = self.load_index(snapshot_id.pack())?;
 
         let entries = source_index
             .resolve_snapshot(snapshot_id.tag())
             .expect("failed to resolve snapshot"); // TODO: Temporary.
-        let entries = source_index.entries_from_handles(entries.iter())?;
+        let entries = matcher.filter(source_index.entries_from_handles(entries.iter())?);
 
         let (new_entries, old_entries) = if opts.reset || head.is_none() {
             // Extract all, remove nothing
             (entries, vec![])
         } else if let Some(head) = head {
+            // Only the files selected when HEAD was extracted are present.
+            let head_matcher = self.read_head_sparse_spec()?.matcher()?;
             // HEAD and new snapshot packs might differ
             if snapshot_id.pack() == head.pack() {
                 let head_entries = source_index.entries_from_handles(
@@ -417,7 +768,7 @@ impl Repository {
                         .expect("failed to resolve snapshot")
                         .iter(), // TODO: Temporary.
                 )?;
-                Self::compute_entry_diff(&head_entries, &entries)
+                Self::compute_entry_diff(&head_matcher.filter(head_entries), &entries)
             } else {
                 let head_index = self.load_index(head.pack())?;
                 let head_entries = head_index
@@ -434,7 +785,7 @@ impl Repository {
                             Error::PackError(e)
                         }
                     })?;
-                Self::compute_entry_diff(&head_entries, &entries)
+                Self::compute_entry_diff(&head_matcher.filter(head_entries), &entries)
             }
         } else {
             unreachable!();
@@ -484,7 +835,7 @@ impl Repository {
         dir_queue.process()?;
 
         self.extract_entries(snapshot_id.pack(), &new_entries, self.path.clone(), opts)?;
-        self.update_head(&snapshot_id)?;
+        self.update_head(&snapshot_id, &sparse_spec, "extract")?;
 
         Ok(ExtractResult {
             added_file_count: (new_entries.len() - updated_paths.len()) as u32,
@@ -493,6 +844,46 @@ impl Repository {
         })
     }
 
+    /// Extracts the files in the snapshot selected by `opts` to a given path,
+    /// which does not need to be the worktree of the repository. Unlike [`Self::extract_snapshot`],
+    /// HEAD is neither read nor updated, and no files are removed from `path`.
+    ///
+    /// Fails with [`Error::DestinationNotEmpty`] if `path` is a non-empty
+    /// directory, unless `opts.force()` is set.
+    pub fn extract_snapshot_into<P>(
+        &mut self,
+        snapshot_id: &SnapshotId,
+        path: P,
+        opts: ExtractOptions,
+    ) -> Result<ExtractResult, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if !opts.force() {
+            match fs::read_dir(path) {
+                Ok(mut dir) => {
+                    if dir.next().is_some() {
+                        return Err(Error::DestinationNotEmpty(path.to_owned()));
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let matcher = opts.sparse_spec().matcher()?;
+        let entries = matcher.filter(self.load_snapshot_entries(snapshot_id)?);
+        ensure_dir(path)?;
+        self.extract_entries(snapshot_id.pack(), &entries, path, opts)?;
+
+        Ok(ExtractResult {
+            added_file_count: entries.len() as u32,
+            removed_file_count: 0,
+            modified_file_count: 0,
+        })
+    }
+
     /// Extract the specified entries to a given path.
     ///
     /// # Arguments
@@ -512,15 +903,113 @@ impl Repository {
         P: AsRef<Path>,
     {
         if self.is_pack_loose(pack_id) {
-            self.copy_loose_entries(entries, path.as_ref(), opts.verify())
-        } else if let Ok(pack) = self.open_pack(pack_id) {
-            pack.extract_entries(entries, path.as_ref(), opts.verify(), opts.num_workers())
+            let verify = self.loose_verify_algorithm(pack_id, opts.verify())?;
+            self.copy_loose_entries(entries, path.as_ref(), verify)
+        } else {
+            self.open_pack_or_fetch(pack_id)?.extract_entries(
+                entries,
+                path.as_ref(),
+                opts.verify(),
+                opts.num_workers(),
+            )
+        }
+    }
+
+    /// Writes the contents of the entries to `writer`, one after another,
+    /// without extracting them to disk first.
+    ///
+    /// # Arguments
+    ///
+    /// * `pack_id` - The pack_id containing the entries.
+    /// * `entries` - The list of entries to write, in order.
+    /// * `verify` - Set to true to verify object checksums while writing.
+    /// * `writer` - The sink to write the contents to.
+    pub fn write_entries<W>(
+        &self,
+        pack_id: &PackId,
+        entries: &[FileEntry],
+        verify: bool,
+        mut writer: W,
+    ) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        if self.is_pack_loose(pack_id) {
+            let verify = self.loose_verify_algorithm(pack_id, verify)?;
+            for entry in entries {
+                let object = open_file(self.loose_object_path(&entry.checksum))?;
+                copy_object(
+                    object,
+                    entry.obj_metadata.size,
+                    &entry.checksum,
+                    verify,
+                    &mut writer,
+                )?;
+            }
+        } else {
+            let mut pack = self.open_pack_or_fetch(pack_id)?;
+            for entry in entries {
+                pack.write_object(entry, verify, &mut writer)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads the contents of the entries one at a time, in order, and passes
+    /// them to `visit`, without extracting them to disk first.
+    ///
+    /// # Arguments
+    ///
+    /// * `pack_id` - The pack_id containing the entries.
+    /// * `entries` - The list of entries to read, in order.
+    /// * `verify` - Set to true to verify object checksums.
+    /// * `visit` - Called with each entry and the contents of its object.
+    pub fn read_entries<F>(
+        &self,
+        pack_id: &PackId,
+        entries: &[FileEntry],
+        verify: bool,
+        mut visit: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&FileEntry, &[u8]) -> Result<(), Error>,
+    {
+        let mut buf = vec![];
+        if self.is_pack_loose(pack_id) {
+            let verify = self.loose_verify_algorithm(pack_id, verify)?;
+            for entry in entries {
+                buf.clear();
+                let object = open_file(self.loose_object_path(&entry.checksum))?;
+                copy_object(
+                    object,
+                    entry.obj_metadata.size,
+                    &entry.checksum,
+                    verify,
+                    &mut buf,
+                )?;
+                visit(entry, &buf)?;
+            }
+        } else {
+            let mut pack = self.open_pack_or_fetch(pack_id)?;
+            for entry in entries {
+                buf.clear();
+                pack.write_object(entry, verify, &mut buf)?;
+                visit(entry, &buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the pack, fetching it from the remote first if it is not
+    /// available locally.
+    fn open_pack_or_fetch(&self, pack_id: &PackId) -> Result<Pack, Error> {
+        if let Ok(pack) = self.open_pack(pack_id) {
+            Ok(pack)
         } else {
             info!("Pack not available locally! Fetching from remote...");
             self.update_remote_pack(pack_id)?;
-            self.open_pack(pack_id).and_then(|pack| {
-                pack.extract_entries(entries, path.as_ref(), opts.verify(), opts.num_workers())
-            })
+            self.open_pack(pack_id)
         }
     }
 
@@ -562,7 +1051,16 @@ impl Repository {
         self.data_dir.to_owned()
     }
 
-    pub fn create_snapshot<I, P>(&mut self, snapshot: &SnapshotId, files: I) -> Result<(), Error>
+    /// Creates a loose snapshot of the files, with checksums computed by
+    /// `hash_algorithm`. The snapshot is annotated with the `attributes` and
+    /// the time it was created.
+    pub fn create_snapshot<I, P>(
+        &mut self,
+        snapshot: &SnapshotId,
+        files: I,
+        hash_algorithm: HashAlgorithm,
+        attributes: Attributes,
+    ) -> Result<(), Error>
     where
         I: Iterator<Item = P>,
         P: AsRef<Path>,
@@ -583,7 +1081,6 @@ impl Repository {
                 );
 
                 let buf: Vec<u8>;
-                let mut checksum = [0u8; 20];
 
                 //let path = Path::new(&file_path);
                 let is_symlink_file = Path::new(&actual_file_path).is_symlink();
@@ -599,9 +1096,7 @@ impl Repository {
                     symlink_target = Path::new("").to_path_buf();
                 }
 
-                let mut hasher = Sha1::new();
-                hasher.input(&buf);
-                hasher.result(&mut checksum);
+                let checksum = hash_algorithm.checksum(&buf);
                 self.write_loose_object(&*buf, &temp_dir, &checksum)?;
 
                 let file_mtime_info = FileTime::from_last_modification_time(&metadata);
@@ -628,8 +1123,9 @@ impl Repository {
             .into_iter()
             .collect::<io::Result<Vec<_>>>()?;
 
-        let mut index = PackIndex::new();
+        let mut index = PackIndex::with_hash_algorithm(hash_algorithm);
         index.push_snapshot(snapshot.tag().to_owned(), pack_entries)?;
+        index.set_snapshot_attributes(snapshot.tag(), with_creation_time(attributes))?;
 
         let loose_path = self.data_dir().join(PACKS_DIR).join(LOOSE_DIR);
         ensure_dir(&loose_path)?;
@@ -639,7 +1135,138 @@ impl Repository {
 
         index.save(index_path)?;
 
-        self.update_head(snapshot)?;
+        self.update_head(snapshot, &SparseSpec::default(), "store")?;
+
+        Ok(())
+    }
+
+    /// Creates a loose snapshot from the files in a tar archive, without
+    /// extracting them to disk first. The modes, modification times and
+    /// symlink targets of the files are taken from the archive.
+    ///
+    /// Directories are skipped, hard links are stored as copies of the files
+    /// they link to, and other special files are ignored. Unlike
+    /// [`Self::create_snapshot`], HEAD is not updated, since the files are not
+    /// present in the worktree.
+    pub fn create_snapshot_from_tar<R>(
+        &mut self,
+        snapshot: &SnapshotId,
+        reader: R,
+        hash_algorithm: HashAlgorithm,
+        attributes: Attributes,
+    ) -> Result<(), Error>
+    where
+        R: Read,
+    {
+        let temp_dir = self.temp_dir();
+        ensure_dir(&temp_dir)?;
+
+        let mut pack_entries: HashMap<OsString, FileEntry> = HashMap::new();
+        let mut archive = tar::Archive::new(reader);
+        let mut buf = vec![];
+        for tar_entry in archive.entries()? {
+            let mut tar_entry = tar_entry?;
+            let tar_path = tar_entry.path()?.into_owned();
+            let path = match archive_relative_path(&tar_path) {
+                Some(path) => OsString::from(path),
+                None => {
+                    warn!("Skipping {}: not a relative UTF-8 path", tar_path.display());
+                    continue;
+                }
+            };
+
+            let header = tar_entry.header();
+            let entry_type = header.entry_type();
+            let mode = header.mode()? & 0o7777;
+            let mut last_modified = header.mtime()? as i64;
+            let mut last_modified_nanos = 0;
+            // Sub-second modification times are stored in PAX extended headers.
+            if let Some(extensions) = tar_entry.pax_extensions()? {
+                for extension in extensions {
+                    let extension = extension?;
+                    if extension.key() == Ok("mtime") {
+                        if let Some((secs, nanos)) = extension.value().ok().and_then(parse_pax_time)
+                        {
+                            last_modified = secs;
+                            last_modified_nanos = nanos;
+                        }
+                    }
+                }
+            }
+
+            let (bits_mods, is_symlink_file, symlink_target) = match entry_type {
+                tar::EntryType::Regular | tar::EntryType::Continuous => {
+                    buf.clear();
+                    tar_entry.read_to_end(&mut buf)?;
+                    (0o100000 | mode, false, PathBuf::new())
+                }
+                tar::EntryType::Symlink => {
+                    let target = tar_entry.link_name()?.ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("symlink {} has no target", tar_path.display()),
+                        )
+                    })?;
+                    // Like in create_snapshot, the object of a symlink is its path.
+                    buf = Self::create_vec_u8_from_string(path.to_string_lossy().into_owned());
+                    (0o120000 | mode, true, target.into_owned())
+                }
+                tar::EntryType::Link => {
+                    let target = tar_entry
+                        .link_name()?
+                        .and_then(|p| archive_relative_path(&p));
+                    match target.and_then(|target| pack_entries.get(OsStr::new(&target))) {
+                        Some(linked) => {
+                            let mut entry = linked.clone();
+                            entry.path = path.clone();
+                            pack_entries.insert(path, entry);
+                        }
+                        None => warn!(
+                            "Skipping {}: hard link to a missing file",
+                            tar_path.display()
+                        ),
+                    }
+                    continue;
+                }
+                tar::EntryType::Directory => continue,
+                _ => {
+                    warn!("Skipping {}: unsupported file type", tar_path.display());
+                    continue;
+                }
+            };
+
+            let checksum = hash_algorithm.checksum(&buf);
+            self.write_loose_object(&*buf, &temp_dir, &checksum)?;
+
+            let entry = FileEntry::new(
+                path.clone(),
+                checksum,
+                ObjectMetadata {
+                    offset: LOOSE_OBJECT_OFFSET,
+                    size: buf.len() as u64,
+                },
+                FileMetadata {
+                    last_modified,
+                    last_modified_nanos,
+                    bits_mods,
+                    is_symlink_file,
+                    symlink_target,
+                },
+            );
+            // Later entries replace earlier ones with the same path, as in tar.
+            pack_entries.insert(path, entry);
+        }
+
+        let mut pack_entries: Vec<_> = pack_entries.into_values().collect();
+        pack_entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut index = PackIndex::with_hash_algorithm(hash_algorithm);
+        index.push_snapshot(snapshot.tag().to_owned(), pack_entries)?;
+        index.set_snapshot_attributes(snapshot.tag(), with_creation_time(attributes))?;
+
+        let loose_path = self.data_dir().join(PACKS_DIR).join(LOOSE_DIR);
+        ensure_dir(&loose_path)?;
+        index.save(loose_path.join(format!("{}.{}", snapshot.tag(), PACK_INDEX_EXTENSION)))?;
 
         Ok(())
     }
@@ -665,16 +1292,20 @@ impl Repository {
     ///
     /// * `pack` - The name of the pack file to create
     /// * `index` - The index for the new pack
+    /// * `sources` - The loose snapshots and packs containing the objects of `index`
     /// * `opts` - Additional options to use during pack creation
     pub fn create_pack(
         &mut self,
         pack: &PackId,
         index: PackIndex,
+        sources: &[PackId],
         opts: &PackOptions,
         reporter: &ProgressReporter,
     ) -> Result<(), Error> {
         let PackId::Pack(pack_name) = pack;
 
+        let packed_objects = self.find_packed_objects(sources)?;
+
         // Construct output file path.
         let pack_path = {
             let mut pack_path = self.data_dir.to_owned();
@@ -689,50 +1320,404 @@ impl Repository {
         ensure_dir(&temp_dir)?;
         let temp_path = create_temp_path(&temp_dir);
 
-        let (index, ordering) = index.compute_object_offsets_and_ordering();
+        let (mut index, ordering) = self.order_objects(index, sources, &packed_objects, opts)?;
+
+        // Gather a list of all objects (or chunks) to compress.
+        let chunks = if opts.chunk_objects {
+            Some(self.write_chunks(
+                &mut index,
+                &ordering,
+                sources,
+                &packed_objects,
+                opts.num_frames,
+            )?)
+        } else {
+            None
+        };
+        let frame_inputs = match &chunks {
+            Some((chunks_path, chunk_sizes)) => {
+                let mut offset = 0;
+                partition_by_u64(chunk_sizes, opts.num_frames, |&size| size)
+                    .into_iter()
+                    .map(|partition| {
+                        let size = partition.iter().sum::<u64>();
+                        offset += size;
+                        FrameInput::Chunks(chunks_path, offset - size, size)
+                    })
+                    .collect::<Vec<_>>()
+            }
+            None => index
+                .objects_partitioned_by_size(opts.num_frames, &ordering)
+                .into_iter()
+                .map(FrameInput::Objects)
+                .collect(),
+        };
+
+        let dictionary = if opts.train_dictionary {
+            self.train_dictionary(&index, &ordering, sources, &packed_objects, opts.num_frames)?
+        } else {
+            None
+        };
+
+        let compressed_frames = self.compress_frames(
+            frame_inputs,
+            &index,
+            sources,
+            &packed_objects,
+            opts,
+            Some(opts.seek_interval).filter(|&interval| interval > 0),
+            dictionary.as_deref(),
+            reporter,
+        )?;
+        if let Some((chunks_path, _)) = chunks {
+            fs::remove_file(chunks_path)?;
+        }
+
+        // Create the header.
+        let frames = compressed_frames.iter().map(|f| f.frame.clone()).collect();
+        let header = PackHeader::with_dictionary(frames, dictionary);
+        let zstd_frames = (opts.seek_interval > 0).then(|| {
+            compressed_frames
+                .iter()
+                .flat_map(|f| f.zstd_frames.iter().cloned())
+                .collect()
+        });
+        let (header_bytes, seek_table) = encode_pack_header(header, zstd_frames)?;
+
+        // And a writer to that temporary file.
+        let mut pack_writer = io::BufWriter::new(create_file(&temp_path)?);
+        // Write header and frames.
+        pack_writer.write_all(&header_bytes)?;
+        write_frame_files(&mut pack_writer, &compressed_frames)?;
+        if let Some(seek_table) = seek_table {
+            seek_table.write(&mut pack_writer)?;
+        }
+        pack_writer.flush()?;
+        drop(pack_writer);
+
+        let index_path = pack_path.with_extension(PACK_INDEX_EXTENSION);
+        info!("Write index: {}", index_path.display());
+        index.save(index_path)?;
 
-        // Gather a list of all objects to compress.
-        let object_partitions = index.objects_partitioned_by_size(opts.num_frames, &ordering);
+        // Finally, move the .pack file itself to the packs/ dir.
+        fs::rename(&temp_path, &pack_path)?;
+
+        Ok(())
+    }
 
-        let workers_per_task = (opts.num_workers + object_partitions.len() as u32 - 1)
-            / std::cmp::max(1, object_partitions.len()) as u32;
+    /// Appends the snapshots to an existing pack. The objects which are not
+    /// in the pack yet are compressed into new frames at the end of the pack
+    /// (with the dictionary of the pack, if it has one), while the existing
+    /// frames are copied over unchanged. The seek table of the pack is
+    /// extended with the new frames; one is added if
+    /// [`PackOptions::seek_interval`] is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `pack` - The name of the pack to append to
+    /// * `index` - The index of the snapshots to append
+    /// * `sources` - The loose snapshots and packs containing the objects of `index`
+    /// * `opts` - Additional options to use during pack creation
+    pub fn append_to_pack(
+        &mut self,
+        pack: &PackId,
+        index: PackIndex,
+        sources: &[PackId],
+        opts: &PackOptions,
+        reporter: &ProgressReporter,
+    ) -> Result<(), Error> {
+        let PackId::Pack(pack_name) = pack;
+        let pack_path = self
+            .data_dir
+            .join(PACKS_DIR)
+            .join(format!("{}.{}", pack_name, PACK_EXTENSION));
+        if !pack_path.exists() {
+            return Err(Error::PackNotFound(pack_name.clone()));
+        }
+        let (file_size, header_size, header) = match read_pack_header(&pack_path)? {
+            Some((file_size, header_size, header)) if header.is_valid() => {
+                (file_size, header_size, header)
+            }
+            _ => return Err(Error::CannotAppend(pack.clone(), "the pack has no header")),
+        };
+        let pack_index = self.load_index(pack)?;
+        if !pack_index.chunks().is_empty() {
+            return Err(Error::CannotAppend(pack.clone(), "the pack is chunked"));
+        }
+        if index.hash_algorithm() != pack_index.hash_algorithm() {
+            return Err(Error::CannotAppend(
+                pack.clone(),
+                "the snapshots use a different hash algorithm",
+            ));
+        }
+
+        let packed_objects = self.find_packed_objects(sources)?;
+        let (mut index, mut ordering) =
+            self.order_objects(index, sources, &packed_objects, opts)?;
+
+        // Only the objects which are not in the pack yet are stored, after
+        // the end of the existing frames.
+        let existing_objects = pack_index
+            .object_checksums()
+            .map(|checksum| (*checksum, pack_index.object_metadata(checksum).clone()))
+            .collect::<HashMap<_, _>>();
+        ordering.retain(|&handle| {
+            let checksum = index.handle_to_checksum(handle);
+            !existing_objects.contains_key(checksum)
+        });
+        let pack_end = header
+            .frames()
+            .iter()
+            .map(|frame| frame.decompressed_size)
+            .sum();
+        index.set_object_offsets(&ordering, pack_end);
+
+        // The new index lists the snapshots of the pack, then the new ones.
+        let mut new_index = PackIndex::with_hash_algorithm(pack_index.hash_algorithm());
+        for snapshots in [&pack_index, &index] {
+            let result = snapshots.for_each_snapshot(|snapshot, entries| {
+                let entries = entries.iter().cloned().map(|mut entry| {
+                    if let Some(metadata) = existing_objects.get(&entry.checksum) {
+                        entry.obj_metadata = metadata.clone();
+                    }
+                    entry
+                });
+                match new_index.push_snapshot(snapshot.to_owned(), entries) {
+                    Ok(()) => ControlFlow::Continue(()),
+                    Err(e) => ControlFlow::Break(e),
+                }
+            })?;
+            if let Some(e) = result {
+                return Err(e.into());
+            }
+            new_index.copy_attributes_from(snapshots);
+        }
+        // Keep the format of an upgraded index.
+        if pack_index.format_version() > new_index.format_version() {
+            new_index.upgrade_format();
+        }
+
+        info!(
+            "Appending {} new objects to {}...",
+            ordering.len(),
+            pack_name
+        );
+        let frame_inputs = index
+            .objects_partitioned_by_size(opts.num_frames, &ordering)
+            .into_iter()
+            .filter(|objects| !objects.is_empty())
+            .map(FrameInput::Objects)
+            .collect();
+        let seek_interval = match (opts.seek_interval, header.seek_table()) {
+            (0, Some(_)) => Some(DEFAULT_SEEK_INTERVAL),
+            (0, None) => None,
+            (interval, _) => Some(interval),
+        };
+        let new_frames = self.compress_frames(
+            frame_inputs,
+            &index,
+            sources,
+            &packed_objects,
+            opts,
+            seek_interval,
+            header.dictionary(),
+            reporter,
+        )?;
+
+        let mut frames = header.frames().to_vec();
+        frames.extend(new_frames.iter().map(|f| f.frame.clone()));
+        let new_header =
+            PackHeader::with_dictionary(frames, header.dictionary().map(|d| d.to_vec()));
+
+        // Without a seek table, each of the existing frames is a single
+        // Zstandard frame.
+        let existing_zstd_frames = match header.seek_table() {
+            Some(seek_table) => seek_table
+                .entries()
+                .iter()
+                .skip(1)
+                .map(|entry| batch::FrameSize {
+                    compressed: entry.compressed_size as u64,
+                    decompressed: entry.decompressed_size as u64,
+                })
+                .collect(),
+            None => header
+                .frames()
+                .iter()
+                .map(|frame| batch::FrameSize {
+                    compressed: frame.frame_size,
+                    decompressed: frame.decompressed_size,
+                })
+                .collect::<Vec<_>>(),
+        };
+        let seek_table_size = header.seek_table().map_or(0, |table| table.frame_size());
+        let frames_size = file_size - header_size - seek_table_size;
+
+        // The header grows with the new frames, so the pack is written again,
+        // copying the existing frames byte for byte.
+        let temp_dir = self.temp_dir();
+        ensure_dir(&temp_dir)?;
+        let temp_path = create_temp_path(&temp_dir);
+        let zstd_frames = seek_interval.map(|_| {
+            existing_zstd_frames
+                .into_iter()
+                .chain(
+                    new_frames
+                        .iter()
+                        .flat_map(|f| f.zstd_frames.iter().cloned()),
+                )
+                .collect()
+        });
+        let (header_bytes, seek_table) = encode_pack_header(new_header, zstd_frames)?;
+        let mut pack_writer = io::BufWriter::new(create_file(&temp_path)?);
+        pack_writer.write_all(&header_bytes)?;
+        let mut pack_reader = open_file(&pack_path)?;
+        io::Seek::seek(&mut pack_reader, io::SeekFrom::Start(header_size))?;
+        io::copy(&mut pack_reader.take(frames_size), &mut pack_writer)?;
+        write_frame_files(&mut pack_writer, &new_frames)?;
+        if let Some(seek_table) = seek_table {
+            seek_table.write(&mut pack_writer)?;
+        }
+        pack_writer.flush()?;
+        drop(pack_writer);
+
+        let temp_index_path = create_temp_path(&temp_dir);
+        new_index.save(&temp_index_path)?;
+
+        // The existing snapshots can be read using either version of the
+        // index, so replacing the .pack file first means that the pack is
+        // usable at any point.
+        fs::rename(&temp_path, &pack_path)?;
+        fs::rename(
+            &temp_index_path,
+            pack_path.with_extension(PACK_INDEX_EXTENSION),
+        )?;
+
+        Ok(())
+    }
+
+    /// Finds the objects which are not available in the loose object store,
+    /// and have to be read from the frames of the source packs. Returns the
+    /// index of the source pack and the metadata of each of these objects.
+    fn find_packed_objects(
+        &self,
+        sources: &[PackId],
+    ) -> Result<HashMap<ObjectChecksum, (usize, ObjectMetadata)>, Error> {
+        let mut loose_objects = HashSet::new();
+        for source in sources.iter().filter(|p| self.is_pack_loose(p)) {
+            loose_objects.extend(self.load_index(source)?.object_checksums().copied());
+        }
+        let mut packed_objects = HashMap::new();
+        for (source_index, source) in sources.iter().enumerate() {
+            if self.is_pack_loose(source) {
+                continue;
+            }
+            let source_pack_index = self.load_index(source)?;
+            for checksum in source_pack_index.object_checksums() {
+                if !loose_objects.contains(checksum) {
+                    packed_objects.entry(*checksum).or_insert_with(|| {
+                        (
+                            source_index,
+                            source_pack_index.object_metadata(checksum).clone(),
+                        )
+                    });
+                }
+            }
+        }
+        Ok(packed_objects)
+    }
+
+    /// Compresses each of the inputs into a separate frame, in parallel.
+    /// Each frame is written to a temporary file as it is compressed, so that
+    /// the memory used is bounded by the number of frames being compressed at
+    /// once, rather than by the size of the pack. Returns the frames, along
+    /// with the files containing them, see [`write_frame_files`]. If
+    /// `max_frame_input` is set, each frame is split into Zstandard frames
+    /// of at most that much input.
+    #[allow(clippy::too_many_arguments)]
+    fn compress_frames(
+        &self,
+        frame_inputs: Vec<FrameInput>,
+        index: &PackIndex,
+        sources: &[PackId],
+        packed_objects: &HashMap<ObjectChecksum, (usize, ObjectMetadata)>,
+        opts: &PackOptions,
+        max_frame_input: Option<u64>,
+        dictionary: Option<&[u8]>,
+        reporter: &ProgressReporter,
+    ) -> Result<Vec<CompressedFrame>, Error> {
+        let workers_per_task = (opts.num_workers + frame_inputs.len() as u32 - 1)
+            / std::cmp::max(1, frame_inputs.len()) as u32;
 
         let task_opts = batch::CompressionOptions {
             window_log: opts.compression_window_log,
             level: opts.compression_level,
             num_workers: workers_per_task,
+            dictionary,
+            max_frame_input,
         };
 
+        let temp_dir = self.temp_dir();
+        ensure_dir(&temp_dir)?;
+
         // Keep count of done compression tasks
         let done_task_count = std::sync::atomic::AtomicUsize::new(0);
-        let total_task_count = object_partitions.len();
+        let total_task_count = frame_inputs.len();
 
         info!("Creating {} compressed frames...", total_task_count);
 
-        let mut frames = vec![];
-        let mut frame_bufs = vec![];
-
         let frame_results = run_in_parallel(
             opts.num_workers as usize,
-            object_partitions.into_iter(),
-            |objects| {
-                let object_readers = objects.iter().map(|&handle| {
-                    // TODO: Method of obtaining readers from packs? Or we can
-                    // just assume packs first get unpacked.
-                    Ok(Box::new(open_file(
-                        self.loose_object_path(index.handle_to_checksum(handle)),
-                    )?))
-                });
-
-                let mut buf = vec![];
-                // Compress all the object files.
-                let r = batch::compress_files(
-                    &mut buf,
-                    object_readers,
-                    &task_opts,
-                    &ProgressReporter::dummy(),
-                )
-                .map(move |bytes| (bytes, buf));
+            frame_inputs.into_iter(),
+            |input| {
+                let frame_path = create_temp_path(&temp_dir);
+                let r = (|| {
+                    let mut writer = io::BufWriter::new(create_file(&frame_path)?);
+                    let zstd_frames = match input {
+                        FrameInput::Objects(objects) => {
+                            let mut packed_bufs =
+                                self.read_packed_objects(objects, index, sources, packed_objects)?;
+                            let object_readers = objects.iter().map(|&handle| {
+                                let checksum = index.handle_to_checksum(handle);
+                                Ok(match packed_bufs.remove(checksum) {
+                                    Some(buf) => Box::new(Cursor::new(buf)) as Box<dyn Read>,
+                                    None => Box::new(open_file(self.loose_object_path(checksum))?),
+                                })
+                            });
+
+                            // Compress all the object files.
+                            batch::compress_files(
+                                &mut writer,
+                                object_readers,
+                                &task_opts,
+                                &ProgressReporter::dummy(),
+                            )?
+                        }
+                        FrameInput::Chunks(chunks_path, offset, size) => {
+                            let mut reader = open_file(chunks_path)?;
+                            io::Seek::seek(&mut reader, io::SeekFrom::Start(offset))?;
+                            batch::compress_files(
+                                &mut writer,
+                                std::iter::once(Ok(reader.take(size))),
+                                &task_opts,
+                                &ProgressReporter::dummy(),
+                            )?
+                        }
+                    };
+                    writer.flush()?;
+                    let frame = PackFrame {
+                        frame_size: fs::metadata(&frame_path)?.len(),
+                        decompressed_size: zstd_frames.iter().map(|f| f.decompressed).sum(),
+                    };
+                    Ok::<_, Error>(CompressedFrame {
+                        frame,
+                        zstd_frames,
+                        path: frame_path.clone(),
+                    })
+                })();
+                if r.is_err() {
+                    let _ = fs::remove_file(&frame_path);
+                }
                 // Update done count.
                 let done = done_task_count.fetch_add(1, std::sync::atomic::Ordering::AcqRel) + 1;
                 // And report the change.
@@ -741,41 +1726,338 @@ impl Repository {
             },
         );
 
+        let mut frames = vec![];
+        let mut error = None;
         for frame_result in frame_results {
-            let (decompressed_size, compressed_buffer) = frame_result?;
-            frames.push(PackFrame {
-                frame_size: compressed_buffer.len() as u64,
-                decompressed_size,
-            });
-            // Note: storing the whole file in memory at this point.
-            // Could write them out, except that the header needs to be prepended.
-            frame_bufs.push(compressed_buffer);
+            match frame_result {
+                Ok(frame) => frames.push(frame),
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        if let Some(e) = error {
+            for frame in frames {
+                let _ = fs::remove_file(frame.path);
+            }
+            return Err(e);
         }
 
         // Report that all compression tasks are done.
         reporter.checkpoint(total_task_count, Some(0));
 
-        // Create and serialize header.
-        let header = PackHeader::new(frames);
-        let header_bytes = rmp_serde::encode::to_vec(&header).expect("Serialization failed!");
+        Ok(frames)
+    }
 
-        // And a writer to that temporary file.
-        let mut pack_writer = io::BufWriter::new(create_file(&temp_path)?);
-        // Write header and frames.
-        write_skippable_frame(&mut pack_writer, &header_bytes)?;
-        for frame_buf in frame_bufs {
-            pack_writer.write_all(&frame_buf)?;
+    /// Splits the objects into content-defined chunks, and writes each
+    /// distinct chunk once to a temporary file, in order of the objects. The
+    /// chunks of the objects are recorded in the index. Returns the path of
+    /// the file and the sizes of the chunks in it.
+    fn write_chunks(
+        &self,
+        index: &mut PackIndex,
+        ordering: &[Handle],
+        sources: &[PackId],
+        packed_objects: &HashMap<ObjectChecksum, (usize, ObjectMetadata)>,
+        num_frames: u32,
+    ) -> Result<(PathBuf, Vec<u64>), Error> {
+        info!("Splitting objects into chunks...");
+        let chunks_path = create_temp_path(&self.temp_dir());
+        let mut writer = ChunkWriter::new(io::BufWriter::new(create_file(&chunks_path)?));
+        let mut chunks = vec![];
+        self.for_each_object(
+            index,
+            ordering,
+            sources,
+            packed_objects,
+            num_frames,
+            |_, buf| {
+                chunks.extend(writer.add_object(buf)?);
+                Ok(())
+            },
+        )?;
+        let chunk_sizes = writer.chunk_sizes().to_vec();
+        writer.into_inner()?;
+
+        info!(
+            "Storing {:.3} MiB of chunks for {:.3} MiB of objects",
+            chunk_sizes.iter().sum::<u64>() as f64 / 1024f64 / 1024f64,
+            index.object_size_total() as f64 / 1024f64 / 1024f64,
+        );
+        index.set_chunks(chunks);
+        Ok((chunks_path, chunk_sizes))
+    }
+
+    /// Trains a Zstandard dictionary on the beginnings of a sample of the
+    /// objects, spread evenly across the ordering. Returns [`None`] if there
+    /// is not enough data to train on.
+    fn train_dictionary(
+        &self,
+        index: &PackIndex,
+        ordering: &[Handle],
+        sources: &[PackId],
+        packed_objects: &HashMap<ObjectChecksum, (usize, ObjectMetadata)>,
+        num_frames: u32,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if ordering.is_empty() {
+            return Ok(None);
         }
-        pack_writer.flush()?;
-        drop(pack_writer);
+        let average_size = index.object_size_total() / ordering.len() as u64;
+        let sample_size = average_size.clamp(1, DICTIONARY_SAMPLE_SIZE as u64);
+        let num_samples = (DICTIONARY_SAMPLES_SIZE as u64 / sample_size) as usize;
+        let stride = std::cmp::max(1, ordering.len() / std::cmp::max(1, num_samples));
+        let sampled = ordering.iter().copied().step_by(stride).collect::<Vec<_>>();
+
+        info!("Training a dictionary on {} objects...", sampled.len());
+        let mut samples = vec![];
+        self.for_each_object(
+            index,
+            &sampled,
+            sources,
+            packed_objects,
+            num_frames,
+            |_, buf| {
+                let len = std::cmp::min(buf.len(), DICTIONARY_SAMPLE_SIZE);
+                samples.push(buf[..len].to_vec());
+                Ok(())
+            },
+        )?;
 
-        let index_path = pack_path.with_extension(PACK_INDEX_EXTENSION);
-        info!("Write index: {}", index_path.display());
-        index.save(index_path)?;
+        match zstd::dict::from_samples(&samples, DICTIONARY_SIZE) {
+            Ok(dictionary) => Ok(Some(dictionary)),
+            Err(e) => {
+                warn!("Packing without a dictionary, since training failed: {}", e);
+                Ok(None)
+            }
+        }
+    }
 
-        // Finally, move the .pack file itself to the packs/ dir.
-        fs::rename(&temp_path, &pack_path)?;
+    /// Lays out the objects of the new pack in the order selected by
+    /// [`PackOptions::ordering`], see [`PackIndex::compute_object_offsets_and_ordering_by`].
+    fn order_objects(
+        &self,
+        index: PackIndex,
+        sources: &[PackId],
+        packed_objects: &HashMap<ObjectChecksum, (usize, ObjectMetadata)>,
+        opts: &PackOptions,
+    ) -> Result<(PackIndex, Vec<Handle>), Error> {
+        info!("Ordering objects by {}...", opts.ordering);
+        Ok(match opts.ordering {
+            ObjectOrdering::Path => {
+                let occurrences = index.object_first_occurrences();
+                index.compute_object_offsets_and_ordering_by(|handle, metadata| {
+                    let (snapshot, path) = &occurrences[&handle];
+                    (path.clone(), *snapshot, metadata.size)
+                })
+            }
+            ObjectOrdering::Snapshot => {
+                let occurrences = index.object_first_occurrences();
+                index.compute_object_offsets_and_ordering_by(|handle, _| {
+                    occurrences[&handle].clone()
+                })
+            }
+            ObjectOrdering::Similarity => {
+                let mut hashes = HashMap::new();
+                self.for_each_object(
+                    &index,
+                    &index.object_handles(),
+                    sources,
+                    packed_objects,
+                    opts.num_frames,
+                    |handle, buf| {
+                        hashes.insert(handle, similarity_hash(buf));
+                        Ok(())
+                    },
+                )?;
+                index.compute_object_offsets_and_ordering_by(|handle, metadata| {
+                    (hashes[&handle], metadata.size)
+                })
+            }
+            _ => index.compute_object_offsets_and_ordering(),
+        })
+    }
 
+    /// Reads the contents of the objects one at a time, in order, and passes
+    /// them to `visit`. To bound the memory used, the objects are split into
+    /// `num_partitions` partitions, and the objects which have to be read from
+    /// a source pack are decompressed one partition at a time.
+    fn for_each_object<F>(
+        &self,
+        index: &PackIndex,
+        objects: &[Handle],
+        sources: &[PackId],
+        packed_objects: &HashMap<ObjectChecksum, (usize, ObjectMetadata)>,
+        num_partitions: u32,
+        mut visit: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(Handle, &[u8]) -> Result<(), Error>,
+    {
+        for objects in index.objects_partitioned_by_size(num_partitions, objects) {
+            let mut packed_bufs =
+                self.read_packed_objects(objects, index, sources, packed_objects)?;
+            for &handle in objects {
+                let checksum = index.handle_to_checksum(handle);
+                let buf = match packed_bufs.remove(checksum) {
+                    Some(buf) => buf,
+                    None => fs::read(self.loose_object_path(checksum))?,
+                };
+                visit(handle, &buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decompresses those of the given objects which have to be read from a
+    /// source pack, see [`Repository::create_pack`]. The contents of the
+    /// objects are verified against their checksums.
+    fn read_packed_objects(
+        &self,
+        objects: &[Handle],
+        index: &PackIndex,
+        sources: &[PackId],
+        packed_objects: &HashMap<ObjectChecksum, (usize, ObjectMetadata)>,
+    ) -> Result<HashMap<ObjectChecksum, Vec<u8>>, Error> {
+        let mut source_entries = vec![vec![]; sources.len()];
+        for &handle in objects {
+            let checksum = index.handle_to_checksum(handle);
+            if let Some((source_index, obj_metadata)) = packed_objects.get(checksum) {
+                source_entries[*source_index].push(FileEntry::new(
+                    OsString::new(),
+                    *checksum,
+                    obj_metadata.clone(),
+                    FileMetadata::default(),
+                ));
+            }
+        }
+
+        let bufs = std::sync::Mutex::new(HashMap::new());
+        for (source, entries) in sources.iter().zip(source_entries) {
+            if entries.is_empty() {
+                continue;
+            }
+            self.open_pack_or_fetch(source)?
+                .read_entries(&entries, true, 1, |entry, buf| {
+                    bufs.lock().unwrap().insert(entry.checksum, buf.to_vec());
+                    Ok(())
+                })?;
+        }
+        Ok(bufs.into_inner().unwrap())
+    }
+
+    /// Turns the snapshots of a pack back into loose snapshots, by
+    /// decompressing its objects into the loose object store and writing a
+    /// loose index for each snapshot. Returns the loose snapshots.
+    ///
+    /// Snapshots which are already loose with the same contents are skipped.
+    /// If the contents differ, the existing loose snapshot is only replaced
+    /// when [`ExtractOptions::force`] is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `pack_id` - The pack to loosen.
+    /// * `opts` - The verification, force and worker count options to use.
+    pub fn loosen_pack(
+        &mut self,
+        pack_id: &PackId,
+        opts: &ExtractOptions,
+    ) -> Result<Vec<SnapshotId>, Error> {
+        if self.is_pack_loose(pack_id) {
+            return Ok(vec![]);
+        }
+        let index = self.load_index(pack_id)?;
+
+        // Check for conflicts before writing anything.
+        let loose_path = self.data_dir().join(PACKS_DIR).join(LOOSE_DIR);
+        let mut skipped = HashSet::new();
+        for tag in index.snapshot_tags() {
+            let loose_pack_id = PackId::Pack(format!("{}/{}", LOOSE_DIR, tag));
+            if !self.is_pack_loose(&loose_pack_id) {
+                continue;
+            }
+            let loose_index = self.load_index(&loose_pack_id)?;
+            if loose_index.compute_snapshot_checksum(tag) == index.compute_snapshot_checksum(tag) {
+                info!("Skipping {}, which is already loose", tag);
+                skipped.insert(tag.to_owned());
+            } else if !opts.force() {
+                return Err(PackError::SnapshotAlreadyExists(
+                    loose_pack_id.to_string(),
+                    tag.into(),
+                )
+                .into());
+            }
+        }
+
+        // Write the objects before the indexes referencing them.
+        let temp_dir = self.temp_dir();
+        ensure_dir(&temp_dir)?;
+        let entries = index
+            .object_checksums()
+            .filter(|checksum| !self.loose_object_path(checksum).exists())
+            .map(|checksum| {
+                FileEntry::new(
+                    OsString::new(),
+                    *checksum,
+                    index.object_metadata(checksum).clone(),
+                    FileMetadata::default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        info!("Loosening {} objects...", entries.len());
+        self.open_pack_or_fetch(pack_id)?.read_entries(
+            &entries,
+            opts.verify(),
+            opts.num_workers(),
+            |entry, buf| Ok(self.write_loose_object(buf, &temp_dir, &entry.checksum)?),
+        )?;
+
+        let mut snapshots = vec![];
+        let result = index.for_each_snapshot(|tag, entries| {
+            let snapshot = SnapshotId::new(PackId::Pack(format!("{}/{}", LOOSE_DIR, tag)), tag);
+            let r = snapshot.map_err(Error::from).and_then(|snapshot| {
+                if !skipped.contains(tag) {
+                    let entries = entries.iter().map(|entry| {
+                        FileEntry::new(
+                            entry.path.clone(),
+                            entry.checksum,
+                            ObjectMetadata {
+                                offset: LOOSE_OBJECT_OFFSET,
+                                size: entry.obj_metadata.size,
+                            },
+                            entry.file_metadata.clone(),
+                        )
+                    });
+                    let mut loose_index = PackIndex::with_hash_algorithm(index.hash_algorithm());
+                    loose_index.push_snapshot(tag.to_owned(), entries)?;
+                    loose_index.copy_attributes_from(&index);
+
+                    let index_path = loose_path.join(format!("{}.{}", tag, PACK_INDEX_EXTENSION));
+                    ensure_dir(index_path.parent().unwrap())?;
+                    loose_index.save(index_path)?;
+                }
+                snapshots.push(snapshot);
+                Ok(())
+            });
+            match r {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(e) => ControlFlow::Break(e),
+            }
+        })?;
+        if let Some(e) = result {
+            return Err(e);
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Deletes the .pack and .pack.idx files of the pack.
+    pub fn remove_pack(&mut self, pack_id: &PackId) -> Result<(), Error> {
+        let PackId::Pack(pack_name) = pack_id;
+        let packs_dir = self.data_dir.join(PACKS_DIR);
+        for extension in [PACK_EXTENSION, PACK_INDEX_EXTENSION] {
+            match fs::remove_file(packs_dir.join(format!("{}.{}", pack_name, extension))) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                r => r,
+            }?;
+        }
         Ok(())
     }
 
@@ -791,19 +2073,96 @@ impl Repository {
         Ok(())
     }
 
-    /// Updates the HEAD snapshot id.
-    pub fn update_head(&mut self, snapshot_id: &SnapshotId) -> Result<(), Error> {
+    /// Deletes the loose objects which are not referenced by any loose
+    /// snapshot, as well as any stale temporary files left behind by
+    /// interrupted operations.
+    ///
+    /// NOTE: This must not run concurrently with other operations on the
+    /// repository, since the objects of a new snapshot are written before its
+    /// index.
+    ///
+    /// # Arguments
+    ///
+    /// * `dry_run` - Only report what would be deleted, without deleting anything.
+    pub fn gc(&self, dry_run: bool) -> Result<GcReport, Error> {
+        let mut referenced = HashSet::new();
+        for pack_id in self.loose_packs()? {
+            let index = self.load_index(&pack_id)?;
+            referenced.extend(index.object_checksums().copied());
+        }
+        info!("Found {} referenced loose objects", referenced.len());
+
+        let mut report = GcReport::default();
+
+        let loose_dir = self.data_dir.join(LOOSE_DIR);
+        let mut unreferenced = vec![];
+        if loose_dir.exists() {
+            for dirent in WalkDir::new(&loose_dir).sort_by_file_name() {
+                let dirent = dirent?;
+                if !dirent.file_type().is_file() {
+                    continue;
+                }
+                match self.loose_object_checksum(dirent.path()) {
+                    Some(checksum) if referenced.contains(&checksum) => {}
+                    Some(_) => unreferenced.push((dirent.metadata()?.len(), dirent.into_path())),
+                    None => warn!(
+                        "Unexpected file in the loose object store: {}",
+                        dirent.path().display()
+                    ),
+                }
+            }
+        }
+
+        let mut dir_queue = EmptyDirectoryCleanupQueue::new();
+        for (size, path) in unreferenced {
+            if !dry_run {
+                fs::remove_file(&path)?;
+                dir_queue.enqueue(path.parent().unwrap(), loose_dir.clone())?;
+            }
+            report.removed_object_count += 1;
+            report.reclaimed_bytes += size;
+        }
+        dir_queue.process()?;
+
+        let temp_dir = self.temp_dir();
+        if temp_dir.exists() {
+            for dirent in fs::read_dir(&temp_dir)? {
+                let path = dirent?.path();
+                let size = fs::symlink_metadata(&path)?.len();
+                if AtomicCreateFile::prune_stale_temp_file(&path, dry_run) {
+                    report.removed_temp_file_count += 1;
+                    report.reclaimed_bytes += size;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Points HEAD to the snapshot, recording the [`SparseSpec`] which was
+    /// used to extract it. The change is appended to the reflog (see
+    /// [`Self::reflog`]), along with the `operation` which made it.
+    pub fn update_head(
+        &mut self,
+        snapshot_id: &SnapshotId,
+        sparse_spec: &SparseSpec,
+        operation: &str,
+    ) -> Result<(), Error> {
+        // A corrupt HEAD is about to be replaced, so it is not an error here.
+        let old_head = self.read_head().ok().and_then(|(head, _)| head);
         let data_dir = self.data_dir();
         let snapshot_string = format!("{}\n", snapshot_id);
         ensure_dir(&self.temp_dir())?;
 
         #[cfg(target_family = "windows")]
         let snapshot_string = Self::replace_back_to_slash(&snapshot_string);
+        let head_string = snapshot_string + &sparse_spec.to_head_lines();
         write_file_atomic(
-            snapshot_string.as_bytes(),
+            head_string.as_bytes(),
             &self.temp_dir(),
             &data_dir.join(HEAD_FILE),
         )?;
+        self.append_reflog(old_head, snapshot_id, operation)?;
 
         Ok(())
     }
@@ -834,11 +2193,26 @@ impl Repository {
         self.progress_reporter_factory = Box::new(factory);
     }
 
+    /// Returns the algorithm used to verify the objects of the loose pack, or
+    /// [`None`] if they should not be verified.
+    fn loose_verify_algorithm(
+        &self,
+        pack_id: &PackId,
+        verify: bool,
+    ) -> Result<Option<HashAlgorithm>, Error> {
+        if !verify {
+            return Ok(None);
+        }
+        Ok(Some(self.load_index(pack_id)?.hash_algorithm()))
+    }
+
+    /// Copies the loose objects of the entries to `path`. If `verify` is set,
+    /// the checksums of the copies are verified using that algorithm.
     fn copy_loose_entries(
         &mut self,
         entries: &[FileEntry],
         path: &Path,
-        verify: bool,
+        verify: Option<HashAlgorithm>,
     ) -> Result<(), Error> {
         let mut dest_paths = vec![];
         let mut dest_path = PathBuf::new();
@@ -883,8 +2257,8 @@ impl Repository {
             .unwrap();
         }
 
-        if verify {
-            let checksums = batch::compute_checksums(&dest_paths)?;
+        if let Some(hash_algorithm) = verify {
+            let checksums = batch::compute_checksums(&dest_paths, hash_algorithm)?;
             let expected_checksums = entries.iter().map(|e| &e.checksum);
             for (expected, actual) in expected_checksums.zip(checksums) {
                 if *expected != actual {
@@ -899,7 +2273,10 @@ impl Repository {
     /// Returns the pair of lists (`added`, `removed`). `added` contains the entries from
     /// `to_entries` which are not present in `from_entries`. `removed` contains the entries
     /// from `from_entries` which are not present in `to_entries`.
-    fn compute_entry_diff(
+    ///
+    /// Entries are compared by path and checksum, so a file whose contents
+    /// changed is listed in both. Changes to [`FileMetadata`] alone are ignored.
+    pub fn compute_entry_diff(
         from_entries: &[FileEntry],
         to_entries: &[FileEntry],
     ) -> (Vec<FileEntry>, Vec<FileEntry>) {
@@ -967,18 +2344,6 @@ impl Repository {
         temp_dir
     }
 
-    pub fn rewrite_loose_object(
-        &self,
-        file_path: OsString,
-        checksum: &ObjectChecksum,
-    ) -> io::Result<()> {
-        let temp_dir = self.temp_dir();
-        let buf = fs::read(file_path)?;
-        let buf = Cursor::new(buf);
-        self.write_loose_object(buf, &temp_dir, checksum)?;
-        Ok(())
-    }
-
     /// Atomically writes an object to the loose object store.
     ///
     /// # Arguments
@@ -1018,6 +2383,21 @@ impl Repository {
         obj_path
     }
 
+    /// The inverse of [`Repository::loose_object_path`]. Returns [`None`] if
+    /// the path does not point into the loose object store.
+    pub fn loose_object_checksum(&self, path: &Path) -> Option<ObjectChecksum> {
+        let relative_path = path.strip_prefix(self.data_dir.join(LOOSE_DIR)).ok()?;
+        let checksum_str = relative_path
+            .components()
+            .map(|c| c.as_os_str().to_str())
+            .collect::<Option<String>>()?;
+        let checksum = ObjectChecksum::from_slice(&hex::decode(checksum_str).ok()?)?;
+        let known_size = HashAlgorithm::ALL
+            .iter()
+            .any(|a| a.checksum_size() == checksum.len());
+        (known_size && self.loose_object_path(&checksum) == path).then_some(checksum)
+    }
+
     /// Updates all remotes and their associated .pack.idx files.
     pub fn update_remotes(&self) -> Result<(), Error> {
         let mut remotes_dir = self.data_dir();
@@ -1121,6 +2501,44 @@ fn is_elfshaker_data_path(_data_dir: &Path, p: &Path) -> bool {
     }*/
 }
 
+/// Records the current time in the [`CREATED_ATTRIBUTE`], unless it is
+/// already set.
+fn with_creation_time(mut attributes: Attributes) -> Attributes {
+    attributes
+        .entry(CREATED_ATTRIBUTE.to_owned())
+        .or_insert_with(|| Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+    attributes
+}
+
+/// Converts a path from a tar archive to a `/`-separated path relative to the
+/// root of the snapshot. Returns None for absolute paths, paths which point
+/// outside of the root, and paths which are not valid UTF-8.
+fn archive_relative_path(path: &Path) -> Option<String> {
+    let mut components = vec![];
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::Normal(name) => components.push(name.to_str()?),
+            _ => return None,
+        }
+    }
+    if components.is_empty() {
+        None
+    } else {
+        Some(components.join("/"))
+    }
+}
+
+/// Parses a PAX timestamp (`<seconds>[.<fraction>]`) into seconds and nanoseconds.
+fn parse_pax_time(value: &str) -> Option<(i64, u32)> {
+    let (secs, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if !fraction.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = format!("{:0<9}", &fraction[..std::cmp::min(fraction.len(), 9)]);
+    Some((secs.parse().ok()?, nanos.parse().ok()?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1144,10 +2562,10 @@ mod tests {
 
     #[test]
     fn building_loose_object_paths_works() {
-        let checksum = [
+        let checksum = ObjectChecksum::from([
             0xFA, 0xF0, 0xDE, 0xAD, 0xBE, 0xEF, 0xBA, 0xDC, 0x0D, 0xE0, 0xFA, 0xF0, 0xDE, 0xAD,
             0xBE, 0xEF, 0xBA, 0xDC, 0x0D, 0xE0,
-        ];
+        ]);
         let repo = Repository {
             path: "/repo".into(),
             data_dir: ("/repo/".to_owned() + repo::REPO_DIR).into(),
@@ -1167,6 +2585,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parsing_loose_object_paths_works() {
+        let checksum = ObjectChecksum::from([
+            0xFA, 0xF0, 0xDE, 0xAD, 0xBE, 0xEF, 0xBA, 0xDC, 0x0D, 0xE0, 0xFA, 0xF0, 0xDE, 0xAD,
+            0xBE, 0xEF, 0xBA, 0xDC, 0x0D, 0xE0,
+        ]);
+        let repo = Repository {
+            path: "/repo".into(),
+            data_dir: ("/repo/".to_owned() + repo::REPO_DIR).into(),
+            progress_reporter_factory: Box::new(|_| ProgressReporter::dummy()),
+        };
+        let path = repo.loose_object_path(&checksum);
+        assert_eq!(Some(checksum), repo.loose_object_checksum(&path));
+
+        let loose_dir = repo.data_dir.join(LOOSE_DIR);
+        assert_eq!(None, repo.loose_object_checksum(&loose_dir.join("fa/f0")));
+        assert_eq!(
+            None,
+            repo.loose_object_checksum(&loose_dir.join("faf0deadbeefbadc0de0faf0deadbeefbadc0de0"))
+        );
+        assert_eq!(
+            None,
+            repo.loose_object_checksum(Path::new(
+                "/elsewhere/fa/f0/deadbeefbadc0de0faf0deadbeefbadc0de0"
+            ))
+        );
+    }
+
+    #[test]
+    fn archive_relative_path_works() {
+        assert_eq!(
+            Some("a/b".to_owned()),
+            archive_relative_path(Path::new("a/b"))
+        );
+        assert_eq!(
+            Some("a/b".to_owned()),
+            archive_relative_path(Path::new("./a/./b"))
+        );
+        assert_eq!(None, archive_relative_path(Path::new("./")));
+        assert_eq!(None, archive_relative_path(Path::new("/etc/passwd")));
+        assert_eq!(None, archive_relative_path(Path::new("a/../../b")));
+    }
+
+    #[test]
+    fn parse_pax_time_works() {
+        assert_eq!(Some((1600000000, 0)), parse_pax_time("1600000000"));
+        assert_eq!(
+            Some((1600000000, 123456789)),
+            parse_pax_time("1600000000.123456789")
+        );
+        assert_eq!(
+            Some((1600000000, 500000000)),
+            parse_pax_time("1600000000.5")
+        );
+        assert_eq!(
+            Some((1600000000, 123456789)),
+            parse_pax_time("1600000000.1234567891")
+        );
+        assert_eq!(None, parse_pax_time("1600000000.x"));
+        assert_eq!(None, parse_pax_time("soon"));
+    }
+
     #[test]
     fn data_dir_detected() {
         let data_dir = Path::new(repo::REPO_DIR);
@@ -1192,8 +2672,8 @@ mod tests {
     #[test]
     fn compute_entry_diff_finds_updates() {
         let path = "/path/to/A";
-        let old_checksum = [0; 20];
-        let new_checksum = [1; 20];
+        let old_checksum = ObjectChecksum::from([0; 20]);
+        let new_checksum = ObjectChecksum::from([1; 20]);
         let old_entries = [FileEntry::new(
             path.into(),
             old_checksum,
@@ -1216,10 +2696,10 @@ mod tests {
     #[test]
     fn compute_entry_diff_finds_update_of_duplicated() {
         let path_a = "/path/to/A";
-        let path_a_old_checksum = [0; 20];
+        let path_a_old_checksum = ObjectChecksum::from([0; 20]);
         let path_b = "/path/to/B";
-        let path_b_old_checksum = [0; 20];
-        let path_a_new_checksum = [1; 20];
+        let path_b_old_checksum = ObjectChecksum::from([0; 20]);
+        let path_a_new_checksum = ObjectChecksum::from([1; 20]);
         let old_entries = [
             FileEntry::new(
                 path_a.into(),
@@ -1251,11 +2731,11 @@ mod tests {
     #[test]
     fn compute_entry_diff_path_switch() {
         let path_a = "/path/to/A";
-        let path_a_old_checksum = [0; 20];
-        let path_a_new_checksum = [1; 20];
+        let path_a_old_checksum = ObjectChecksum::from([0; 20]);
+        let path_a_new_checksum = ObjectChecksum::from([1; 20]);
         let path_b = "/path/to/B";
-        let path_b_old_checksum = [1; 20];
-        let path_b_new_checksum = [0; 20];
+        let path_b_old_checksum = ObjectChecksum::from([1; 20]);
+        let path_b_new_checksum = ObjectChecksum::from([0; 20]);
         let old_entries = [
             FileEntry::new(
                 path_a.into(),
@@ -0,0 +1,176 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+//! The seek table of the Zstandard seekable format, which lists the sizes of
+//! the Zstandard frames in a .pack file, so that reads can start from the
+//! frame closest to an object rather than from the start of a pack frame.
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::error::Error;
+
+/// The magic number of the skippable frame containing the seek table.
+const SEEK_TABLE_MAGIC: u32 = 0x184D2A5E;
+/// The magic number at the very end of a seekable file.
+const SEEKABLE_MAGIC: u32 = 0x8F92EAB1;
+/// The size of the footer at the end of the seek table.
+const FOOTER_SIZE: u64 = 9;
+/// The bit of the seek table descriptor set if the entries have checksums.
+const CHECKSUM_FLAG: u8 = 1 << 7;
+
+/// The compressed and decompressed size of a Zstandard frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeekTableEntry {
+    pub compressed_size: u32,
+    pub decompressed_size: u32,
+}
+
+/// A position at which decompression can start.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeekPoint {
+    /// The offset of the Zstandard frame in the .pack file.
+    pub offset: u64,
+    /// The offset of the frame contents in the decompressed data of the pack.
+    pub decompressed_offset: u64,
+}
+
+/// Lists the Zstandard frames of a .pack file, in order, starting with the
+/// header of the pack (which decompresses to nothing).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SeekTable {
+    entries: Vec<SeekTableEntry>,
+}
+
+impl SeekTable {
+    pub fn new(entries: Vec<SeekTableEntry>) -> Self {
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[SeekTableEntry] {
+        &self.entries
+    }
+
+    /// The size of the skippable frame containing the seek table.
+    pub fn frame_size(&self) -> u64 {
+        8 + self.entries.len() as u64 * 8 + FOOTER_SIZE
+    }
+
+    /// The positions at which decompression can start, in order.
+    pub fn seek_points(&self) -> Vec<SeekPoint> {
+        let mut offset = 0;
+        let mut decompressed_offset = 0;
+        let mut points = vec![];
+        for entry in &self.entries {
+            if entry.decompressed_size != 0 {
+                points.push(SeekPoint {
+                    offset,
+                    decompressed_offset,
+                });
+            }
+            offset += entry.compressed_size as u64;
+            decompressed_offset += entry.decompressed_size as u64;
+        }
+        points
+    }
+
+    /// Writes the seek table as a skippable frame. Returns the number of bytes written.
+    pub fn write(&self, mut writer: impl Write) -> io::Result<u64> {
+        let content_size = self.frame_size() - 8;
+        writer.write_all(&SEEK_TABLE_MAGIC.to_le_bytes())?;
+        writer.write_all(&(content_size as u32).to_le_bytes())?;
+        for entry in &self.entries {
+            writer.write_all(&entry.compressed_size.to_le_bytes())?;
+            writer.write_all(&entry.decompressed_size.to_le_bytes())?;
+        }
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        writer.write_all(&[0])?;
+        writer.write_all(&SEEKABLE_MAGIC.to_le_bytes())?;
+        Ok(self.frame_size())
+    }
+
+    /// Reads the seek table at the end of a file of the given size. Returns
+    /// [`None`] if the file does not end with a seek table.
+    pub fn read<R: Read + Seek>(mut reader: R, file_size: u64) -> Result<Option<Self>, Error> {
+        if file_size < 8 + FOOTER_SIZE {
+            return Ok(None);
+        }
+        let mut footer = [0u8; FOOTER_SIZE as usize];
+        reader.seek(SeekFrom::Start(file_size - FOOTER_SIZE))?;
+        reader.read_exact(&mut footer)?;
+        if read_u32(&footer[5..]) != SEEKABLE_MAGIC {
+            return Ok(None);
+        }
+        let num_entries = read_u32(&footer[..4]) as u64;
+        let has_checksums = footer[4] & CHECKSUM_FLAG != 0;
+        let entry_size = if has_checksums { 12 } else { 8 };
+        let frame_size = 8 + num_entries * entry_size + FOOTER_SIZE;
+        if frame_size > file_size {
+            return Err(Error::CorruptPack);
+        }
+
+        let mut frame = vec![0u8; (frame_size - FOOTER_SIZE) as usize];
+        reader.seek(SeekFrom::Start(file_size - frame_size))?;
+        reader.read_exact(&mut frame)?;
+        if read_u32(&frame[..4]) != SEEK_TABLE_MAGIC
+            || read_u32(&frame[4..8]) as u64 != frame_size - 8
+        {
+            return Err(Error::CorruptPack);
+        }
+        let entries = frame[8..]
+            .chunks(entry_size as usize)
+            .map(|entry| SeekTableEntry {
+                compressed_size: read_u32(&entry[..4]),
+                decompressed_size: read_u32(&entry[4..8]),
+            })
+            .collect();
+        Ok(Some(Self { entries }))
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seek_table_roundtrips() {
+        let table = SeekTable::new(vec![
+            SeekTableEntry {
+                compressed_size: 20,
+                decompressed_size: 0,
+            },
+            SeekTableEntry {
+                compressed_size: 100,
+                decompressed_size: 1000,
+            },
+            SeekTableEntry {
+                compressed_size: 50,
+                decompressed_size: 400,
+            },
+        ]);
+        let mut file = vec![0u8; 170];
+        assert_eq!(table.frame_size(), table.write(&mut file).unwrap());
+
+        let read = SeekTable::read(io::Cursor::new(&file), file.len() as u64).unwrap();
+        assert_eq!(Some(table.clone()), read);
+        assert_eq!(
+            vec![
+                SeekPoint {
+                    offset: 20,
+                    decompressed_offset: 0
+                },
+                SeekPoint {
+                    offset: 120,
+                    decompressed_offset: 1000
+                },
+            ],
+            table.seek_points()
+        );
+
+        // Files without a seek table are recognised.
+        let read = SeekTable::read(io::Cursor::new(&file[..170]), 170).unwrap();
+        assert_eq!(None, read);
+    }
+}
@@ -0,0 +1,144 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+use super::error::Error;
+use crate::packidx::FileEntry;
+
+const INCLUDE_PREFIX: &str = "include ";
+const EXCLUDE_PREFIX: &str = "exclude ";
+
+/// Selects a subset of the files in a snapshot by their path.
+///
+/// A file is selected if it matches any of the `include` patterns (or there
+/// are none), and none of the `exclude` patterns. The patterns are globs
+/// matched against the path of the file relative to the worktree, where `*`
+/// does not match `/` and `**` matches any number of directories.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SparseSpec {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl SparseSpec {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+
+    /// Returns true if the spec selects all files.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Compiles the patterns.
+    pub fn matcher(&self) -> Result<SparseMatcher, Error> {
+        let include = if self.include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&self.include)?)
+        };
+        let exclude = build_glob_set(&self.exclude)?;
+        Ok(SparseMatcher { include, exclude })
+    }
+
+    /// Parses the spec from the lines of HEAD following the snapshot.
+    pub(super) fn from_head_lines<'a, I>(lines: I) -> Result<Self, Error>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut spec = Self::default();
+        for line in lines.filter(|line| !line.is_empty()) {
+            if let Some(pattern) = line.strip_prefix(INCLUDE_PREFIX) {
+                spec.include.push(pattern.to_owned());
+            } else if let Some(pattern) = line.strip_prefix(EXCLUDE_PREFIX) {
+                spec.exclude.push(pattern.to_owned());
+            } else {
+                return Err(Error::CorruptHead);
+            }
+        }
+        Ok(spec)
+    }
+
+    /// Formats the spec as the lines of HEAD following the snapshot.
+    pub(super) fn to_head_lines(&self) -> String {
+        let include = self.include.iter().map(|p| (INCLUDE_PREFIX, p));
+        let exclude = self.exclude.iter().map(|p| (EXCLUDE_PREFIX, p));
+        include
+            .chain(exclude)
+            .map(|(prefix, pattern)| format!("{}{}\n", prefix, pattern))
+            .collect()
+    }
+}
+
+/// The compiled form of a [`SparseSpec`].
+pub struct SparseMatcher {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl SparseMatcher {
+    /// Returns true if the path is selected.
+    pub fn is_match<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        self.include.as_ref().is_none_or(|set| set.is_match(path)) && !self.exclude.is_match(path)
+    }
+
+    /// Drops the entries which are not selected.
+    pub fn filter(&self, mut entries: Vec<FileEntry>) -> Vec<FileEntry> {
+        entries.retain(|entry| self.is_match(&entry.path));
+        entries
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(include: &[&str], exclude: &[&str]) -> SparseSpec {
+        SparseSpec::new(
+            include.iter().map(|p| p.to_string()).collect(),
+            exclude.iter().map(|p| p.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn sparse_matcher_works() {
+        let matcher = spec(&["bin/clang", "lib/**"], &["**/*.a"])
+            .matcher()
+            .unwrap();
+        assert!(matcher.is_match("bin/clang"));
+        assert!(!matcher.is_match("bin/clang++"));
+        assert!(matcher.is_match("lib/libLLVM.so"));
+        assert!(matcher.is_match("lib/clang/13/include/stddef.h"));
+        assert!(!matcher.is_match("lib/libLLVMCore.a"));
+        assert!(!matcher.is_match("include/llvm/IR/Module.h"));
+
+        let matcher = spec(&[], &["*.o"]).matcher().unwrap();
+        assert!(matcher.is_match("bin/clang"));
+        assert!(!matcher.is_match("main.o"));
+        // * does not cross directories
+        assert!(matcher.is_match("obj/main.o"));
+    }
+
+    #[test]
+    fn sparse_spec_head_lines_roundtrip() {
+        let original = spec(&["bin/clang", "lib/**"], &["**/*.a"]);
+        let text = original.to_head_lines();
+        assert_eq!("include bin/clang\ninclude lib/**\nexclude **/*.a\n", text);
+        let parsed = SparseSpec::from_head_lines(text.lines()).unwrap();
+        assert_eq!(original, parsed);
+
+        assert!(SparseSpec::from_head_lines("".lines()).unwrap().is_empty());
+        assert!(SparseSpec::from_head_lines("bogus".lines()).is_err());
+    }
+}
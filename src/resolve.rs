@@ -0,0 +1,36 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use clap::{App, Arg, ArgMatches};
+use std::error::Error;
+
+use super::utils::open_repo_from_cwd;
+
+pub const SUBCOMMAND: &str = "resolve";
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let repo = open_repo_from_cwd()?;
+
+    for snapshot in matches.values_of("snapshots").unwrap() {
+        println!("{}", repo.find_snapshot(snapshot)?);
+    }
+
+    Ok(())
+}
+
+pub fn get_app() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .about(
+            "Prints the snapshots in the canonical <pack>:<snapshot> form. Accepts \
+            anything the other commands accept as a snapshot: tags, refs, @{<n>}, \
+            <pack>:@first and <pack>:@last, followed by offsets in the order of the \
+            snapshots (~<n> to go back, ^+<n> to go forward).",
+        )
+        .arg(
+            Arg::with_name("snapshots")
+                .required(true)
+                .index(1)
+                .multiple(true)
+                .help("The snapshots to resolve (e.g. my-pack:@last~1)."),
+        )
+}
@@ -2,12 +2,9 @@
 //! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
 
 use clap::{App, Arg, ArgMatches};
-use rand::RngCore;
 use std::{collections::HashMap, error::Error};
 
 use super::utils::open_repo_from_cwd;
-use crate::repo::fs::open_file;
-use crate::repo::ExtractOptions;
 
 pub const SUBCOMMAND: &str = "show";
 
@@ -15,17 +12,11 @@ pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     let snapshot = matches.value_of("snapshot").unwrap();
     let paths: Vec<_> = matches.values_of_os("path").unwrap().collect();
 
-    let mut repo = open_repo_from_cwd()?;
+    let repo = open_repo_from_cwd()?;
     let snapshot = repo.find_snapshot(snapshot)?;
-    let pack_index = repo.load_index(snapshot.pack())?;
 
-    let entries: HashMap<_, _> = pack_index
-        .entries_from_handles(
-            pack_index
-                .resolve_snapshot(snapshot.tag())
-                .expect("failed to resolve snapshot")
-                .iter(), // Temporary.
-        )?
+    let entries: HashMap<_, _> = repo
+        .load_snapshot_entries(&snapshot)?
         .into_iter()
         .map(|e| (e.path.clone(), e))
         .collect();
@@ -41,30 +32,13 @@ pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
         None => return Err("Some of the paths did not match files in the snapshot!".into()),
     };
 
-    let temp_dir = {
-        let mut bytes = [0u8; 32];
-        rand::thread_rng().fill_bytes(&mut bytes);
-        hex::encode(bytes)
-    };
-    let temp_dir = std::env::temp_dir().join(temp_dir);
-
-    let mut do_extract = || -> Result<(), Box<dyn Error>> {
-        let mut opts = ExtractOptions::default();
-        opts.set_verify(true);
-
-        repo.extract_entries(snapshot.pack(), &selected_entries, &temp_dir, opts)?;
-
-        // Dump the contents of all entries to stdout.
-        for e in &selected_entries {
-            let path = temp_dir.join(&e.path);
-            std::io::copy(&mut open_file(path)?, &mut std::io::stdout())?;
-        }
-        Ok(())
-    };
-
-    let extract_result = do_extract();
-    std::fs::remove_dir_all(temp_dir)?;
-    extract_result?;
+    // Stream the contents of all entries to stdout.
+    repo.write_entries(
+        snapshot.pack(),
+        &selected_entries,
+        true,
+        std::io::stdout().lock(),
+    )?;
 
     Ok(())
 }
@@ -1,13 +1,12 @@
 //! SPDX-License-Identifier: Apache-2.0
 //! Copyright (C) 2023 Tipi technologies or its affiliates and Contributors. All rights reserved.
 
-use crate::packidx::PackError;
+use crate::packidx::{HashAlgorithm, ObjectChecksum, PackError};
 use crate::repo::{
     fs::{get_last_modified, open_file},
     Repository, SnapshotId, REPO_DIR,
 };
 use clap::{App, Arg, ArgMatches};
-use crypto::{digest::Digest, sha1::Sha1};
 use filetime::FileTime;
 use log::info;
 use std::{
@@ -167,7 +166,9 @@ fn probe_snapshot_files(
         .resolve_snapshot(snapshot.tag())
         .expect("failed to resolve snapshot"); // TODO: Temporary.
 
-    for entry in idx.entries_from_handles(handles.iter())? {
+    // Only the files selected when HEAD was extracted are in the workspace.
+    let matcher = repo.read_head_sparse_spec()?.matcher()?;
+    for entry in matcher.filter(idx.entries_from_handles(handles.iter())?) {
         let pathbuf = repo.path().join(&entry.path);
         let path = pathbuf.as_path();
 
@@ -214,7 +215,7 @@ fn probe_snapshot_files(
                     info!("same mtime \"{}\"", path.display());
                     false
                 } else {
-                    let workspace_checksum = calculate_sha1(&path)?;
+                    let workspace_checksum = calculate_checksum(&path, idx.hash_algorithm())?;
 
                     if entry.checksum != workspace_checksum {
                         info!(
@@ -281,25 +282,24 @@ fn add_untracked_files(
     list
 }
 
-fn calculate_sha1(path: &Path) -> std::io::Result<[u8; 20]> {
+fn calculate_checksum(
+    path: &Path,
+    hash_algorithm: HashAlgorithm,
+) -> std::io::Result<ObjectChecksum> {
     let file = open_file(path)?;
     let mut file_handler = BufReader::new(file);
 
-    let mut checksum = [0u8; 20];
-
-    let mut hasher = Sha1::new();
+    let mut hasher = hash_algorithm.hasher();
 
     let mut buffer = vec![0u8; 4096];
     while let Ok(bytes_read) = file_handler.read(&mut buffer) {
         if bytes_read == 0 {
             break;
         }
-        hasher.input(&buffer[..bytes_read]);
+        hasher.update(&buffer[..bytes_read]);
     }
 
-    hasher.result(&mut checksum);
-
-    Ok(checksum)
+    Ok(hasher.finalize())
 }
 
 #[cfg(test)]
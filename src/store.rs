@@ -3,22 +3,34 @@
 
 use clap::{App, Arg, ArgMatches};
 use log::error;
-use std::{error::Error, ffi::OsStr, fs, io, path::PathBuf};
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fs,
+    io::{self, BufRead, BufReader, Read},
+    path::PathBuf,
+};
 use walkdir::WalkDir;
 
 use super::utils::open_repo_from;
 use crate::{
+    packidx::{Attributes, HashAlgorithm, CREATED_ATTRIBUTE, MESSAGE_ATTRIBUTE},
     repo::{self, PackId, Repository, SnapshotId},
     utils::open_repo_with_separate_worktree_from,
 };
 
 pub const SUBCOMMAND: &str = "store";
 
+/// The magic number at the start of each Zstandard frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
 pub fn do_store(
     elfshaker_repo_dir: PathBuf,
     worktree_dir: PathBuf,
     snapshot: &str,
     files: &Vec<PathBuf>,
+    hash_algorithm: Option<HashAlgorithm>,
+    attributes: Attributes,
 ) -> Result<(), Box<dyn Error>> {
     // Use snapshot name as pack name.
     let pack_id = PathBuf::from(format!("loose/{}", snapshot));
@@ -28,7 +40,35 @@ pub fn do_store(
     fs::create_dir_all(&elfshaker_repo_dir)?;
 
     let mut repo = open_repo_with_separate_worktree_from(&elfshaker_repo_dir, &worktree_dir)?;
-    repo.create_snapshot(&snapshot, files.into_iter())?;
+    let hash_algorithm = match hash_algorithm {
+        Some(hash_algorithm) => hash_algorithm,
+        None => repo.default_hash_algorithm()?,
+    };
+    repo.create_snapshot(&snapshot, files.into_iter(), hash_algorithm, attributes)?;
+
+    Ok(())
+}
+
+/// Creates a loose snapshot from the files in a tar archive read from `reader`.
+pub fn do_store_from_tar(
+    elfshaker_repo_dir: PathBuf,
+    worktree_dir: PathBuf,
+    snapshot: &str,
+    reader: impl Read,
+    hash_algorithm: Option<HashAlgorithm>,
+    attributes: Attributes,
+) -> Result<(), Box<dyn Error>> {
+    let pack_id = PackId::Pack(format!("loose/{}", snapshot));
+    let snapshot = SnapshotId::new(pack_id, snapshot)?;
+
+    fs::create_dir_all(&elfshaker_repo_dir)?;
+
+    let mut repo = open_repo_with_separate_worktree_from(&elfshaker_repo_dir, &worktree_dir)?;
+    let hash_algorithm = match hash_algorithm {
+        Some(hash_algorithm) => hash_algorithm,
+        None => repo.default_hash_algorithm()?,
+    };
+    repo.create_snapshot_from_tar(&snapshot, reader, hash_algorithm, attributes)?;
 
     Ok(())
 }
@@ -36,7 +76,27 @@ pub fn do_store(
 pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     let files_from = matches.value_of("files-from");
     let files0_from = matches.value_of("files0-from");
+    let from_tar = matches.value_of("from-tar");
     let snapshot = matches.value_of("snapshot").unwrap();
+    let hash_algorithm = matches
+        .value_of("hash")
+        .map(str::parse::<HashAlgorithm>)
+        .transpose()?;
+    let attributes = parse_attributes(
+        matches.value_of("message"),
+        matches.values_of("meta").into_iter().flatten(),
+    )?;
+
+    if let Some(archive) = from_tar {
+        return do_store_from_tar(
+            std::env::current_dir()?.join(repo::REPO_DIR),
+            std::env::current_dir()?,
+            snapshot,
+            open_archive(archive)?,
+            hash_algorithm,
+            attributes,
+        );
+    }
 
     if files_from.is_some() && files0_from.is_some() {
         error!("Cannot specify both --files-from and --files0-from!");
@@ -58,6 +118,8 @@ pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
         std::env::current_dir()?,
         &snapshot,
         &files,
+        hash_algorithm,
+        attributes,
     )
 }
 
@@ -88,6 +150,78 @@ pub fn get_app() -> App<'static, 'static> {
                 .value_name("file")
                 .help("Reads the NUL-separated (ASCII \\0) list of files to include in the snapshot from the specified file. '-' is taken to mean stdin."),
         )
+        .arg(
+            Arg::with_name("from-tar")
+                .takes_value(true)
+                .long("from-tar")
+                .value_name("archive")
+                .conflicts_with_all(&["files-from", "files0-from"])
+                .help("Creates the snapshot from the files in the specified tar archive (optionally compressed with Zstandard), instead of the files in the repository. HEAD is not updated. '-' is taken to mean stdin."),
+        )
+        .arg(
+            Arg::with_name("hash")
+                .takes_value(true)
+                .long("hash")
+                .value_name("algorithm")
+                .possible_values(HashAlgorithm::NAMES)
+                .help("The hash algorithm used to compute the checksums of the files. Snapshots can only be packed together if they use the same algorithm, so this defaults to the algorithm of the snapshot at HEAD (or of the most recent snapshot), and to blake3 in new repositories."),
+        )
+        .arg(
+            Arg::with_name("message")
+                .takes_value(true)
+                .short("m")
+                .long("message")
+                .value_name("message")
+                .help("A message describing the snapshot, shown by list and find."),
+        )
+        .arg(
+            Arg::with_name("meta")
+                .takes_value(true)
+                .long("meta")
+                .value_name("key=value")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Annotates the snapshot with the key and value (e.g. --meta commit=1a2b3c). Can be given multiple times."),
+        )
+}
+
+/// Collects the message and the `key=value` annotations of the snapshot.
+fn parse_attributes<'a>(
+    message: Option<&str>,
+    meta: impl Iterator<Item = &'a str>,
+) -> Result<Attributes, Box<dyn Error>> {
+    let mut attributes = Attributes::new();
+    for item in meta {
+        let (key, value) = match item.split_once('=') {
+            Some((key, value)) if !key.is_empty() => (key, value),
+            _ => return Err(format!("Expected key=value, got '{}'!", item).into()),
+        };
+        if key == MESSAGE_ATTRIBUTE || key == CREATED_ATTRIBUTE {
+            return Err(format!("The key '{}' is reserved!", key).into());
+        }
+        attributes.insert(key.to_owned(), value.to_owned());
+    }
+    if let Some(message) = message {
+        attributes.insert(MESSAGE_ATTRIBUTE.to_owned(), message.to_owned());
+    }
+    Ok(attributes)
+}
+
+/// Opens the tar archive, decompressing it if it starts with a Zstandard frame.
+fn open_archive(path: &str) -> io::Result<Box<dyn Read>> {
+    let mut reader: Box<dyn BufRead> = if path == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(fs::File::open(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("couldn't open {}", path))
+        })?))
+    };
+
+    if reader.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::with_buffer(reader)?))
+    } else {
+        Ok(reader)
+    }
 }
 
 #[cfg(unix)]
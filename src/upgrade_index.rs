@@ -0,0 +1,53 @@
+//! SPDX-License-Identifier: Apache-2.0
+//! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
+
+use clap::{App, Arg, ArgMatches};
+use std::{error::Error, str::FromStr};
+
+use super::utils::open_repo_from_cwd;
+use crate::repo::PackId;
+
+pub const SUBCOMMAND: &str = "upgrade-index";
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let repo = open_repo_from_cwd()?;
+
+    let packs = match matches.values_of("packs") {
+        Some(packs) => packs.map(PackId::from_str).collect::<Result<Vec<_>, _>>()?,
+        // The indexes of remote packs would be replaced on update anyway.
+        None => repo
+            .packs()?
+            .into_iter()
+            .filter(|pack_id| !repo.is_pack_remote(pack_id))
+            .collect(),
+    };
+
+    let mut upgraded = 0;
+    for pack_id in &packs {
+        if let Some((old_version, new_version)) = repo.upgrade_index(pack_id)? {
+            println!(
+                "Upgraded {} (version {} -> {})",
+                pack_id, old_version, new_version
+            );
+            upgraded += 1;
+        }
+    }
+    eprintln!("Upgraded {} of {} pack index(es).", upgraded, packs.len());
+
+    Ok(())
+}
+
+pub fn get_app() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .about(
+            "Rewrites pack indexes in the latest format, which records the hash \
+            algorithm and features of the index in a header. Older versions of \
+            elfshaker cannot read the upgraded indexes.",
+        )
+        .arg(
+            Arg::with_name("packs")
+                .index(1)
+                .multiple(true)
+                .help("The packs (or loose snapshots, as loose/<snapshot>) to upgrade. Defaults to all packs, except those fetched from remotes."),
+        )
+}
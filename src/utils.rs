@@ -2,10 +2,12 @@
 //! Copyright (C) 2021 Arm Limited or its affiliates and Contributors. All rights reserved.
 
 use crate::log::measure;
+use crate::packidx::{Attributes, CREATED_ATTRIBUTE, MESSAGE_ATTRIBUTE};
 use crate::progress::ProgressReporter;
 use crate::repo::{Error as RepoError, Repository};
 use log::info;
 use std::io::Write;
+use std::str::FromStr;
 use std::sync::{
     atomic::{AtomicIsize, Ordering},
     Arc,
@@ -96,6 +98,66 @@ pub fn format_size(bytes: u64) -> String {
     format!("{:.3}MiB", bytes as f64 / 1024.0 / 1024.0)
 }
 
+/// The header of the columns returned by [`format_snapshot_attributes`].
+pub const SNAPSHOT_ATTRIBUTES_HEADER: [&str; 3] = ["CREATED", "MESSAGE", "META"];
+
+/// Formats the creation time, the message and the other attributes of a
+/// snapshot as table columns.
+pub fn format_snapshot_attributes(attributes: &Attributes) -> [String; 3] {
+    let or_dash = |value: Option<&String>| value.cloned().unwrap_or_else(|| "-".to_owned());
+    let meta = attributes
+        .iter()
+        .filter(|(key, _)| *key != CREATED_ATTRIBUTE && *key != MESSAGE_ATTRIBUTE)
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>();
+    [
+        or_dash(attributes.get(CREATED_ATTRIBUTE)),
+        or_dash(attributes.get(MESSAGE_ATTRIBUTE)),
+        if meta.is_empty() {
+            "-".to_owned()
+        } else {
+            meta.join(",")
+        },
+    ]
+}
+
+/// Selects snapshots by their attributes. Parsed from `key=value`, which
+/// matches snapshots with that value, or `key`, which matches snapshots with
+/// any value.
+#[derive(Debug, PartialEq)]
+pub struct AttributeFilter {
+    key: String,
+    value: Option<String>,
+}
+
+impl AttributeFilter {
+    pub fn matches(&self, attributes: &Attributes) -> bool {
+        match (attributes.get(&self.key), &self.value) {
+            (Some(actual), Some(expected)) => actual == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+impl FromStr for AttributeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = match s.split_once('=') {
+            Some((key, value)) => (key, Some(value.to_owned())),
+            None => (s, None),
+        };
+        if key.is_empty() {
+            return Err(format!("Expected key=value or key, got '{}'!", s));
+        }
+        Ok(Self {
+            key: key.to_owned(),
+            value,
+        })
+    }
+}
+
 /// Opens the repo from the provided directory and logs some standard
 /// stats about the process.
 pub fn open_repo_with_separate_worktree_from(
@@ -1,7 +1,22 @@
 use assert_cmd::prelude::*;
 use assert_fs::prelude::*;
+use elfshaker::packidx::PackIndex;
 use predicates::prelude::*;
-use std::{fs::remove_file, process::Command};
+use std::{fs::remove_file, path::Path, process::Command};
+
+/// Rewrites the index without the attributes of its snapshots, like the
+/// indexes written by older versions of elfshaker.
+fn write_without_attributes(index_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let index = PackIndex::load(index_path)?;
+    let mut old_index = PackIndex::with_hash_algorithm(index.hash_algorithm());
+    for tag in index.snapshot_tags() {
+        let handles = index.resolve_snapshot(tag).unwrap();
+        let entries = index.entries_from_handles(handles.iter())?;
+        old_index.push_snapshot(tag.clone(), entries)?;
+    }
+    old_index.save(index_path)?;
+    Ok(())
+}
 
 // main use case: loosen and repackage in order to add a snapshot to an existing pack. Should be
 // faster than unpacking each single snapshot.
@@ -301,3 +316,1809 @@ fn symlink_to_directory() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn gc_removes_unreferenced_loose_objects() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: two loose snapshots with different contents
+    let files_list = temp.child("files.txt");
+    files_list
+        .write_str("foo.txt\n")
+        .expect("unable to write files.txt");
+    let foo_file = temp.child("foo.txt");
+    foo_file
+        .write_str("Snapshot 1 contents")
+        .expect("unable to write foo.txt");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    foo_file
+        .write_str("Snapshot 2 contents")
+        .expect("unable to update foo.txt");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot2", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. drop the first snapshot, leaving its object unreferenced
+    remove_file(
+        temp.path()
+            .join("elfshaker_data/packs/loose/snapshot1.pack.idx"),
+    )?;
+
+    // 3. a dry run reports the object but keeps it
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["gc", "--dry-run"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Would remove 1 loose object(s)"));
+
+    // 4. gc removes it
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.arg("gc");
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 1 loose object(s)"));
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.arg("gc");
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 0 loose object(s)"));
+
+    // 5. the remaining snapshot is still intact
+    foo_file
+        .write_str("garbage")
+        .expect("unable to update foo.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "--reset", "snapshot2"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    foo_file.assert("Snapshot 2 contents");
+
+    Ok(())
+}
+
+#[test]
+fn repack_existing_packs() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let files_list = temp.child("files.txt");
+    files_list
+        .write_str("foo.txt\n")
+        .expect("unable to write files.txt");
+
+    // 1. prepare: two packs with one snapshot each
+    let foo_file = temp.child("foo.txt");
+    for (snapshot, pack) in [("snapshot1", "packA"), ("snapshot2", "packB")] {
+        foo_file
+            .write_str(&format!("{} contents", snapshot))
+            .expect("unable to write foo.txt");
+
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", snapshot, "--files-from", "files.txt"]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["pack", pack, &format!("loose/{}", snapshot)]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+
+    // 2. drop the loose snapshots, so that objects can only come from the packs
+    std::fs::remove_dir_all(temp.path().join("elfshaker_data/loose"))?;
+    std::fs::remove_dir_all(temp.path().join("elfshaker_data/packs/loose"))?;
+
+    // 3. merge both packs
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "merged", "packA", "packB"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "packA", "packA"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().failure();
+
+    // 4. check snapshots
+    for snapshot in ["snapshot1", "snapshot2"] {
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["extract", "--reset", &format!("merged:{}", snapshot)]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+        foo_file.assert(format!("{} contents", snapshot).as_str());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn loosen_restores_loose_snapshots() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let files_list = temp.child("files.txt");
+    files_list
+        .write_str("foo.txt\n")
+        .expect("unable to write files.txt");
+
+    // 1. prepare: a pack with two snapshots, and nothing loose
+    let foo_file = temp.child("foo.txt");
+    for snapshot in ["snapshot1", "snapshot2"] {
+        foo_file
+            .write_str(&format!("{} contents", snapshot))
+            .expect("unable to write foo.txt");
+
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", snapshot, "--files-from", "files.txt"]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    std::fs::remove_dir_all(temp.path().join("elfshaker_data/loose"))?;
+    std::fs::remove_dir_all(temp.path().join("elfshaker_data/packs/loose"))?;
+
+    // 2. loosen the pack, removing it afterwards
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["loosen", "--verify", "--remove", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Loosened 2 snapshot(s)"));
+    assert!(!temp.path().join("elfshaker_data/packs/pack1.pack").exists());
+
+    // 3. check snapshots
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.arg("list");
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("loose/snapshot1"))
+        .stdout(predicate::str::contains("loose/snapshot2"))
+        .stdout(predicate::str::contains("pack1").not());
+
+    for snapshot in ["snapshot1", "snapshot2"] {
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["extract", "--reset", snapshot]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+        foo_file.assert(format!("{} contents", snapshot).as_str());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn fsck_reports_corrupt_objects() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let files_list = temp.child("files.txt");
+    files_list
+        .write_str("foo.txt\n")
+        .expect("unable to write files.txt");
+
+    // 1. prepare: a packed and loose snapshot
+    let foo_file = temp.child("foo.txt");
+    foo_file
+        .write_str("Snapshot 1 contents")
+        .expect("unable to write foo.txt");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. a healthy repository passes
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["fsck", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""issues":[]"#));
+
+    // 3. corrupt the loose object
+    let object = walkdir::WalkDir::new(temp.path().join("elfshaker_data/loose"))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_type().is_file())
+        .expect("no loose object found");
+    std::fs::write(object.path(), "corrupt")?;
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["fsck", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(r#""kind":"loose-object""#))
+        .stdout(predicate::str::contains("checksum did not match"));
+
+    // 4. a pack header whose frame sizes overflow is reported
+    let mut header = vec![0x92, 0xcf];
+    header.extend(848629801635942891u64.to_be_bytes());
+    header.extend([0x91, 0x92, 0xcf]);
+    header.extend(u64::MAX.to_be_bytes());
+    header.push(0x00);
+    let mut pack = 0x184D2A50u32.to_le_bytes().to_vec();
+    pack.extend((header.len() as u32).to_le_bytes());
+    pack.extend(header);
+    std::fs::write(temp.path().join("elfshaker_data/packs/pack1.pack"), pack)?;
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["fsck", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(r#""kind":"pack""#))
+        .stdout(predicate::str::contains("The frame sizes overflow!"));
+
+    Ok(())
+}
+
+#[test]
+fn diff_lists_changes_across_packs() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: a packed snapshot with foo.txt and bar.txt
+    let files_list = temp.child("files.txt");
+    files_list
+        .write_str("foo.txt\nbar.txt\n")
+        .expect("unable to write files.txt");
+    let foo_file = temp.child("foo.txt");
+    foo_file
+        .write_str("Snapshot 1 contents")
+        .expect("unable to write foo.txt");
+    temp.child("bar.txt")
+        .write_str("bar")
+        .expect("unable to write bar.txt");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. a loose snapshot which modifies foo.txt, drops bar.txt and adds baz.txt
+    files_list
+        .write_str("foo.txt\nbaz.txt\n")
+        .expect("unable to update files.txt");
+    foo_file
+        .write_str("Snapshot 2 contents")
+        .expect("unable to update foo.txt");
+    temp.child("baz.txt")
+        .write_str("baz")
+        .expect("unable to write baz.txt");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot2", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 3. check the diff
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["diff", "pack1:snapshot1", "snapshot2", "--name-only"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout("bar.txt\nbaz.txt\nfoo.txt\n");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["diff", "pack1:snapshot1", "snapshot2", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            r#"{"status":"removed","path":"bar.txt","old":{"size":3,"#,
+        ))
+        .stdout(predicate::str::contains(
+            r#"{"status":"added","path":"baz.txt","old":null,"new":{"size":3,"#,
+        ))
+        .stdout(predicate::str::contains(
+            r#"{"status":"modified","path":"foo.txt","old":{"size":19,"#,
+        ));
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["diff", "snapshot2", "snapshot2", "--name-only"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout("");
+
+    Ok(())
+}
+
+#[test]
+fn extract_into_leaves_head_untouched() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: a packed and a loose snapshot
+    let files_list = temp.child("files.txt");
+    files_list
+        .write_str("foo.txt\n")
+        .expect("unable to write files.txt");
+    let foo_file = temp.child("foo.txt");
+    foo_file
+        .write_str("Snapshot 1 contents")
+        .expect("unable to write foo.txt");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    foo_file
+        .write_str("Snapshot 2 contents")
+        .expect("unable to update foo.txt");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot2", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let head = temp.child("elfshaker_data/HEAD");
+    let head_contents = std::fs::read_to_string(head.path())?;
+
+    // 2. extract both snapshots side by side
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "pack1:snapshot1", "--into", "out1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "snapshot2", "--into", "out2"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    temp.child("out1/foo.txt").assert("Snapshot 1 contents");
+    temp.child("out2/foo.txt").assert("Snapshot 2 contents");
+    foo_file.assert("Snapshot 2 contents");
+    head.assert(head_contents.as_str());
+
+    // 3. non-empty destinations are only overwritten with --force
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "pack1:snapshot1", "--into", "out2"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("is not empty"));
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "pack1:snapshot1", "--into", "out2", "--force"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    temp.child("out2/foo.txt").assert("Snapshot 1 contents");
+    head.assert(head_contents.as_str());
+
+    Ok(())
+}
+
+#[test]
+fn sparse_extract_respects_head_spec() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: two snapshots, which differ in bin/clang and bin/opt
+    temp.child("files.txt")
+        .write_str("bin/clang\nbin/opt\nlib/libfoo.so\nlib/libbar.a\n")
+        .expect("unable to write files.txt");
+    let clang = temp.child("bin/clang");
+    let opt = temp.child("bin/opt");
+    let libbar = temp.child("lib/libbar.a");
+    clang.write_str("clang 1").expect("unable to write clang");
+    opt.write_str("opt 1").expect("unable to write opt");
+    temp.child("lib/libfoo.so")
+        .write_str("libfoo")
+        .expect("unable to write libfoo.so");
+    libbar
+        .write_str("libbar")
+        .expect("unable to write libbar.a");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    clang.write_str("clang 2").expect("unable to update clang");
+    opt.write_str("opt 2").expect("unable to update opt");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot2", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "snapshot1", "--reset"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. a sparse extract removes the files which are no longer selected
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args([
+        "extract",
+        "snapshot2",
+        "--include",
+        "bin/clang",
+        "--include",
+        "lib/**",
+        "--exclude",
+        "**/*.a",
+    ]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    clang.assert("clang 2");
+    opt.assert(predicate::path::missing());
+    libbar.assert(predicate::path::missing());
+    temp.child("lib/libfoo.so").assert("libfoo");
+    temp.child("elfshaker_data/HEAD")
+        .assert(predicate::str::ends_with(
+            ":snapshot2\ninclude bin/clang\ninclude lib/**\nexclude **/*.a\n",
+        ));
+
+    // The files which were not extracted are not reported as changed.
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["status", "snapshot2", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout("[\"files.txt\"]\n");
+
+    // 3. extracting without a spec restores all files
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "snapshot1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    clang.assert("clang 1");
+    opt.assert("opt 1");
+    libbar.assert("libbar");
+    temp.child("elfshaker_data/HEAD")
+        .assert(predicate::str::ends_with(":snapshot1\n"));
+
+    Ok(())
+}
+
+#[test]
+fn show_streams_files_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: a snapshot with two files
+    temp.child("files.txt")
+        .write_str("a.txt\nb.txt\n")
+        .expect("unable to write files.txt");
+    temp.child("a.txt")
+        .write_str("aaa\n")
+        .expect("unable to write a.txt");
+    temp.child("b.txt")
+        .write_str("bbb\n")
+        .expect("unable to write b.txt");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. show the files from the loose snapshot
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["show", "snapshot1", "b.txt", "a.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout("bbb\naaa\n");
+
+    // 3. and from the pack, where b.txt has to be read twice
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["show", "pack1:snapshot1", "b.txt", "a.txt", "b.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout("bbb\naaa\nbbb\n");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["show", "pack1:snapshot1", "c.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn export_writes_tar_archives() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: a packed snapshot with an executable and a symlink
+    temp.child("files.txt")
+        .write_str("bin/tool\nlink\n")
+        .expect("unable to write files.txt");
+    let tool = temp.child("bin/tool");
+    tool.write_str("tool contents")
+        .expect("unable to write tool");
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(tool.path(), std::fs::Permissions::from_mode(0o755))?;
+        std::os::unix::fs::symlink("bin/tool", temp.path().join("link"))?;
+    }
+    #[cfg(target_family = "windows")]
+    std::os::windows::fs::symlink_file("bin/tool", temp.path().join("link"))?;
+    filetime::set_file_mtime(
+        tool.path(),
+        filetime::FileTime::from_unix_time(1_600_000_000, 123_456_789),
+    )?;
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. export to a plain and a compressed archive
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["export", "pack1:snapshot1", "-o", "out.tar"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["export", "pack1:snapshot1", "-o", "out.tar.zst"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let plain = std::fs::read(temp.path().join("out.tar"))?;
+    let compressed = std::fs::read(temp.path().join("out.tar.zst"))?;
+    assert_eq!(plain, zstd::decode_all(&compressed[..])?);
+
+    // 3. check the archive contents
+    let mut archive = tar::Archive::new(&plain[..]);
+    let mut found = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if path == std::path::Path::new("bin/tool") {
+            #[cfg(target_family = "unix")]
+            assert_eq!(0o755, entry.header().mode()?);
+            assert_eq!(1_600_000_000, entry.header().mtime()?);
+            let pax_mtime = entry
+                .pax_extensions()?
+                .expect("missing PAX header")
+                .map(|ext| ext.unwrap())
+                .find(|ext| ext.key() == Ok("mtime"))
+                .map(|ext| ext.value().unwrap().to_owned());
+            assert_eq!(Some("1600000000.123456789".to_owned()), pax_mtime);
+            let mut contents = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut contents)?;
+            assert_eq!("tool contents", contents);
+        } else {
+            assert_eq!(std::path::Path::new("link"), path);
+            assert_eq!(tar::EntryType::Symlink, entry.header().entry_type());
+            assert_eq!(
+                Some(std::path::Path::new("bin/tool")),
+                entry.link_name()?.as_deref()
+            );
+        }
+        found += 1;
+    }
+    assert_eq!(2, found);
+
+    Ok(())
+}
+
+#[test]
+fn store_from_tar_roundtrips_export() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: a snapshot with an executable and a symlink
+    temp.child("files.txt")
+        .write_str("bin/tool\nlink\n")
+        .expect("unable to write files.txt");
+    let tool = temp.child("bin/tool");
+    tool.write_str("tool contents")
+        .expect("unable to write tool");
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(tool.path(), std::fs::Permissions::from_mode(0o755))?;
+        std::os::unix::fs::symlink("bin/tool", temp.path().join("link"))?;
+    }
+    #[cfg(target_family = "windows")]
+    std::os::windows::fs::symlink_file("bin/tool", temp.path().join("link"))?;
+    let mtime = filetime::FileTime::from_unix_time(1_600_000_000, 123_456_789);
+    filetime::set_file_mtime(tool.path(), mtime)?;
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["export", "snapshot1", "-o", "out.tar.zst"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. store the archive as a new snapshot
+    let head = std::fs::read_to_string(temp.path().join("elfshaker_data/HEAD"))?;
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot2", "--from-tar", "out.tar.zst"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    temp.child("elfshaker_data/HEAD").assert(head.as_str());
+
+    // 3. the snapshots are identical
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["diff", "snapshot1", "snapshot2", "--name-only"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout("");
+
+    // Loose snapshots are extracted without restoring symlinks, so pack them first.
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "pack1:snapshot2", "--into", "out"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let out_tool = temp.child("out/bin/tool");
+    out_tool.assert("tool contents");
+    let metadata = std::fs::metadata(out_tool.path())?;
+    assert_eq!(
+        mtime,
+        filetime::FileTime::from_last_modification_time(&metadata)
+    );
+    assert_eq!(
+        std::path::Path::new("bin/tool"),
+        std::fs::read_link(temp.path().join("out/link"))?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn chunked_pack_roundtrips() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: two snapshots of a large file with a small edit
+    temp.child("files.txt")
+        .write_str("big.bin\nsmall.txt\n")
+        .expect("unable to write files.txt");
+    let mut state = 1u64;
+    let big1 = (0..300 * 1024)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        })
+        .collect::<Vec<_>>();
+    let mut big2 = big1.clone();
+    big2[200 * 1024..200 * 1024 + 5].copy_from_slice(b"edit!");
+
+    std::fs::write(temp.path().join("big.bin"), &big1)?;
+    temp.child("small.txt")
+        .write_str("Snapshot 1 contents")
+        .expect("unable to write small.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    std::fs::write(temp.path().join("big.bin"), &big2)?;
+    temp.child("small.txt")
+        .write_str("Snapshot 2 contents")
+        .expect("unable to write small.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot2", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. pack with content-defined chunks
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1", "--chunk", "--frames", "2"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let index = std::fs::read(temp.path().join("elfshaker_data/packs/pack1.pack.idx"))?;
+    assert_eq!(b"ELFS\0\0\0\x03", &index[..8]);
+
+    // 3. the snapshots read back unchanged
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "pack1:snapshot1", "--into", "out1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    assert_eq!(big1, std::fs::read(temp.path().join("out1/big.bin"))?);
+    temp.child("out1/small.txt").assert("Snapshot 1 contents");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["show", "pack1:snapshot2", "big.bin"]);
+    cmd.current_dir(temp.path());
+    assert_eq!(big2, cmd.assert().success().get_output().stdout);
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["fsck", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""issues":[]"#));
+
+    Ok(())
+}
+
+#[test]
+fn pack_orderings_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: two loose snapshots
+    temp.child("files.txt")
+        .write_str("a.txt\nb/c.txt\n")
+        .expect("unable to write files.txt");
+    for snapshot in ["snapshot1", "snapshot2"] {
+        temp.child("a.txt")
+            .write_str(&format!("{} a", snapshot))
+            .expect("unable to write a.txt");
+        temp.child("b/c.txt")
+            .write_str(&format!("{} c", snapshot).repeat(100))
+            .expect("unable to write c.txt");
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", snapshot, "--files-from", "files.txt"]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+
+    // 2. every ordering produces a pack which reads back unchanged
+    for order in ["size", "path", "similarity", "snapshot"] {
+        let pack = format!("pack-{}", order);
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["pack", &pack, "--order", order, "--frames", "2"]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+
+        let out = format!("out-{}", order);
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["extract", &format!("{}:snapshot1", pack), "--into", &out]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+        temp.child(format!("{}/a.txt", out)).assert("snapshot1 a");
+        temp.child(format!("{}/b/c.txt", out))
+            .assert("snapshot1 c".repeat(100));
+    }
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack-bogus", "--order", "bogus"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn multi_frame_pack_removes_temporary_frames() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: three loose snapshots of several files
+    let names = (0..8).map(|i| format!("file{}.txt", i)).collect::<Vec<_>>();
+    temp.child("files.txt")
+        .write_str(&names.join("\n"))
+        .expect("unable to write files.txt");
+    for snapshot in ["snapshot1", "snapshot2", "snapshot3"] {
+        for name in &names {
+            temp.child(name)
+                .write_str(&format!("{} {}", snapshot, name).repeat(1000))
+                .expect("unable to write file");
+        }
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", snapshot, "--files-from", "files.txt"]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+
+    // 2. each frame is compressed into a temporary file, then copied to the pack
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1", "--frames", "4"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    assert_eq!(
+        0,
+        std::fs::read_dir(temp.path().join("elfshaker_data/trash"))?.count()
+    );
+
+    // 3. the snapshots read back unchanged from all frames
+    for snapshot in ["snapshot1", "snapshot2", "snapshot3"] {
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["extract", &format!("pack1:{}", snapshot), "--verify"]);
+        cmd.args(["--into", snapshot]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+        for name in &names {
+            temp.child(format!("{}/{}", snapshot, name))
+                .assert(format!("{} {}", snapshot, name).repeat(1000));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn dictionary_pack_roundtrips() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: a snapshot of many small, similar files
+    let names = (0..100)
+        .map(|i| format!("file{}.txt", i))
+        .collect::<Vec<_>>();
+    temp.child("files.txt")
+        .write_str(&names.join("\n"))
+        .expect("unable to write files.txt");
+    for (i, name) in names.iter().enumerate() {
+        let contents = (0..40)
+            .map(|line| format!("{} line {}: value {}\n", name, line, (i * 7 + line) % 13))
+            .collect::<String>();
+        temp.child(name)
+            .write_str(&contents)
+            .expect("unable to write file");
+    }
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. pack with a dictionary
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1", "--train-dictionary", "--frames", "4"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // The header is followed by a skippable frame containing the dictionary.
+    let pack = std::fs::read(temp.path().join("elfshaker_data/packs/pack1.pack"))?;
+    let read_u32 = |offset: usize| {
+        u32::from_le_bytes([
+            pack[offset],
+            pack[offset + 1],
+            pack[offset + 2],
+            pack[offset + 3],
+        ])
+    };
+    let header_size = 8 + read_u32(4) as usize;
+    assert_eq!(0x184D2A50, read_u32(header_size));
+    assert_eq!(0xEC30A437, read_u32(header_size + 8));
+
+    // 3. the snapshot reads back unchanged
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "pack1:snapshot1", "--into", "out"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    for name in &names {
+        assert_eq!(
+            std::fs::read(temp.path().join(name))?,
+            std::fs::read(temp.path().join("out").join(name))?
+        );
+    }
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["show", "pack1:snapshot1", "file42.txt"]);
+    cmd.current_dir(temp.path());
+    assert_eq!(
+        std::fs::read(temp.path().join("file42.txt"))?,
+        cmd.assert().success().get_output().stdout
+    );
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["fsck", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""issues":[]"#));
+
+    Ok(())
+}
+
+#[test]
+fn pack_append_keeps_existing_frames() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let pack_path = temp.path().join("elfshaker_data/packs/pack1.pack");
+    let header_size =
+        |pack: &[u8]| 8 + u32::from_le_bytes([pack[4], pack[5], pack[6], pack[7]]) as usize;
+
+    // 1. prepare: a pack with one snapshot
+    temp.child("files.txt")
+        .write_str("a.txt\nb.txt\n")
+        .expect("unable to write files.txt");
+    temp.child("a.txt")
+        .write_str("Snapshot 1 a")
+        .expect("unable to write a.txt");
+    temp.child("b.txt")
+        .write_str("unchanged")
+        .expect("unable to write b.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1", "--frames", "1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    let original = std::fs::read(&pack_path)?;
+
+    // 2. append a second snapshot
+    temp.child("a.txt")
+        .write_str("Snapshot 2 a")
+        .expect("unable to write a.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot2", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "--append", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // The existing frames are unchanged, and followed by the new frame.
+    let appended = std::fs::read(&pack_path)?;
+    let frames = &original[header_size(&original)..];
+    let start = header_size(&appended);
+    assert_eq!(frames, &appended[start..start + frames.len()]);
+    assert!(appended.len() > start + frames.len());
+
+    // 3. both snapshots read back unchanged
+    for (snapshot, a) in [("snapshot1", "Snapshot 1 a"), ("snapshot2", "Snapshot 2 a")] {
+        let out = format!("out-{}", snapshot);
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["extract", &format!("pack1:{}", snapshot), "--into", &out]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+        temp.child(format!("{}/a.txt", out)).assert(a);
+        temp.child(format!("{}/b.txt", out)).assert("unchanged");
+    }
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["fsck", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""issues":[]"#));
+
+    // 4. there is nothing left to append
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "--append", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("no loose snapshots to append"));
+
+    Ok(())
+}
+
+#[test]
+fn seekable_pack_roundtrips() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let pack_path = temp.path().join("elfshaker_data/packs/pack1.pack");
+    let ends_with_seek_table = |pack: &[u8]| pack.ends_with(&0x8F92EAB1u32.to_le_bytes());
+
+    // 1. prepare: a snapshot with a file spanning several seek intervals
+    temp.child("files.txt")
+        .write_str("big.txt\nsmall.txt\n")
+        .expect("unable to write files.txt");
+    let big = (0..200_000u64)
+        .map(|i| format!("line {} {}\n", i, i.wrapping_mul(2654435761) % 1000003))
+        .collect::<String>();
+    assert!(big.len() > 2 * 1024 * 1024);
+    temp.child("big.txt")
+        .write_str(&big)
+        .expect("unable to write big.txt");
+    temp.child("small.txt")
+        .write_str("Snapshot 1")
+        .expect("unable to write small.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. pack with a seek table
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1", "--frames", "1", "--seek-interval", "1"]);
+    cmd.args(["--compression-level", "3"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    assert!(ends_with_seek_table(&std::fs::read(&pack_path)?));
+
+    // 3. the snapshot reads back unchanged
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "pack1:snapshot1", "--into", "out"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    temp.child("out/big.txt").assert(big.as_str());
+    temp.child("out/small.txt").assert("Snapshot 1");
+
+    for file in ["big.txt", "small.txt"] {
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["show", "pack1:snapshot1", file]);
+        cmd.current_dir(temp.path());
+        assert_eq!(
+            std::fs::read(temp.path().join("out").join(file))?,
+            cmd.assert().success().get_output().stdout
+        );
+    }
+
+    // 4. appending keeps the seek table
+    temp.child("small.txt")
+        .write_str("Snapshot 2")
+        .expect("unable to write small.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot2", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "--append", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    assert!(ends_with_seek_table(&std::fs::read(&pack_path)?));
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["show", "pack1:snapshot2", "small.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout("Snapshot 2");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["fsck", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""issues":[]"#));
+
+    Ok(())
+}
+
+#[test]
+fn hash_algorithms_are_recorded_in_pack_indexes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: one snapshot with SHA-1 checksums, one with BLAKE3
+    temp.child("files.txt")
+        .write_str("file.txt\n")
+        .expect("unable to write files.txt");
+    temp.child("file.txt")
+        .write_str("Snapshot 1")
+        .expect("unable to write file.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.args(["--hash", "sha1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    temp.child("file.txt")
+        .write_str("Snapshot 2")
+        .expect("unable to write file.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot2", "--files-from", "files.txt"]);
+    cmd.args(["--hash", "blake3"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. snapshots with different algorithms cannot be packed together
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("different hash algorithms"));
+
+    // 3. the packs record the algorithm
+    for (pack, snapshot) in [("sha1", "snapshot1"), ("blake3", "snapshot2")] {
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["pack", pack, &format!("loose/{}", snapshot)]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+
+        let index_path = temp
+            .path()
+            .join(format!("elfshaker_data/packs/{}.pack.idx", pack));
+        let index = PackIndex::load(index_path)?;
+        assert_eq!(pack, index.hash_algorithm().name());
+
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["extract", &format!("{}:{}", pack, snapshot), "--verify"]);
+        cmd.args(["--into", pack]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+    temp.child("sha1/file.txt").assert("Snapshot 1");
+    temp.child("blake3/file.txt").assert("Snapshot 2");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["fsck", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""issues":[]"#));
+
+    Ok(())
+}
+
+#[test]
+fn new_snapshots_use_the_hash_algorithm_of_the_repository() -> Result<(), Box<dyn std::error::Error>>
+{
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: a snapshot with SHA-1 checksums, then one with the default
+    temp.child("files.txt")
+        .write_str("file.txt\n")
+        .expect("unable to write files.txt");
+    for (snapshot, hash) in [("snapshot1", Some("sha1")), ("snapshot2", None)] {
+        temp.child("file.txt")
+            .write_str(snapshot)
+            .expect("unable to write file.txt");
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", snapshot, "--files-from", "files.txt"]);
+        if let Some(hash) = hash {
+            cmd.args(["--hash", hash]);
+        }
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+
+    // 2. both snapshots are packed together
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    let index_path = temp.path().join("elfshaker_data/packs/pack1.pack.idx");
+    assert_eq!(
+        "sha1",
+        PackIndex::load(&index_path)?.hash_algorithm().name()
+    );
+
+    // 3. later snapshots can be appended to the pack
+    temp.child("file.txt")
+        .write_str("snapshot3")
+        .expect("unable to write file.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot3", "--files-from", "files.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "--append", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    assert_eq!(
+        ["snapshot1", "snapshot2", "snapshot3"],
+        PackIndex::load(&index_path)?.snapshot_tags()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn upgrade_index_converts_old_indexes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: a pack with an index in the old format
+    temp.child("files.txt")
+        .write_str("file.txt\n")
+        .expect("unable to write files.txt");
+    temp.child("file.txt")
+        .write_str("Snapshot 1")
+        .expect("unable to write file.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot1", "--files-from", "files.txt"]);
+    cmd.args(["--hash", "sha1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    write_without_attributes(
+        &temp
+            .path()
+            .join("elfshaker_data/packs/loose/snapshot1.pack.idx"),
+    )?;
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let index_path = temp.path().join("elfshaker_data/packs/pack1.pack.idx");
+    assert_eq!(b"ELFS\0\0\0\x01", &std::fs::read(&index_path)?[..8]);
+
+    // 2. upgrade the pack, and skip the indexes which are up to date
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["upgrade-index", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout("Upgraded pack1 (version 1 -> 3)\n");
+    assert_eq!(b"ELFS\0\0\0\x03", &std::fs::read(&index_path)?[..8]);
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["upgrade-index"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout("Upgraded loose/snapshot1 (version 1 -> 3)\n")
+        .stderr(predicate::str::contains("Upgraded 1 of 2 pack index(es)."));
+
+    // 3. the upgraded indexes can be read
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "pack1:snapshot1", "--verify", "--into", "out"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    temp.child("out/file.txt").assert("Snapshot 1");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["fsck", "--json"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""issues":[]"#));
+
+    Ok(())
+}
+
+#[test]
+fn upgrade_index_keeps_the_order_of_loose_snapshots() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: two old loose indexes, stored in the reverse order of their names
+    temp.child("files.txt")
+        .write_str("file.txt\n")
+        .expect("unable to write files.txt");
+    for (seconds, snapshot) in [(1_000_000_000, "snapshot-b"), (1_000_000_100, "snapshot-a")] {
+        temp.child("file.txt")
+            .write_str(snapshot)
+            .expect("unable to write file.txt");
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args([
+            "store",
+            snapshot,
+            "--files-from",
+            "files.txt",
+            "--hash",
+            "sha1",
+        ]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+        let index_path = temp
+            .path()
+            .join(format!("elfshaker_data/packs/loose/{}.pack.idx", snapshot));
+        write_without_attributes(&index_path)?;
+        filetime::set_file_mtime(&index_path, filetime::FileTime::from_unix_time(seconds, 0))?;
+    }
+
+    // 2. the loose snapshots are still in the order they were stored
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["upgrade-index"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Upgraded 2 of 2 pack index(es)."));
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["resolve", "snapshot-a~1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout("loose/snapshot-b:snapshot-b\n");
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_metadata_is_listed_and_filtered() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: two snapshots with messages and annotations
+    temp.child("files.txt")
+        .write_str("file.txt\n")
+        .expect("unable to write files.txt");
+    for (snapshot, compiler) in [("snapshot1", "clang-14"), ("snapshot2", "clang-15")] {
+        temp.child("file.txt")
+            .write_str(snapshot)
+            .expect("unable to write file.txt");
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", snapshot, "--files-from", "files.txt"]);
+        cmd.args(["--message", &format!("Build of {}", snapshot)]);
+        cmd.args([
+            "--meta",
+            &format!("compiler={}", compiler),
+            "--meta",
+            "opt=O2",
+        ]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "snapshot3", "--files-from", "files.txt"]);
+    cmd.args(["--meta", "created=yesterday"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("reserved"));
+
+    // 2. the metadata is carried through pack
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["list", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout(predicate::str::is_match(
+        r"^snapshot1 +1 *\nsnapshot2 +1 *\n$",
+    )?);
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["list", "pack1", "--long"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Build of snapshot1"))
+        .stdout(predicate::str::contains("compiler=clang-15,opt=O2"))
+        .stdout(predicate::str::is_match(
+            r"snapshot1 +1 +\d{4}-\d\d-\d\dT\d\d:\d\d:\d\dZ",
+        )?);
+
+    // 3. list and find filter by the metadata
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["list", "pack1", "--meta", "compiler=clang-14"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("snapshot1"))
+        .stdout(predicate::str::contains("snapshot2").not());
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args([
+        "find",
+        "--meta",
+        "opt",
+        "--meta",
+        "message=Build of snapshot2",
+    ]);
+    cmd.current_dir(temp.path());
+    // Only the snapshot and the pack, as scripts read them from each line.
+    cmd.assert().success().stdout(predicate::str::is_match(
+        r"^snapshot2 +loose/snapshot2 *\nsnapshot2 +pack1 *\n$",
+    )?);
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["find", "snapshot2", "--long"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Build of snapshot2"));
+
+    Ok(())
+}
+
+#[test]
+fn refs_point_to_snapshots() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: two packed snapshots
+    temp.child("files.txt")
+        .write_str("file.txt\n")
+        .expect("unable to write files.txt");
+    for snapshot in ["snapshot1", "snapshot2"] {
+        temp.child("file.txt")
+            .write_str(snapshot)
+            .expect("unable to write file.txt");
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", snapshot, "--files-from", "files.txt"]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. refs can be set to snapshots, other refs and HEAD
+    let refs = [
+        ("known-good", "pack1:snapshot1"),
+        ("nightly/latest", "snapshot2"),
+        ("previous", "known-good"),
+    ];
+    for (name, snapshot) in refs {
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["ref", "set", name, snapshot]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["ref", "list"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::is_match(r"known-good +pack1:snapshot1")?)
+        .stdout(predicate::str::is_match(
+            r"nightly/latest +loose/snapshot2:snapshot2",
+        )?)
+        .stdout(predicate::str::is_match(r"previous +pack1:snapshot1")?);
+
+    // 3. refs can be used in place of snapshots
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "known-good", "--force"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    temp.child("file.txt").assert("snapshot1");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "nightly/latest"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    temp.child("file.txt").assert("snapshot2");
+
+    // A ref with the name of another snapshot is ambiguous.
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["ref", "set", "snapshot1", "pack1:snapshot2"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "snapshot1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "The name 'snapshot1' is both a ref to pack1:snapshot2 and a snapshot",
+        ))
+        .stderr(predicate::str::contains("<pack>:snapshot1"));
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["ref", "delete", "snapshot1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["ref", "set", "known-good", "HEAD"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["ref", "set", "broken", "pack1:snapshot3"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().failure();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["ref", "set", "HEAD", "snapshot1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid ref name"));
+
+    // 4. deleted refs are gone
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["ref", "delete", "nightly/latest", "previous"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    temp.child("elfshaker_data/refs/nightly")
+        .assert(predicate::path::missing());
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["ref"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout("known-good loose/snapshot2:snapshot2 \n");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["ref", "delete", "previous"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("does not exist"));
+
+    Ok(())
+}
+
+#[test]
+fn reflog_records_head_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: two snapshots
+    temp.child("files.txt")
+        .write_str("file.txt\n")
+        .expect("unable to write files.txt");
+    for snapshot in ["snapshot1", "snapshot2"] {
+        temp.child("file.txt")
+            .write_str(snapshot)
+            .expect("unable to write file.txt");
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", snapshot, "--files-from", "files.txt"]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+
+    // 2. an extract can be undone by extracting the previous HEAD
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "snapshot1", "--force"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    temp.child("file.txt").assert("snapshot1");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "@{1}"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    temp.child("file.txt").assert("snapshot2");
+
+    // 3. packing moves HEAD into the pack
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack1", "loose/snapshot2"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["reflog"]);
+    cmd.current_dir(temp.path());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let lines = String::from_utf8(output)?
+        .lines()
+        .map(|line| line.split_whitespace().map(str::to_owned).collect())
+        .collect::<Vec<Vec<_>>>();
+    let expected = [
+        ("@{0}", "pack1:snapshot2", "pack"),
+        ("@{1}", "loose/snapshot2:snapshot2", "extract"),
+        ("@{2}", "loose/snapshot1:snapshot1", "extract"),
+        ("@{3}", "loose/snapshot2:snapshot2", "store"),
+        ("@{4}", "loose/snapshot1:snapshot1", "store"),
+    ];
+    assert_eq!(expected.len(), lines.len());
+    for (line, (entry, snapshot, operation)) in lines.iter().zip(expected) {
+        assert_eq!([entry, snapshot, operation], [&line[0], &line[1], &line[3]]);
+    }
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["show", "HEAD@{5}", "file.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("'@{5}' does not exist"));
+
+    Ok(())
+}
+
+#[test]
+fn snapshots_are_navigated_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: two packs of two snapshots, and a loose snapshot
+    temp.child("files.txt")
+        .write_str("file.txt\n")
+        .expect("unable to write files.txt");
+    for snapshot in ["s1", "s2", "s3", "s4", "s5"] {
+        temp.child("file.txt")
+            .write_str(snapshot)
+            .expect("unable to write file.txt");
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", snapshot, "--files-from", "files.txt"]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+    for (pack, snapshots) in [("pack-a", ["s1", "s2"]), ("pack-b", ["s3", "s4"])] {
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["pack", pack]);
+        cmd.args(snapshots.map(|s| format!("loose/{}", s)));
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+
+    // 2. offsets follow the order of the snapshots, across packs
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["resolve", "pack-a:@first", "pack-a:@last", "s3~1", "s2^+1"]);
+    cmd.args(["pack-b:@last^+1", "s5~3", "pack-b:@first~", "s1^+2~1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout(
+        "pack-a:s1\npack-a:s2\npack-a:s2\npack-b:s3\n\
+         loose/s5:s5\npack-a:s2\npack-a:s2\npack-a:s2\n",
+    );
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["show", "pack-b:@last~2", "file.txt"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout("s2");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["resolve", "s1~1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("no snapshot 1 snapshot(s) before"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn bisect_finds_the_first_bad_snapshot() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: s3 cannot be tested, s5 is the first bad snapshot
+    temp.child("files.txt")
+        .write_str("file.txt\n")
+        .expect("unable to write files.txt");
+    let contents = ["ok", "ok", "skip", "ok", "bad", "bad"];
+    for (i, content) in contents.iter().enumerate() {
+        temp.child("file.txt")
+            .write_str(content)
+            .expect("unable to write file.txt");
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args(["store", &format!("s{}", i + 1), "--files-from", "files.txt"]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack-a", "loose/s1", "loose/s2", "loose/s3"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["extract", "s1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. bisect across the pack and the loose snapshots
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["bisect", "start", "s1", "s6"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout(predicate::str::contains(
+        "4 snapshot(s) left to test, extracted pack-a:s3",
+    ));
+    temp.child("file.txt").assert("skip");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["bisect", "run", "sh", "-c"]);
+    cmd.arg(r#"grep -q skip file.txt && exit 125; grep -q ok file.txt"#);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout(predicate::str::contains(
+        "loose/s5:s5 is the first bad snapshot",
+    ));
+
+    // 3. reset returns to the snapshot extracted before the bisection
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["bisect", "reset"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout("HEAD is back at loose/s1:s1\n");
+    temp.child("file.txt").assert("ok");
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["bisect", "good"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("No bisection is in progress"));
+
+    Ok(())
+}
+
+#[test]
+fn find_matches_tags_metadata_and_paths() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // 1. prepare: bin/lld is added in llvm-2, and llvm-1 is packed
+    temp.child("bin/clang")
+        .write_str("clang")
+        .expect("unable to write bin/clang");
+    temp.child("files.txt")
+        .write_str("bin/clang\n")
+        .expect("unable to write files.txt");
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["store", "llvm-1", "--files-from", "files.txt"]);
+    cmd.args(["-m", "Release build"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    temp.child("bin/lld")
+        .write_str("lld")
+        .expect("unable to write bin/lld");
+    temp.child("files.txt")
+        .write_str("bin/clang\nbin/lld\n")
+        .expect("unable to write files.txt");
+    for (snapshot, message) in [("llvm-2", "Debug build"), ("gcc-1", "Release build")] {
+        let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+        cmd.args([
+            "store",
+            snapshot,
+            "--files-from",
+            "files.txt",
+            "-m",
+            message,
+        ]);
+        cmd.current_dir(temp.path());
+        cmd.assert().success();
+    }
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["pack", "pack-a", "loose/llvm-1"]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success();
+
+    // 2. tags are matched with globs and regexes, in the selected packs
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args([
+        "find",
+        "--glob",
+        "llvm-*",
+        "--pack",
+        "loose/llvm-2",
+        "--pack",
+        "pack-a",
+    ]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("llvm-1").and(predicate::str::contains("llvm-2")))
+        .stdout(predicate::str::contains("gcc-1").not());
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args([
+        "find",
+        "--regex",
+        "^(gcc|llvm)-1$",
+        "--pack",
+        "pack-a",
+        "--json",
+    ]);
+    cmd.current_dir(temp.path());
+    cmd.assert().success().stdout(predicate::str::starts_with(
+        r#"[{"snapshot":"pack-a:llvm-1","pack":"pack-a","tag":"llvm-1","attributes":{"#,
+    ));
+
+    // 3. messages and file paths are searched too
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args([
+        "find", "Release", "--in", "message", "--limit", "1", "--json",
+    ]);
+    cmd.current_dir(temp.path());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let results: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(1, results.as_array().unwrap().len());
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["find", "--glob", "**/lld", "--in", "path", "--json"]);
+    cmd.current_dir(temp.path());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let results: serde_json::Value = serde_json::from_slice(&output)?;
+    let results = results.as_array().unwrap();
+    let mut tags: Vec<_> = results.iter().map(|r| r["tag"].as_str().unwrap()).collect();
+    tags.sort_unstable();
+    assert_eq!(vec!["gcc-1", "llvm-2"], tags);
+    assert!(results
+        .iter()
+        .all(|r| r["paths"] == serde_json::json!(["bin/lld"])));
+
+    let mut cmd = Command::cargo_bin("elfshaker_executable")?;
+    cmd.args(["find", "x", "--in", "files"]);
+    cmd.current_dir(temp.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Cannot search 'files'"));
+
+    Ok(())
+}